@@ -0,0 +1,283 @@
+use super::*;
+
+const DEFAULT_TILE: u16 = 0x2000; // passable grass, first tile of the tileset
+const DEFAULT_HEIGHT: u8 = 0;
+
+// Builds a complete map in code; ids and the counts of GeneralMapInfoSection are handled by build()
+pub struct AlmMapBuilder {
+    width: usize,
+    height: usize,
+    general_info: GeneralMapInfoSection,
+    tiles: Vec<TileEntry>,
+    heights: Vec<u8>,
+    map_objects: MapObjectsSection,
+    fractions: Vec<FractionEntry>,
+    units: Vec<UnitEntry>,
+    structures: Vec<StructureEntry>,
+    sacks: Vec<SackEntry>,
+    effects: Vec<EffectEntry>,
+    triggers: TriggersSection,
+    random_seed: u32
+}
+impl AlmMapBuilder {
+    pub fn new(width: usize, height: usize) -> Self {
+        let size = width * height;
+        Self {
+            width,
+            height,
+            general_info: GeneralMapInfoSection {
+                width: width as u32,
+                height: height as u32,
+                ..Default::default()
+            },
+            tiles: vec![TileEntry::from_raw(DEFAULT_TILE); size],
+            heights: vec![DEFAULT_HEIGHT; size],
+            map_objects: MapObjectsSection::new(width, height),
+            fractions: Vec::new(),
+            units: Vec::new(),
+            structures: Vec::new(),
+            sacks: Vec::new(),
+            effects: Vec::new(),
+            triggers: TriggersSection {
+                instances: Vec::new(),
+                checks: Vec::new(),
+                triggers: Vec::new()
+            },
+            random_seed: 0
+        }
+    }
+
+    pub fn set_lighting(&mut self, negative_sun_angle: f32, darkness: u32, contrast: u32) -> &mut Self {
+        self.general_info.negative_sun_angle = negative_sun_angle;
+        self.general_info.darkness = darkness;
+        self.general_info.contrast = contrast;
+        self
+    }
+
+    pub fn set_time_in_minutes(&mut self, time_in_minutes: u32) -> &mut Self {
+        self.general_info.time_in_minutes = time_in_minutes;
+        self
+    }
+
+    pub fn set_random_seed(&mut self, random_seed: u32) -> &mut Self {
+        self.random_seed = random_seed;
+        self
+    }
+
+    // Out of map coordinates are ignored by all the painting methods
+    pub fn paint_tile(&mut self, x: usize, y: usize, tile: TileEntry) -> &mut Self {
+        if x < self.width && y < self.height {
+            self.tiles[y * self.width + x] = tile;
+        }
+        self
+    }
+
+    pub fn fill_tiles(&mut self, x: usize, y: usize, width: usize, height: usize, tile: TileEntry) -> &mut Self {
+        for tile_y in y..(y + height).min(self.height) {
+            for tile_x in x..(x + width).min(self.width) {
+                self.tiles[tile_y * self.width + tile_x] = tile;
+            }
+        }
+        self
+    }
+
+    // Retiles the brush area and its border only, AlmMap::retile_terrain evens out the rest
+    pub fn paint_terrain<TLayout: TerrainTileLayout>(
+        &mut self,
+        x: usize,
+        y: usize,
+        brush: TerrainBrush,
+        layout: &TLayout
+    ) -> &mut Self {
+        terrain_painter::paint_terrain(&mut self.tiles, self.width, x, y, brush, layout);
+        self
+    }
+
+    pub fn set_height(&mut self, x: usize, y: usize, height: u8) -> &mut Self {
+        if x < self.width && y < self.height {
+            self.heights[y * self.width + x] = height;
+        }
+        self
+    }
+
+    pub fn place_object(&mut self, x: usize, y: usize, object_id: ObjectId) -> &mut Self {
+        self.map_objects.set(x, y, object_id);
+        self
+    }
+
+    // Returns the fraction id used by units, structures and diplomacy
+    pub fn add_fraction(&mut self, name: &str, color_id: u32, money: u32, flags: u32) -> u32 {
+        self.fractions.push(FractionEntry {
+            color_id,
+            flags,
+            money,
+            name: name.to_string(),
+            diplomacy_states: [0u16; 0x10]
+        });
+        (self.fractions.len() - 1) as u32
+    }
+
+    // Sets how fraction_id treats other_fraction_id; the state is stored as is
+    pub fn set_diplomacy(&mut self, fraction_id: u32, other_fraction_id: u32, state: u16) -> &mut Self {
+        if let Some(fraction) = self.fractions.get_mut(fraction_id as usize) {
+            if let Some(diplomacy) = fraction.diplomacy_states.get_mut(other_fraction_id as usize) {
+                *diplomacy = state;
+            }
+        }
+        self
+    }
+
+    // unit_id is assigned here, whatever the entry had
+    pub fn add_unit(&mut self, mut unit: UnitEntry) -> u16 {
+        unit.unit_id = (self.units.len() + 1) as u16;
+        self.units.push(unit);
+        self.units.len() as u16
+    }
+
+    pub fn add_structure(&mut self, mut structure: StructureEntry) -> u16 {
+        structure.id = (self.structures.len() + 1) as u16;
+        self.structures.push(structure);
+        self.structures.len() as u16
+    }
+
+    pub fn add_bridge(&mut self, x_coord: u32, y_coord: u32, width: u32, height: u32, fraction_id: u32) -> u16 {
+        self.add_structure(StructureEntry {
+            x_coord,
+            y_coord,
+            type_id: 33,
+            fraction_id,
+            bridge_info: BridgeInfo { width, height },
+            ..Default::default()
+        })
+    }
+
+    pub fn add_sack(&mut self, sack: SackEntry) -> &mut Self {
+        self.sacks.push(sack);
+        self
+    }
+
+    pub fn add_effect(&mut self, effect: EffectEntry) -> &mut Self {
+        self.effects.push(effect);
+        self
+    }
+
+    // Instance and check ids are assigned here, triggers refer to them by these ids
+    pub fn add_instance(&mut self, mut instance: InstanceEntry) -> u32 {
+        instance.id = (self.triggers.instances.len() + 1) as u32;
+        self.triggers.instances.push(instance);
+        self.triggers.instances.len() as u32
+    }
+
+    pub fn add_check(&mut self, mut check: CheckEntry) -> u32 {
+        check.id = (self.triggers.checks.len() + 1) as u32;
+        self.triggers.checks.push(check);
+        self.triggers.checks.len() as u32
+    }
+
+    pub fn add_trigger(&mut self, trigger: TriggerEntry) -> &mut Self {
+        self.triggers.triggers.push(trigger);
+        self
+    }
+
+    pub fn build(self) -> AlmMap {
+        let mut general_info = self.general_info;
+        general_info.fraction_count = self.fractions.len() as u32;
+        general_info.structure_count = self.structures.len() as u32;
+        general_info.unit_count = self.units.len() as u32;
+        general_info.sack_count = self.sacks.len() as u32;
+        general_info.logic_count = self.triggers.triggers.len() as u32;
+        AlmMap {
+            header: AlmHeader {
+                signature: ALM_SIGNATURE,
+                header_size: ALM_HEADER_SIZE,
+                mysterious_size: 0,
+                section_count: SectionKind::ALL.len() as u32,
+                random_seed: self.random_seed
+            },
+            section_headers: Vec::new(),
+            general_info,
+            tiles: Some(TilesSection { tiles: self.tiles }),
+            height_map: Some(HeightMapSection { heights: self.heights }),
+            map_objects: Some(self.map_objects),
+            structures: Some(StructuresSection { structures: self.structures }),
+            fractions: Some(FractionsSection { fractions: self.fractions }),
+            units: Some(UnitsSection { units: self.units }),
+            triggers: Some(self.triggers),
+            sacks: Some(SacksSection { sacks: self.sacks }),
+            effects: Some(EffectsSection { effects: self.effects }),
+            unknown_sections: Vec::new(),
+            warnings: Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alm::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_built_map_round_trip() {
+        let mut builder = AlmMapBuilder::new(8, 6);
+        builder
+            .set_lighting(-1.5, 10, 20)
+            .set_time_in_minutes(600)
+            .set_random_seed(1234)
+            .fill_tiles(2, 2, 3, 2, TileEntry::new(3, 15, 2, false))
+            .set_height(7, 5, 200)
+            .place_object(1, 4, ObjectId(12));
+        let player = builder.add_fraction("Player", 1, 5000, 0);
+        let enemy = builder.add_fraction("Enemy", 2, 100, 1);
+        builder.set_diplomacy(player, enemy, 1);
+        let unit_id = builder.add_unit(UnitEntry::new(0x180, 0x280, 3, player));
+        builder.add_unit(UnitEntry::new(0x580, 0x480, 7, enemy));
+        builder.add_bridge(0x300, 0x100, 2, 1, player);
+        builder
+            .add_sack(SackEntry {
+                unit_id: unit_id as u32,
+                x_coord: 0,
+                y_coord: 0,
+                money: 50,
+                items: vec![ItemEntry { id: 0x2A01, wielded: 1, effect_id: 1 }]
+            })
+            .add_effect(EffectEntry {
+                corrupt_effect_id: 1,
+                trap_x: 0,
+                trap_y: 0,
+                flags_or_magic_sphere: 0,
+                service_data: 0,
+                modifiers: vec![EffectModifier { modifier_type: 4, modifier_value: 15 }]
+            });
+        let map = builder.build();
+
+        let mut bytes = Vec::new();
+        map.write(&mut bytes).unwrap();
+        let read = AlmMap::read(&mut Cursor::new(&bytes[..])).unwrap();
+        assert!(read.warnings.is_empty());
+        assert!(read.unknown_sections.is_empty());
+        assert_eq!(read.section_headers.len(), SectionKind::ALL.len());
+        assert_eq!(read.header.random_seed, 1234);
+        assert_eq!(read.general_info.width, 8);
+        assert_eq!(read.general_info.unit_count, 2);
+        assert_eq!(read.general_info.time_in_minutes, 600);
+
+        let tiles = &read.tiles.as_ref().unwrap().tiles;
+        assert_eq!(tiles[2 * 8 + 2].raw(), TileEntry::new(3, 15, 2, false).raw());
+        assert_eq!(read.height_map.as_ref().unwrap().heights[5 * 8 + 7], 200);
+        assert_eq!(read.map_objects.as_ref().unwrap().get(1, 4), Some(ObjectId(12)));
+        let fractions = &read.fractions.as_ref().unwrap().fractions;
+        assert_eq!(fractions[1].name, "Enemy");
+        assert_eq!(fractions[0].diplomacy_states[1], 1);
+        let units = &read.units.as_ref().unwrap().units;
+        assert_eq!((units[1].unit_id, units[1].type_id, units[1].x_coord), (2, 7, 0x580));
+        let structures = &read.structures.as_ref().unwrap().structures;
+        assert!(structures[0].is_bridge());
+        assert_eq!(structures[0].bridge_info.width, 2);
+        assert_eq!(read.sacks.as_ref().unwrap().sacks[0].items[0].id, 0x2A01);
+        assert_eq!(read.effects.as_ref().unwrap().effects[0].modifiers[0].modifier_value, 15);
+
+        let mut rewritten = Vec::new();
+        read.write(&mut rewritten).unwrap();
+        assert_eq!(rewritten, bytes);
+    }
+}
@@ -1,5 +1,5 @@
 use bin_serialization_rs::{Reflectable, SerializationReflector, Endianness};
-use std::io::{Result, Read};
+use std::io::{Result, Read, Write};
 use crate::shared_types::{U32Wrapper, U16Wrapper, U64Wrapper};
 
 #[derive(Clone, Default, Debug)]
@@ -46,6 +46,19 @@ impl EffectEntry {
             modifiers
         })
     }
+
+    pub fn write_to_stream<TStream: Write>(&self, stream: &mut TStream, endianness: Endianness) -> Result<()> {
+        U32Wrapper(self.corrupt_effect_id).serialize(stream, endianness)?;
+        U32Wrapper(self.trap_x).serialize(stream, endianness)?;
+        U32Wrapper(self.trap_y).serialize(stream, endianness)?;
+        U16Wrapper(self.flags_or_magic_sphere).serialize(stream, endianness)?;
+        U64Wrapper(self.service_data).serialize(stream, endianness)?;
+        U32Wrapper(self.modifiers.len() as u32).serialize(stream, endianness)?;
+        for modifier in self.modifiers.iter() {
+            modifier.clone().serialize(stream, endianness)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -61,4 +74,12 @@ impl EffectsSection {
         }
         Ok(Self { effects })
     }
+
+    pub fn write_to_stream<TStream: Write>(&self, stream: &mut TStream, endianness: Endianness) -> Result<()> {
+        U32Wrapper(self.effects.len() as u32).serialize(stream, endianness)?;
+        for effect in self.effects.iter() {
+            effect.write_to_stream(stream, endianness)?;
+        }
+        Ok(())
+    }
 }
\ No newline at end of file
@@ -1,5 +1,6 @@
-use std::io::{Result, Read};
+use std::io::{Result, Read, Write};
 use bin_serialization_rs::{Reflectable, SerializationReflector, Endianness};
+use crate::shared_types::encode_cp866_into;
 
 #[derive(Clone, Default, Debug)]
 pub struct FractionEntry {
@@ -17,8 +18,9 @@ impl Reflectable for FractionEntry {
         reflector.reflect_u32(&mut self.flags)?;
         reflector.reflect_u32(&mut self.money)?;
         let mut name_bytes = [0u8;0x20];
-        for i in 0..name_bytes.len() {
-            reflector.reflect_u8(&mut name_bytes[i])?;
+        encode_cp866_into(&self.name, &mut name_bytes);
+        for byte in name_bytes.iter_mut() {
+            reflector.reflect_u8(byte)?;
         }
         self.name = cp866_rs::decode_bytes(&name_bytes);
         for i in 0..self.diplomacy_states.len() {
@@ -47,4 +49,11 @@ impl FractionsSection {
             fractions
         })
     }
+
+    pub(crate) fn write<TStream: Write>(&self, stream: &mut TStream) -> Result<()> {
+        for fraction in self.fractions.iter() {
+            fraction.clone().serialize(stream, Endianness::LittleEndian)?;
+        }
+        Ok(())
+    }
 }
\ No newline at end of file
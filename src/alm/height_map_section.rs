@@ -1,4 +1,4 @@
-use std::io::Read;
+use std::io::{Read, Write};
 
 #[derive(Debug)]
 pub struct HeightMapSection {
@@ -11,7 +11,11 @@ impl HeightMapSection {
     ) -> std::io::Result<Self> {
         let size = map_info.width as usize * map_info.height as usize;
         let mut heights = vec![0u8; size];
-        stream.read(&mut heights)?;
+        stream.read_exact(&mut heights)?;
         Ok(Self { heights })
     }
+
+    pub(crate) fn write<TStream: Write>(&self, stream: &mut TStream) -> std::io::Result<()> {
+        stream.write_all(&self.heights)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,148 @@
+use std::io::{Cursor, ErrorKind, Read, Result, Seek, SeekFrom};
+use bin_serialization_rs::{Endianness, Reflectable};
+use super::*;
+
+#[derive(Clone, Debug)]
+pub struct SectionIndexEntry {
+    pub header: SectionHeader,
+    pub offset: u64 // of the section data, right past its header
+}
+
+// Knows where every section lives and reads them only when asked,
+// apart from the general section which all the others depend upon
+pub struct AlmIndex<TStream: Read + Seek> {
+    stream: TStream,
+    pub header: AlmHeader,
+    pub sections: Vec<SectionIndexEntry>, // in file order
+    pub general_info: GeneralMapInfoSection
+}
+impl<TStream: Read + Seek> AlmIndex<TStream> {
+    pub fn open(mut stream: TStream) -> Result<Self> {
+        let header = AlmHeader::deserialize(&mut stream, Endianness::LittleEndian)?;
+        let start = stream.stream_position()?;
+        let file_size = stream.seek(SeekFrom::End(0))?;
+        stream.seek(SeekFrom::Start(start))?;
+        let mut sections = Vec::new();
+        for _ in 0..header.section_count {
+            let section_header = SectionHeader::deserialize(&mut stream, Endianness::LittleEndian)?;
+            let offset = stream.stream_position()?;
+            let next_offset = offset + section_header.data_size as u64;
+            sections.push(SectionIndexEntry { header: section_header, offset });
+            if next_offset >= file_size {
+                break;
+            }
+            stream.seek(SeekFrom::Start(next_offset))?;
+        }
+        let mut index = Self {
+            stream,
+            header,
+            sections,
+            general_info: GeneralMapInfoSection::default()
+        };
+        index.general_info = index
+            .load(SectionKind::General, |stream, _| GeneralMapInfoSection::deserialize(stream, Endianness::LittleEndian))?
+            .ok_or_else(|| std::io::Error::from(ErrorKind::InvalidInput))?;
+        Ok(index)
+    }
+
+    pub fn find(&self, kind: SectionKind) -> Option<&SectionIndexEntry> {
+        self.sections.iter().find(|entry| entry.header.kind() == Some(kind))
+    }
+
+    pub fn raw_section(&mut self, position: usize) -> Result<Vec<u8>> {
+        let entry = self.sections.get(position).ok_or_else(|| std::io::Error::from(ErrorKind::InvalidInput))?;
+        let data_size = entry.header.data_size as u64;
+        self.stream.seek(SeekFrom::Start(entry.offset))?;
+        let mut data = Vec::new();
+        (&mut self.stream).take(data_size).read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    pub fn tiles(&mut self) -> Result<Option<TilesSection>> {
+        self.load(SectionKind::Tiles, TilesSection::read)
+    }
+
+    pub fn height_map(&mut self) -> Result<Option<HeightMapSection>> {
+        self.load(SectionKind::HeightMap, HeightMapSection::read)
+    }
+
+    pub fn map_objects(&mut self) -> Result<Option<MapObjectsSection>> {
+        self.load(SectionKind::MapObjects, MapObjectsSection::read)
+    }
+
+    pub fn structures(&mut self) -> Result<Option<StructuresSection>> {
+        self.load(SectionKind::Structures, StructuresSection::read)
+    }
+
+    pub fn fractions(&mut self) -> Result<Option<FractionsSection>> {
+        self.load(SectionKind::Fractions, FractionsSection::read)
+    }
+
+    pub fn units(&mut self) -> Result<Option<UnitsSection>> {
+        self.load(SectionKind::Units, UnitsSection::read)
+    }
+
+    pub fn triggers(&mut self) -> Result<Option<TriggersSection>> {
+        self.load(SectionKind::Triggers, |stream, _| TriggersSection::read_from_stream(stream, Endianness::LittleEndian))
+    }
+
+    pub fn sacks(&mut self) -> Result<Option<SacksSection>> {
+        self.load(SectionKind::Sacks, SacksSection::read)
+    }
+
+    pub fn effects(&mut self) -> Result<Option<EffectsSection>> {
+        self.load(SectionKind::Effects, |stream, _| EffectsSection::read_from_stream(stream, Endianness::LittleEndian))
+    }
+
+    pub fn into_inner(self) -> TStream {
+        self.stream
+    }
+
+    fn load<T, F>(&mut self, kind: SectionKind, read: F) -> Result<Option<T>>
+    where F: FnOnce(&mut Cursor<Vec<u8>>, &GeneralMapInfoSection) -> Result<T> {
+        let position = match self.sections.iter().position(|entry| entry.header.kind() == Some(kind)) {
+            None => return Ok(None),
+            Some(position) => position
+        };
+        let data = self.raw_section(position)?;
+        Ok(Some(read(&mut Cursor::new(data), &self.general_info)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use crate::alm::*;
+
+    #[test]
+    fn test_index_of_written_map() {
+        let mut builder = AlmMapBuilder::new(3, 2);
+        let player = builder.add_fraction("Player", 0, 100, 0);
+        builder
+            .paint_tile(1, 1, TileEntry::new(1, 2, 3, true))
+            .set_height(2, 0, 7);
+        builder.add_unit(UnitEntry::new(0x100, 0x100, 1, player));
+        let mut map = builder.build();
+        map.effects = None;
+        let mut bytes = Vec::new();
+        map.write(&mut bytes).unwrap();
+
+        let mut index = AlmIndex::open(Cursor::new(bytes)).unwrap();
+        assert_eq!((index.general_info.width, index.general_info.height), (3, 2));
+        assert!(index.find(SectionKind::Units).is_some());
+        assert!(index.find(SectionKind::Effects).is_none());
+        assert!(index.effects().unwrap().is_none());
+
+        assert_eq!(index.tiles().unwrap().unwrap().tiles, map.tiles.as_ref().unwrap().tiles);
+        assert_eq!(index.height_map().unwrap().unwrap().heights, map.height_map.as_ref().unwrap().heights);
+        let units = index.units().unwrap().unwrap().units;
+        assert_eq!(units.len(), 1);
+        assert_eq!((units[0].x_coord, units[0].unit_id), (0x100, 1));
+        let fractions = index.fractions().unwrap().unwrap().fractions;
+        assert_eq!((fractions[0].name.as_str(), fractions[0].money), ("Player", 100));
+
+        let tiles_position = index.sections.iter().position(|entry| entry.header.kind() == Some(SectionKind::Tiles)).unwrap();
+        assert_eq!(index.raw_section(tiles_position).unwrap().len(), 3 * 2 * 2);
+        assert!(index.raw_section(index.sections.len()).is_err());
+    }
+}
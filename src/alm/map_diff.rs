@@ -0,0 +1,583 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Display;
+use std::hash::Hash;
+use super::*;
+use super::trigger_script::trigger_fields;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TileRect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub before: String,
+    pub after: String
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ObjectChange {
+    pub x: usize,
+    pub y: usize,
+    pub before: ObjectId, // empty when the object was added
+    pub after: ObjectId // empty when the object was removed
+}
+
+// Entries are matched by key, entries sharing a key are paired in order.
+// Moved means only the stored coordinates differ
+#[derive(Clone, Debug)]
+pub enum EntryChange<K, T> {
+    Added { key: K, entry: T },
+    Removed { key: K, entry: T },
+    Moved { key: K, from: (u32, u32), to: (u32, u32) },
+    Changed { key: K, changes: Vec<FieldChange> }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MapDiff {
+    pub general: Vec<FieldChange>,
+    pub painted_tiles: Vec<TileRect>,
+    pub height_changes: Vec<TileRect>,
+    pub objects: Vec<ObjectChange>,
+    pub structures: Vec<EntryChange<u16, StructureEntry>>,
+    pub fractions: Vec<EntryChange<usize, FractionEntry>>,
+    pub units: Vec<EntryChange<u16, UnitEntry>>,
+    pub triggers: Vec<EntryChange<String, String>>, // keyed by name, entries are the trigger written out
+    pub sacks: Vec<EntryChange<u32, SackEntry>>, // keyed by owner, ground sacks have 0
+    pub effects: Vec<EntryChange<usize, EffectEntry>>
+}
+impl MapDiff {
+    pub fn is_empty(&self) -> bool {
+        self.general.is_empty()
+            && self.painted_tiles.is_empty()
+            && self.height_changes.is_empty()
+            && self.objects.is_empty()
+            && self.structures.is_empty()
+            && self.fractions.is_empty()
+            && self.units.is_empty()
+            && self.triggers.is_empty()
+            && self.sacks.is_empty()
+            && self.effects.is_empty()
+    }
+}
+
+// Cells are compared over the area both maps share, a resize shows up in general
+pub fn diff(before: &AlmMap, after: &AlmMap) -> MapDiff {
+    let width = before.general_info.width.min(after.general_info.width) as usize;
+    let height = before.general_info.height.min(after.general_info.height) as usize;
+    let cell = |map: &AlmMap, x: usize, y: usize| y * map.general_info.width as usize + x;
+
+    let tile = |map: &AlmMap, x: usize, y: usize| {
+        map.tiles.as_ref().and_then(|section| section.tiles.get(cell(map, x, y)).copied())
+    };
+    let painted_tiles = changed_rects(width, height, |x, y| tile(before, x, y) != tile(after, x, y));
+    let height_at = |map: &AlmMap, x: usize, y: usize| {
+        map.height_map.as_ref().and_then(|section| section.heights.get(cell(map, x, y)).copied())
+    };
+    let height_changes = changed_rects(width, height, |x, y| height_at(before, x, y) != height_at(after, x, y));
+
+    let object = |map: &AlmMap, x: usize, y: usize| {
+        map.map_objects.as_ref().and_then(|section| section.get(x, y)).unwrap_or_default()
+    };
+    let mut objects = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            let (object_before, object_after) = (object(before, x, y), object(after, x, y));
+            if object_before != object_after {
+                objects.push(ObjectChange { x, y, before: object_before, after: object_after });
+            }
+        }
+    }
+
+    let structures = diff_entries(
+        before.structures.as_ref().map_or(&[][..], |s| &s.structures[..]),
+        after.structures.as_ref().map_or(&[][..], |s| &s.structures[..]),
+        |_, structure| structure.id
+    );
+    let fractions = diff_entries(
+        before.fractions.as_ref().map_or(&[][..], |s| &s.fractions[..]),
+        after.fractions.as_ref().map_or(&[][..], |s| &s.fractions[..]),
+        |idx, _| idx
+    );
+    let units = diff_entries(
+        before.units.as_ref().map_or(&[][..], |s| &s.units[..]),
+        after.units.as_ref().map_or(&[][..], |s| &s.units[..]),
+        |_, unit| unit.unit_id
+    );
+    let sacks = diff_entries(
+        before.sacks.as_ref().map_or(&[][..], |s| &s.sacks[..]),
+        after.sacks.as_ref().map_or(&[][..], |s| &s.sacks[..]),
+        |_, sack| sack.unit_id
+    );
+    let effects = diff_entries(
+        before.effects.as_ref().map_or(&[][..], |s| &s.effects[..]),
+        after.effects.as_ref().map_or(&[][..], |s| &s.effects[..]),
+        |idx, _| idx
+    );
+
+    let written_triggers = |map: &AlmMap| -> Vec<WrittenTrigger> {
+        map.triggers
+            .iter()
+            .flat_map(|section| section.triggers.iter().map(move |trigger| (section, trigger)))
+            .map(|(section, trigger)| WrittenTrigger {
+                name: trigger.name.clone(),
+                fields: trigger_fields(section, trigger)
+            })
+            .collect()
+    };
+    let triggers = diff_entries(&written_triggers(before), &written_triggers(after), |_, trigger| trigger.name.clone())
+        .into_iter()
+        .map(|change| match change {
+            EntryChange::Added { key, entry } => EntryChange::Added { key, entry: entry.to_string() },
+            EntryChange::Removed { key, entry } => EntryChange::Removed { key, entry: entry.to_string() },
+            EntryChange::Moved { key, from, to } => EntryChange::Moved { key, from, to },
+            EntryChange::Changed { key, changes } => EntryChange::Changed { key, changes }
+        })
+        .collect();
+
+    MapDiff {
+        general: field_changes(&before.general_info.fields(), &after.general_info.fields()),
+        painted_tiles,
+        height_changes,
+        objects,
+        structures,
+        fractions,
+        units,
+        triggers,
+        sacks,
+        effects
+    }
+}
+
+impl std::fmt::Display for MapDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for change in self.general.iter() {
+            writeln!(f, "general: {}", change)?;
+        }
+        for rect in self.painted_tiles.iter() {
+            writeln!(f, "tiles: painted {}", rect)?;
+        }
+        for rect in self.height_changes.iter() {
+            writeln!(f, "heights: changed {}", rect)?;
+        }
+        for change in self.objects.iter() {
+            match (change.before.is_empty(), change.after.is_empty()) {
+                (true, _) => writeln!(f, "objects: added {} at ({}, {})", change.after.0, change.x, change.y)?,
+                (_, true) => writeln!(f, "objects: removed {} at ({}, {})", change.before.0, change.x, change.y)?,
+                _ => writeln!(
+                    f,
+                    "objects: replaced {} with {} at ({}, {})",
+                    change.before.0,
+                    change.after.0,
+                    change.x,
+                    change.y
+                )?
+            }
+        }
+        write_entry_changes(f, "structure", &self.structures)?;
+        write_entry_changes(f, "fraction", &self.fractions)?;
+        write_entry_changes(f, "unit", &self.units)?;
+        for change in self.triggers.iter() {
+            match change {
+                EntryChange::Added { key, entry } => writeln!(f, "trigger \"{}\": added\n{}", key, entry)?,
+                EntryChange::Removed { key, entry } => writeln!(f, "trigger \"{}\": removed\n{}", key, entry)?,
+                EntryChange::Moved { .. } => {},
+                EntryChange::Changed { key, changes } => {
+                    writeln!(f, "trigger \"{}\": changed", key)?;
+                    for change in changes.iter() {
+                        writeln!(f, "    {}", change)?;
+                    }
+                }
+            }
+        }
+        write_entry_changes(f, "sack of unit", &self.sacks)?;
+        write_entry_changes(f, "effect", &self.effects)
+    }
+}
+
+impl std::fmt::Display for TileRect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{} at ({}, {})", self.width, self.height, self.x, self.y)
+    }
+}
+
+impl std::fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {} -> {}", self.field, self.before, self.after)
+    }
+}
+
+fn write_entry_changes<K: Display, T>(
+    f: &mut std::fmt::Formatter<'_>,
+    label: &str,
+    changes: &[EntryChange<K, T>]
+) -> std::fmt::Result {
+    for change in changes.iter() {
+        match change {
+            EntryChange::Added { key, .. } => writeln!(f, "{} {}: added", label, key)?,
+            EntryChange::Removed { key, .. } => writeln!(f, "{} {}: removed", label, key)?,
+            EntryChange::Moved { key, from, to } => {
+                writeln!(f, "{} {}: moved ({}, {}) -> ({}, {})", label, key, from.0, from.1, to.0, to.1)?
+            },
+            EntryChange::Changed { key, changes } => {
+                let changes: Vec<String> = changes.iter().map(|change| change.to_string()).collect();
+                writeln!(f, "{} {}: {}", label, key, changes.join(", "))?
+            }
+        }
+    }
+    Ok(())
+}
+
+// Splits the changed cells into rectangles, each grown right first and then down
+fn changed_rects<F: Fn(usize, usize) -> bool>(width: usize, height: usize, is_changed: F) -> Vec<TileRect> {
+    let mut changed: Vec<bool> = (0..width * height).map(|idx| is_changed(idx % width, idx / width)).collect();
+    let mut rects = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            if !changed[y * width + x] {
+                continue;
+            }
+            let rect_width = (x..width).take_while(|&cell_x| changed[y * width + cell_x]).count();
+            let rect_height = (y..height)
+                .take_while(|&cell_y| (x..x + rect_width).all(|cell_x| changed[cell_y * width + cell_x]))
+                .count();
+            for cell_y in y..y + rect_height {
+                for cell_x in x..x + rect_width {
+                    changed[cell_y * width + cell_x] = false;
+                }
+            }
+            rects.push(TileRect { x, y, width: rect_width, height: rect_height });
+        }
+    }
+    rects
+}
+
+// Fields missing on one side compare as empty
+fn field_changes(before: &[(&'static str, String)], after: &[(&'static str, String)]) -> Vec<FieldChange> {
+    let mut fields: Vec<&'static str> = before.iter().map(|&(field, _)| field).collect();
+    fields.extend(after.iter().map(|&(field, _)| field).filter(|field| !before.iter().any(|(f, _)| f == field)));
+    let value = |entries: &[(&'static str, String)], field: &str| {
+        entries.iter().find(|(f, _)| *f == field).map_or_else(String::new, |(_, value)| value.clone())
+    };
+    fields
+        .into_iter()
+        .map(|field| FieldChange { field, before: value(before, field), after: value(after, field) })
+        .filter(|change| change.before != change.after)
+        .collect()
+}
+
+fn diff_entries<K, T, F>(before: &[T], after: &[T], key: F) -> Vec<EntryChange<K, T>>
+where
+    K: Clone + Eq + Hash,
+    T: DiffEntry + Clone,
+    F: Fn(usize, &T) -> K
+{
+    let mut unmatched: HashMap<K, VecDeque<usize>> = HashMap::new();
+    for (idx, entry) in before.iter().enumerate() {
+        unmatched.entry(key(idx, entry)).or_default().push_back(idx);
+    }
+    let mut matched = vec![false; before.len()];
+    let mut changes = Vec::new();
+    for (idx, entry) in after.iter().enumerate() {
+        let key = key(idx, entry);
+        let old_idx = match unmatched.get_mut(&key).and_then(|indices| indices.pop_front()) {
+            Some(old_idx) => old_idx,
+            None => {
+                changes.push(EntryChange::Added { key, entry: entry.clone() });
+                continue;
+            }
+        };
+        matched[old_idx] = true;
+        let old = &before[old_idx];
+        let field_changes = field_changes(&old.fields(), &entry.fields());
+        let (from, to) = (old.position(), entry.position());
+        if !field_changes.is_empty() {
+            let mut field_changes = field_changes;
+            if from != to {
+                field_changes.insert(0, FieldChange {
+                    field: "position",
+                    before: format!("({}, {})", from.0, from.1),
+                    after: format!("({}, {})", to.0, to.1)
+                });
+            }
+            changes.push(EntryChange::Changed { key, changes: field_changes });
+        } else if from != to {
+            changes.push(EntryChange::Moved { key, from, to });
+        }
+    }
+    for (idx, entry) in before.iter().enumerate().filter(|&(idx, _)| !matched[idx]) {
+        changes.push(EntryChange::Removed { key: key(idx, entry), entry: entry.clone() });
+    }
+    changes
+}
+
+// What diff_entries compares: the stored coordinates and every other field as text
+trait DiffEntry {
+    fn position(&self) -> (u32, u32) {
+        (0, 0)
+    }
+    fn fields(&self) -> Vec<(&'static str, String)>;
+}
+
+// Section counts are left out, they follow the sections themselves
+impl DiffEntry for GeneralMapInfoSection {
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("width", self.width.to_string()),
+            ("height", self.height.to_string()),
+            ("negative_sun_angle", self.negative_sun_angle.to_string()),
+            ("time_in_minutes", self.time_in_minutes.to_string()),
+            ("darkness", self.darkness.to_string()),
+            ("contrast", self.contrast.to_string()),
+            ("use_tiles", self.use_tiles.to_string())
+        ]
+    }
+}
+
+impl DiffEntry for StructureEntry {
+    fn position(&self) -> (u32, u32) {
+        (self.x_coord, self.y_coord)
+    }
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![
+            ("type_id", self.type_id.to_string()),
+            ("health", self.health.to_string()),
+            ("fraction_id", self.fraction_id.to_string())
+        ];
+        if self.is_bridge() {
+            fields.push(("bridge", format!("{}x{}", self.bridge_info.width, self.bridge_info.height)));
+        }
+        fields
+    }
+}
+
+impl DiffEntry for FractionEntry {
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("name", format!("\"{}\"", self.name)),
+            ("color_id", self.color_id.to_string()),
+            ("flags", self.flags.to_string()),
+            ("money", self.money.to_string()),
+            ("diplomacy_states", format!("{:?}", self.diplomacy_states))
+        ]
+    }
+}
+
+impl DiffEntry for UnitEntry {
+    fn position(&self) -> (u32, u32) {
+        (self.x_coord, self.y_coord)
+    }
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("type_id", self.type_id.to_string()),
+            ("face_ide", self.face_ide.to_string()),
+            ("special_flags", self.special_flags.to_string()),
+            ("server_id", self.server_id.to_string()),
+            ("fraction_id", self.fraction_id.to_string()),
+            ("sack_id", self.sack_id.to_string()),
+            ("view_angle", self.view_angle.to_string()),
+            ("group_id", self.group_id.to_string())
+        ]
+    }
+}
+
+impl DiffEntry for SackEntry {
+    fn position(&self) -> (u32, u32) {
+        (self.x_coord, self.y_coord)
+    }
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let items: Vec<String> = self.items
+            .iter()
+            .map(|item| format!("{}/{}/{}", item.id, item.wielded, item.effect_id))
+            .collect();
+        vec![
+            ("money", self.money.to_string()),
+            ("items", format!("[{}]", items.join(", ")))
+        ]
+    }
+}
+
+impl DiffEntry for EffectEntry {
+    fn position(&self) -> (u32, u32) {
+        (self.trap_x, self.trap_y)
+    }
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let modifiers: Vec<String> = self.modifiers
+            .iter()
+            .map(|modifier| format!("{}={}", modifier.modifier_type, modifier.modifier_value))
+            .collect();
+        vec![
+            ("corrupt_effect_id", self.corrupt_effect_id.to_string()),
+            ("flags_or_magic_sphere", self.flags_or_magic_sphere.to_string()),
+            ("service_data", self.service_data.to_string()),
+            ("modifiers", format!("[{}]", modifiers.join(", ")))
+        ]
+    }
+}
+
+#[derive(Clone)]
+struct WrittenTrigger {
+    name: String,
+    fields: Vec<(&'static str, String)>
+}
+impl DiffEntry for WrittenTrigger {
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        self.fields.clone()
+    }
+}
+impl std::fmt::Display for WrittenTrigger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (idx, (field, value)) in self.fields.iter().enumerate() {
+            if idx > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "    {}: {}", field, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alm::map_diff::*;
+
+    const SCRIPT: &str = "\
+instance \"Win\" = ForceMissionComplete();
+instance \"Lose\" = ForceMissionFailed();
+check \"One\" = Constant(value: Number = 1);
+
+trigger \"Kept\" {
+    if \"One\" == \"One\";
+    do \"Win\";
+}
+
+trigger \"Edited\" {
+    do \"Win\";
+}
+
+trigger \"Dropped\" {
+    do \"Lose\";
+}
+";
+
+    fn map(script: &str) -> AlmMap {
+        let mut builder = AlmMapBuilder::new(6, 4);
+        let player = builder.add_fraction("Player", 1, 0, 0);
+        for x in 1..=3 {
+            builder.add_unit(UnitEntry::new(x * 0x100, 0x100, 5, player));
+        }
+        builder.place_object(0, 0, ObjectId(7)).place_object(5, 3, ObjectId(8));
+        let mut map = builder.build();
+        map.triggers = Some(compile_triggers(script).unwrap());
+        map
+    }
+
+    #[test]
+    fn test_changed_rects_are_merged() {
+        // an L of five cells and a lone one
+        let changed = [(1, 1), (2, 1), (1, 2), (2, 2), (1, 3), (4, 0)];
+        let rects = changed_rects(5, 4, |x, y| changed.contains(&(x, y)));
+        assert_eq!(rects, vec![
+            TileRect { x: 4, y: 0, width: 1, height: 1 },
+            TileRect { x: 1, y: 1, width: 2, height: 2 },
+            TileRect { x: 1, y: 3, width: 1, height: 1 }
+        ]);
+        assert!(changed_rects(5, 4, |_, _| false).is_empty());
+        assert_eq!(changed_rects(5, 4, |_, _| true), vec![TileRect { x: 0, y: 0, width: 5, height: 4 }]);
+    }
+
+    #[test]
+    fn test_units_matched_by_id() {
+        let before = map(SCRIPT);
+        let mut after = map(SCRIPT);
+        let units = &mut after.units.as_mut().unwrap().units;
+        units[0].x_coord = 0x480;
+        units[1].x_coord = 0x500;
+        units[1].type_id = 6;
+        units.remove(2);
+        let mut added = UnitEntry::new(0, 0, 5, 0);
+        added.unit_id = 9;
+        units.push(added);
+
+        let diff = diff(&before, &after);
+        assert_eq!(diff.units.len(), 4);
+        assert!(matches!(diff.units[0], EntryChange::Moved { key: 1, from: (0x100, 0x100), to: (0x480, 0x100) }));
+        match &diff.units[1] {
+            EntryChange::Changed { key: 2, changes } => assert_eq!(changes, &vec![
+                FieldChange { field: "position", before: "(512, 256)".to_string(), after: "(1280, 256)".to_string() },
+                FieldChange { field: "type_id", before: "5".to_string(), after: "6".to_string() }
+            ]),
+            change => panic!("{:?}", change)
+        }
+        assert!(matches!(diff.units[2], EntryChange::Added { key: 9, .. }));
+        assert!(matches!(diff.units[3], EntryChange::Removed { key: 3, .. }));
+        assert!(diff.structures.is_empty() && diff.objects.is_empty() && diff.triggers.is_empty());
+    }
+
+    #[test]
+    fn test_triggers_matched_by_name() {
+        let script = SCRIPT
+            .replace("trigger \"Edited\" {\n    do \"Win\";", "trigger \"Edited\" {\n    do \"Lose\";")
+            .replace("trigger \"Dropped\"", "trigger \"New\"");
+        let diff = diff(&map(SCRIPT), &map(&script));
+        let keys: Vec<(&str, &str)> = diff.triggers
+            .iter()
+            .map(|change| match change {
+                EntryChange::Added { key, .. } => ("added", key.as_str()),
+                EntryChange::Removed { key, .. } => ("removed", key.as_str()),
+                EntryChange::Moved { key, .. } => ("moved", key.as_str()),
+                EntryChange::Changed { key, .. } => ("changed", key.as_str())
+            })
+            .collect();
+        assert_eq!(keys, vec![("changed", "Edited"), ("added", "New"), ("removed", "Dropped")]);
+        match &diff.triggers[0] {
+            EntryChange::Changed { changes, .. } => assert_eq!(changes, &vec![FieldChange {
+                field: "action 1",
+                before: "\"Win\" ForceMissionComplete()".to_string(),
+                after: "\"Lose\" ForceMissionFailed()".to_string()
+            }]),
+            change => panic!("{:?}", change)
+        }
+        assert_eq!(diff.to_string(), "\
+trigger \"Edited\": changed
+    action 1: \"Win\" ForceMissionComplete() -> \"Lose\" ForceMissionFailed()
+trigger \"New\": added
+    run_once: 0
+    action 1: \"Lose\" ForceMissionFailed()
+trigger \"Dropped\": removed
+    run_once: 0
+    action 1: \"Lose\" ForceMissionFailed()
+");
+    }
+
+    #[test]
+    fn test_objects_and_text() {
+        let before = map(SCRIPT);
+        let mut after = map(SCRIPT);
+        let objects = after.map_objects.as_mut().unwrap();
+        objects.set(0, 0, ObjectId::EMPTY);
+        objects.set(2, 1, ObjectId(3));
+        objects.set(5, 3, ObjectId(9));
+        after.units.as_mut().unwrap().units[2].y_coord = 0x200;
+        after.general_info.darkness = 4;
+
+        let diff = diff(&before, &after);
+        assert_eq!(diff.objects, vec![
+            ObjectChange { x: 0, y: 0, before: ObjectId(7), after: ObjectId::EMPTY },
+            ObjectChange { x: 2, y: 1, before: ObjectId::EMPTY, after: ObjectId(3) },
+            ObjectChange { x: 5, y: 3, before: ObjectId(8), after: ObjectId(9) }
+        ]);
+        assert_eq!(diff.to_string(), "\
+general: darkness: 0 -> 4
+objects: removed 7 at (0, 0)
+objects: added 3 at (2, 1)
+objects: replaced 8 with 9 at (5, 3)
+unit 3: moved (768, 256) -> (768, 512)
+");
+        assert!(crate::alm::map_diff::diff(&before, &map(SCRIPT)).is_empty());
+    }
+}
@@ -1,17 +1,205 @@
-use std::io::Read;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use crate::regfile::Registry;
+
+// Index into the objects registry (trees, rocks and other decorations); 0 stands for an empty tile
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ObjectId(pub u8);
+impl ObjectId {
+    pub const EMPTY: ObjectId = ObjectId(0);
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
 
 #[derive(Debug)]
 pub struct MapObjectsSection {
-    pub heights: Vec<u8>
+    pub width: usize,
+    pub height: usize,
+    pub objects: Vec<ObjectId>
 }
 impl MapObjectsSection {
     pub(crate) fn read<TStream: Read>(
         stream: &mut TStream,
         map_info: &super::GeneralMapInfoSection
     ) -> std::io::Result<Self> {
-        let size = map_info.width as usize * map_info.height as usize;
-        let mut heights = vec![0u8; size];
-        stream.read(&mut heights)?;
-        Ok(Self { heights })
+        let (width, height) = (map_info.width as usize, map_info.height as usize);
+        let mut ids = vec![0u8; width * height];
+        stream.read_exact(&mut ids)?;
+        Ok(Self {
+            width,
+            height,
+            objects: ids.into_iter().map(ObjectId).collect()
+        })
+    }
+
+    pub(crate) fn write<TStream: Write>(&self, stream: &mut TStream) -> std::io::Result<()> {
+        let ids: Vec<u8> = self.objects.iter().map(|id| id.0).collect();
+        stream.write_all(&ids)
     }
-}
\ No newline at end of file
+
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            objects: vec![ObjectId::EMPTY; width * height]
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<ObjectId> {
+        if x < self.width && y < self.height {
+            Some(self.objects[y * self.width + x])
+        } else {
+            None
+        }
+    }
+
+    // Returns false when the coordinate is out of the map
+    pub fn set(&mut self, x: usize, y: usize, object_id: ObjectId) -> bool {
+        if x < self.width && y < self.height {
+            self.objects[y * self.width + x] = object_id;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn iter_objects(&self) -> impl Iterator<Item = (usize, usize, ObjectId)> + '_ {
+        let width = self.width.max(1);
+        self.objects
+            .iter()
+            .enumerate()
+            .filter(|(_, id)| !id.is_empty())
+            .map(move |(idx, &id)| (idx % width, idx / width, id))
+    }
+
+    // Cells whose id has no definition yield None, so broken references stay visible
+    pub fn iter_resolved<'a>(
+        &'a self,
+        definitions: &'a ObjectDefinitions
+    ) -> impl Iterator<Item = (usize, usize, ObjectId, Option<&'a ObjectDefinition>)> + 'a {
+        self.iter_objects().map(move |(x, y, id)| (x, y, id, definitions.get(id)))
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ObjectDefinition {
+    pub id: u32,
+    pub section: String, // registry directory the definition was read from
+    pub ints: HashMap<String, i32>,
+    pub floats: HashMap<String, f64>,
+    pub strings: HashMap<String, String>
+}
+impl ObjectDefinition {
+    pub fn file(&self) -> Option<&str> {
+        self.string("File")
+    }
+
+    pub fn string(&self, name: &str) -> Option<&str> {
+        self.strings
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn int(&self, name: &str) -> Option<i32> {
+        self.ints
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, &value)| value)
+    }
+}
+
+// Every registry directory holding an "ID" value is taken as an object definition
+#[derive(Clone, Debug, Default)]
+pub struct ObjectDefinitions {
+    pub definitions: HashMap<u32, ObjectDefinition>
+}
+impl ObjectDefinitions {
+    pub fn from_registry(registry: &mut Registry) -> Self {
+        let listing = registry.list_all();
+        let mut definitions = HashMap::new();
+        for id_path in listing.ints.iter() {
+            let (section, name) = split_registry_path(id_path);
+            if !name.eq_ignore_ascii_case("ID") {
+                continue;
+            }
+            let id = match registry.get_int(id_path) {
+                Ok(id) if id >= 0 => id as u32,
+                _ => continue
+            };
+            let mut definition = ObjectDefinition {
+                id,
+                section: section.to_string(),
+                ..Default::default()
+            };
+            for path in listing.ints.iter().filter(|path| split_registry_path(path).0 == section) {
+                if let Ok(value) = registry.get_int(path) {
+                    definition.ints.insert(split_registry_path(path).1.to_string(), value);
+                }
+            }
+            for path in listing.floats.iter().filter(|path| split_registry_path(path).0 == section) {
+                if let Ok(value) = registry.get_float(path) {
+                    definition.floats.insert(split_registry_path(path).1.to_string(), value);
+                }
+            }
+            for path in listing.strings.iter().filter(|path| split_registry_path(path).0 == section) {
+                if let Ok(value) = registry.get_string(path) {
+                    definition.strings.insert(split_registry_path(path).1.to_string(), value.to_string());
+                }
+            }
+            definitions.insert(id, definition);
+        }
+        Self { definitions }
+    }
+
+    pub fn get(&self, object_id: ObjectId) -> Option<&ObjectDefinition> {
+        if object_id.is_empty() {
+            return None;
+        }
+        self.definitions.get(&(object_id.0 as u32))
+    }
+}
+
+// "Objects/Tree1/ID" -> ("Objects/Tree1", "ID")
+fn split_registry_path(path: &str) -> (&str, &str) {
+    match path.rfind('/') {
+        None => ("", path),
+        Some(slash) => (&path[..slash], &path[slash + 1..])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alm::map_objects_section::*;
+
+    #[test]
+    fn test_get_and_set() {
+        let mut section = MapObjectsSection::new(3, 2);
+        assert!(section.set(2, 1, ObjectId(5)));
+        assert!(!section.set(3, 0, ObjectId(5)));
+        assert!(!section.set(0, 2, ObjectId(5)));
+        assert_eq!(section.get(2, 1), Some(ObjectId(5)));
+        assert_eq!(section.get(0, 0), Some(ObjectId::EMPTY));
+        assert_eq!(section.get(3, 1), None);
+        assert_eq!(section.objects[5], ObjectId(5));
+    }
+
+    #[test]
+    fn test_iter_resolved() {
+        let mut section = MapObjectsSection::new(3, 2);
+        section.set(1, 0, ObjectId(1));
+        section.set(0, 1, ObjectId(9));
+        let mut definitions = ObjectDefinitions::default();
+        let mut tree = ObjectDefinition { id: 1, section: "Objects/Tree1".to_string(), ..Default::default() };
+        tree.strings.insert("file".to_string(), "tree1".to_string());
+        definitions.definitions.insert(1, tree);
+
+        let resolved: Vec<_> = section
+            .iter_resolved(&definitions)
+            .map(|(x, y, id, definition)| (x, y, id, definition.and_then(|definition| definition.file())))
+            .collect();
+        assert_eq!(resolved, vec![(1, 0, ObjectId(1), Some("tree1")), (0, 1, ObjectId(9), None)]);
+    }
+}
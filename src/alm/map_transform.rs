@@ -0,0 +1,475 @@
+use std::collections::HashSet;
+use super::*;
+use super::trigger_enums::ArgumentType;
+use crate::game_database::{GameDatabase, structure_footprint};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MapTransform {
+    Crop { x: u32, y: u32, width: u32, height: u32 }, // clipped to the map
+    Extend { left: u32, top: u32, right: u32, bottom: u32, filler: TileEntry },
+    MirrorHorizontally,
+    MirrorVertically,
+    RotateClockwise
+}
+
+#[derive(Clone, Debug)]
+pub enum DroppedEntity {
+    Unit(UnitEntry),
+    Structure(StructureEntry),
+    Sack(SackEntry), // lying outside or owned by a dropped unit
+    Trap { effect_index: usize, x: u32, y: u32 }, // index and position before the transform
+    // A coordinate outside the new map gets clamped into it, one without its pair can't be rotated and is kept
+    TriggerCoordinate { location: TriggerLocation, argument_type: ArgumentType, value: u32 }
+}
+
+impl AlmMap {
+    // Mirroring and rotating move tiles without fixing their transition corners, see transform_retiled
+    pub fn transform(&mut self, transform: MapTransform, database: Option<&GameDatabase>) -> Vec<DroppedEntity> {
+        let geometry = Geometry::new(self.general_info.width, self.general_info.height, transform);
+        let mut dropped = Vec::new();
+        let item_effects: HashSet<u32> = self.sacks
+            .iter()
+            .flat_map(|sacks| sacks.sacks.iter())
+            .flat_map(|sack| sack.items.iter())
+            .map(|item| item.effect_id)
+            .collect();
+        self.transform_cells(&geometry);
+
+        if let Some(structures) = &mut self.structures {
+            let mut kept = Vec::with_capacity(structures.structures.len());
+            for mut structure in structures.structures.drain(..) {
+                let (x, y) = structure.tile_position();
+                let (width, height) = structure_footprint(&structure, database);
+                let (new_x, new_y, new_width, new_height) = geometry.rect(x, y, width, height);
+                if !geometry.contains_rect(new_x, new_y, new_width, new_height) {
+                    dropped.push(DroppedEntity::Structure(structure));
+                    continue;
+                }
+                if structure.is_bridge() && structure.bridge_info.width != 0 && structure.bridge_info.height != 0 {
+                    structure.bridge_info.width = new_width;
+                    structure.bridge_info.height = new_height;
+                }
+                structure.x_coord = (new_x as u32) << 8 | (structure.x_coord & 0xFF);
+                structure.y_coord = (new_y as u32) << 8 | (structure.y_coord & 0xFF);
+                kept.push(structure);
+            }
+            structures.structures = kept;
+        }
+
+        let mut dropped_units = HashSet::new();
+        if let Some(units) = &mut self.units {
+            let mut kept = Vec::with_capacity(units.units.len());
+            for mut unit in units.units.drain(..) {
+                match geometry.fixed_point(unit.x_coord, unit.y_coord) {
+                    Some((x, y)) => {
+                        unit.x_coord = x;
+                        unit.y_coord = y;
+                        kept.push(unit);
+                    },
+                    None => {
+                        dropped_units.insert(unit.unit_id as u32);
+                        dropped.push(DroppedEntity::Unit(unit));
+                    }
+                }
+            }
+            units.units = kept;
+        }
+
+        // Owned sacks go with their unit, the ones on the ground are assumed to use unit coordinates
+        if let Some(sacks) = &mut self.sacks {
+            let mut kept = Vec::with_capacity(sacks.sacks.len());
+            for mut sack in sacks.sacks.drain(..) {
+                if sack.unit_id != 0 {
+                    if dropped_units.contains(&sack.unit_id) {
+                        dropped.push(DroppedEntity::Sack(sack));
+                    } else {
+                        kept.push(sack);
+                    }
+                    continue;
+                }
+                match geometry.fixed_point(sack.x_coord, sack.y_coord) {
+                    Some((x, y)) => {
+                        sack.x_coord = x;
+                        sack.y_coord = y;
+                        kept.push(sack);
+                    },
+                    None => dropped.push(DroppedEntity::Sack(sack))
+                }
+            }
+            sacks.sacks = kept;
+        }
+
+        // Effects are either item magic, referred to by sack items through their 1-based index,
+        // or traps lying on a tile. Dropped traps are removed and the item references renumbered
+        if let Some(effects) = &mut self.effects {
+            let mut kept = Vec::with_capacity(effects.effects.len());
+            let mut new_ids = Vec::with_capacity(effects.effects.len()); // 0 for removed effects
+            for (effect_index, mut effect) in effects.effects.drain(..).enumerate() {
+                if !item_effects.contains(&(effect_index as u32 + 1)) {
+                    let (x, y) = geometry.point(effect.trap_x as i64, effect.trap_y as i64);
+                    if !geometry.contains(x, y) {
+                        dropped.push(DroppedEntity::Trap { effect_index, x: effect.trap_x, y: effect.trap_y });
+                        new_ids.push(0);
+                        continue;
+                    }
+                    effect.trap_x = x as u32;
+                    effect.trap_y = y as u32;
+                }
+                kept.push(effect);
+                new_ids.push(kept.len() as u32);
+            }
+            effects.effects = kept;
+            let items = self.sacks
+                .iter_mut()
+                .flat_map(|sacks| sacks.sacks.iter_mut())
+                .flat_map(|sack| sack.items.iter_mut());
+            for item in items {
+                if let Some(&new_id) = item.effect_id.checked_sub(1).and_then(|idx| new_ids.get(idx as usize)) {
+                    item.effect_id = new_id;
+                }
+            }
+        }
+
+        if let Some(triggers) = &mut self.triggers {
+            for check in triggers.checks.iter_mut() {
+                let location = TriggerLocation::Check(check.id);
+                let types = &check.argument_types;
+                transform_coordinates(&mut check.argument_values, types, location, &geometry, &mut dropped);
+            }
+            for instance in triggers.instances.iter_mut() {
+                let location = TriggerLocation::Instance(instance.id);
+                let types = &instance.argument_types;
+                transform_coordinates(&mut instance.argument_values, types, location, &geometry, &mut dropped);
+            }
+        }
+
+        let info = &mut self.general_info;
+        info.width = geometry.new_width as u32;
+        info.height = geometry.new_height as u32;
+        if let Some(structures) = &self.structures {
+            info.structure_count = structures.structures.len() as u32;
+        }
+        if let Some(units) = &self.units {
+            info.unit_count = units.units.len() as u32;
+        }
+        if let Some(sacks) = &self.sacks {
+            info.sack_count = sacks.sacks.len() as u32;
+        }
+        dropped
+    }
+
+    // Transforms the map, then retiles it after a mirror or a rotation
+    pub fn transform_retiled<TLayout: TerrainTileLayout>(
+        &mut self,
+        transform: MapTransform,
+        database: Option<&GameDatabase>,
+        layout: &TLayout
+    ) -> Vec<DroppedEntity> {
+        let dropped = self.transform(transform, database);
+        if matches!(transform, MapTransform::MirrorHorizontally | MapTransform::MirrorVertically | MapTransform::RotateClockwise) {
+            self.retile_terrain(layout);
+        }
+        dropped
+    }
+
+    fn transform_cells(&mut self, geometry: &Geometry) {
+        let old_width = geometry.old_width as usize;
+        let (new_width, new_height) = (geometry.new_width as usize, geometry.new_height as usize);
+        let sources: Vec<Option<usize>> = (0..new_width * new_height)
+            .map(|idx| geometry.source(idx % new_width, idx / new_width).map(|(x, y)| y * old_width + x))
+            .collect();
+        let filler = match geometry.transform {
+            MapTransform::Extend { filler, .. } => filler,
+            _ => TileEntry::from_raw(0)
+        };
+
+        if let Some(tiles) = &mut self.tiles {
+            tiles.tiles = sources
+                .iter()
+                .map(|source| source.and_then(|idx| tiles.tiles.get(idx).copied()).unwrap_or(filler))
+                .collect();
+        }
+        if let Some(height_map) = &mut self.height_map {
+            height_map.heights = sources
+                .iter()
+                .map(|source| source.and_then(|idx| height_map.heights.get(idx).copied()).unwrap_or(0))
+                .collect();
+        }
+        if let Some(map_objects) = &mut self.map_objects {
+            let mut transformed = MapObjectsSection::new(new_width, new_height);
+            for (object, source) in transformed.objects.iter_mut().zip(sources.iter()) {
+                if let Some(&id) = source.and_then(|idx| map_objects.objects.get(idx)) {
+                    *object = id;
+                }
+            }
+            *map_objects = transformed;
+        }
+    }
+}
+
+struct Geometry {
+    transform: MapTransform,
+    old_width: i64,
+    old_height: i64,
+    new_width: i64,
+    new_height: i64
+}
+impl Geometry {
+    fn new(width: u32, height: u32, transform: MapTransform) -> Self {
+        let (old_width, old_height) = (width as i64, height as i64);
+        let (new_width, new_height) = match transform {
+            MapTransform::Crop { x, y, width, height } => (
+                (width as i64).min(old_width - x as i64).max(0),
+                (height as i64).min(old_height - y as i64).max(0)
+            ),
+            MapTransform::Extend { left, top, right, bottom, .. } => (
+                old_width + left as i64 + right as i64,
+                old_height + top as i64 + bottom as i64
+            ),
+            MapTransform::MirrorHorizontally | MapTransform::MirrorVertically => (old_width, old_height),
+            MapTransform::RotateClockwise => (old_height, old_width)
+        };
+        Self { transform, old_width, old_height, new_width, new_height }
+    }
+
+    // Where an old tile ends up, possibly outside the new map
+    fn point(&self, x: i64, y: i64) -> (i64, i64) {
+        match self.transform {
+            MapTransform::Crop { x: left, y: top, .. } => (x - left as i64, y - top as i64),
+            MapTransform::Extend { left, top, .. } => (x + left as i64, y + top as i64),
+            MapTransform::MirrorHorizontally => (self.old_width - 1 - x, y),
+            MapTransform::MirrorVertically => (x, self.old_height - 1 - y),
+            MapTransform::RotateClockwise => (self.old_height - 1 - y, x)
+        }
+    }
+
+    // The old tile a new one is taken from
+    fn source(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        let (x, y) = (x as i64, y as i64);
+        let (source_x, source_y) = match self.transform {
+            MapTransform::Crop { x: left, y: top, .. } => (x + left as i64, y + top as i64),
+            MapTransform::Extend { left, top, .. } => (x - left as i64, y - top as i64),
+            MapTransform::MirrorHorizontally => (self.old_width - 1 - x, y),
+            MapTransform::MirrorVertically => (x, self.old_height - 1 - y),
+            MapTransform::RotateClockwise => (y, self.old_height - 1 - x)
+        };
+        let inside = (0..self.old_width).contains(&source_x) && (0..self.old_height).contains(&source_y);
+        inside.then_some((source_x as usize, source_y as usize))
+    }
+
+    fn contains(&self, x: i64, y: i64) -> bool {
+        (0..self.new_width).contains(&x) && (0..self.new_height).contains(&y)
+    }
+
+    fn contains_rect(&self, x: i64, y: i64, width: u32, height: u32) -> bool {
+        self.contains(x, y) && self.contains(x + width as i64 - 1, y + height as i64 - 1)
+    }
+
+    // Top left corner and size of a transformed rectangle
+    fn rect(&self, x: u32, y: u32, width: u32, height: u32) -> (i64, i64, u32, u32) {
+        let (x, y) = (x as i64, y as i64);
+        let (x1, y1) = self.point(x, y);
+        let (x2, y2) = self.point(x + width as i64 - 1, y + height as i64 - 1);
+        let (width, height) = match self.transform {
+            MapTransform::RotateClockwise => (height, width),
+            _ => (width, height)
+        };
+        (x1.min(x2), y1.min(y2), width, height)
+    }
+
+    // 8.8 fixed point coordinates: the tile moves and the offset inside it is mirrored along
+    fn fixed_point(&self, x: u32, y: u32) -> Option<(u32, u32)> {
+        let (tile_x, tile_y) = self.point((x >> 8) as i64, (y >> 8) as i64);
+        if !self.contains(tile_x, tile_y) {
+            return None;
+        }
+        let (offset_x, offset_y) = (x & 0xFF, y & 0xFF);
+        let (offset_x, offset_y) = match self.transform {
+            MapTransform::MirrorHorizontally => (0xFF - offset_x, offset_y),
+            MapTransform::MirrorVertically => (offset_x, 0xFF - offset_y),
+            MapTransform::RotateClockwise => (0xFF - offset_y, offset_x),
+            _ => (offset_x, offset_y)
+        };
+        Some(((tile_x as u32) << 8 | offset_x, (tile_y as u32) << 8 | offset_y))
+    }
+}
+
+// The n-th X argument pairs with the n-th Y one. With exactly two pairs the arguments
+// are taken for a box, and its corners are put back in order afterwards
+fn transform_coordinates(
+    values: &mut [u32; 10],
+    types: &[ArgumentType],
+    location: TriggerLocation,
+    geometry: &Geometry,
+    dropped: &mut Vec<DroppedEntity>
+) {
+    let slots = |argument_type: ArgumentType| -> Vec<usize> {
+        types.iter().enumerate().filter(|(_, &t)| t == argument_type).map(|(idx, _)| idx).collect()
+    };
+    let (xs, ys) = (slots(ArgumentType::X), slots(ArgumentType::Y));
+    let max_x = (geometry.new_width - 1).max(0);
+    let max_y = (geometry.new_height - 1).max(0);
+    for pair in 0..xs.len().max(ys.len()) {
+        let (x_slot, y_slot) = (xs.get(pair).copied(), ys.get(pair).copied());
+        let is_rotation = matches!(geometry.transform, MapTransform::RotateClockwise);
+        let (x, y) = match (x_slot, y_slot) {
+            (Some(x_slot), Some(y_slot)) => geometry.point(values[x_slot] as i64, values[y_slot] as i64),
+            _ if is_rotation => {
+                let (argument_type, slot) = match x_slot {
+                    Some(slot) => (ArgumentType::X, slot),
+                    None => (ArgumentType::Y, y_slot.unwrap_or_default())
+                };
+                dropped.push(DroppedEntity::TriggerCoordinate { location, argument_type, value: values[slot] });
+                continue;
+            },
+            (Some(x_slot), None) => (geometry.point(values[x_slot] as i64, 0).0, 0),
+            (None, Some(y_slot)) => (0, geometry.point(0, values[y_slot] as i64).1),
+            (None, None) => continue
+        };
+        if let Some(slot) = x_slot {
+            if !(0..=max_x).contains(&x) {
+                dropped.push(DroppedEntity::TriggerCoordinate { location, argument_type: ArgumentType::X, value: values[slot] });
+            }
+            values[slot] = x.clamp(0, max_x) as u32;
+        }
+        if let Some(slot) = y_slot {
+            if !(0..=max_y).contains(&y) {
+                dropped.push(DroppedEntity::TriggerCoordinate { location, argument_type: ArgumentType::Y, value: values[slot] });
+            }
+            values[slot] = y.clamp(0, max_y) as u32;
+        }
+    }
+    if xs.len() == 2 && ys.len() == 2 {
+        for slots in [&xs, &ys] {
+            if values[slots[0]] > values[slots[1]] {
+                values.swap(slots[0], slots[1]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alm::*;
+    use crate::data_bin::{DataBinContent, StructureInfo, StructureSection};
+    use crate::game_database::GameDatabase;
+
+    fn effect(trap_x: u32, trap_y: u32) -> EffectEntry {
+        EffectEntry {
+            corrupt_effect_id: 0,
+            trap_x,
+            trap_y,
+            flags_or_magic_sphere: 0,
+            service_data: 0,
+            modifiers: Vec::new()
+        }
+    }
+
+    #[test]
+    fn test_traps_follow_the_map() {
+        let mut builder = AlmMapBuilder::new(4, 4);
+        builder
+            .add_sack(SackEntry {
+                unit_id: 0,
+                x_coord: 2 << 8,
+                y_coord: 2 << 8,
+                money: 0,
+                items: vec![
+                    ItemEntry { id: 1, wielded: 0, effect_id: 1 },
+                    ItemEntry { id: 2, wielded: 0, effect_id: 3 }
+                ]
+            })
+            .add_effect(effect(0, 0))
+            .add_effect(effect(0, 0))
+            .add_effect(effect(0, 0));
+        let mut map = builder.build();
+
+        assert!(map.transform(MapTransform::MirrorHorizontally, None).is_empty());
+        let effects = &map.effects.as_ref().unwrap().effects;
+        let positions: Vec<(u32, u32)> = effects.iter().map(|effect| (effect.trap_x, effect.trap_y)).collect();
+        assert_eq!(positions, vec![(0, 0), (3, 0), (0, 0)]);
+
+        let dropped = map.transform(MapTransform::Crop { x: 0, y: 0, width: 3, height: 4 }, None);
+        assert!(matches!(dropped[..], [DroppedEntity::Trap { effect_index: 1, x: 3, y: 0 }]));
+        assert_eq!(map.effects.as_ref().unwrap().effects.len(), 2);
+        let items = &map.sacks.as_ref().unwrap().sacks[0].items;
+        assert_eq!((items[0].effect_id, items[1].effect_id), (1, 2));
+    }
+
+    #[test]
+    fn test_structure_footprint_from_database() {
+        let map = || {
+            let mut builder = AlmMapBuilder::new(4, 4);
+            builder.add_fraction("Player", 0, 0, 0);
+            builder.add_structure(StructureEntry { type_id: 1, ..Default::default() });
+            builder.build()
+        };
+        let mut info = StructureInfo::default();
+        info.details.size_x = 3;
+        info.details.size_y = 2;
+        let database = GameDatabase::new(DataBinContent {
+            shape_section: None,
+            item_section: None,
+            magic_item_section: None,
+            parameter_section: None,
+            spell_section: None,
+            structure_section: Some(StructureSection { data: vec![StructureInfo::default(), info] }),
+            unit_section: None,
+            human_section: None
+        });
+
+        let mut mirrored = map();
+        assert!(mirrored.transform(MapTransform::MirrorHorizontally, None).is_empty());
+        assert_eq!(mirrored.structures.as_ref().unwrap().structures[0].tile_position(), (3, 0));
+        let mut mirrored = map();
+        assert!(mirrored.transform(MapTransform::MirrorHorizontally, Some(&database)).is_empty());
+        assert_eq!(mirrored.structures.as_ref().unwrap().structures[0].tile_position(), (1, 0));
+
+        let mut cropped = map();
+        let dropped = cropped.transform(MapTransform::Crop { x: 0, y: 0, width: 2, height: 4 }, Some(&database));
+        assert!(matches!(dropped[..], [DroppedEntity::Structure(_)]));
+    }
+
+    struct MaskLayout;
+    impl TerrainTileLayout for MaskLayout {
+        fn tile(&self, terrain: TerrainKind, corner_mask: u8, _x: usize, _y: usize) -> TileEntry {
+            let terrain_id = match terrain {
+                TerrainKind::Grass => 0,
+                TerrainKind::Sand => 1,
+                TerrainKind::Rock => 2,
+                TerrainKind::Water => 3
+            };
+            TileEntry::new(terrain_id, corner_mask, 0, false)
+        }
+    }
+
+    #[test]
+    fn test_mirror_fixes_transition_corners() {
+        let sand = |x: usize| {
+            let mut builder = AlmMapBuilder::new(3, 3);
+            builder.paint_terrain(x, 0, TerrainBrush { terrain: TerrainKind::Sand, radius: 0 }, &MaskLayout);
+            let mut map = builder.build();
+            map.retile_terrain(&MaskLayout);
+            map
+        };
+        let tiles = |map: &AlmMap| map.tiles.as_ref().unwrap().tiles.clone();
+        let expected = tiles(&sand(2));
+
+        let mut moved = sand(0);
+        moved.transform(MapTransform::MirrorHorizontally, None);
+        assert_ne!(tiles(&moved), expected);
+        let mut retiled = sand(0);
+        assert!(retiled.transform_retiled(MapTransform::MirrorHorizontally, None, &MaskLayout).is_empty());
+        assert_eq!(tiles(&retiled), expected);
+    }
+
+    #[test]
+    fn test_huge_footprint_is_dropped() {
+        let mut builder = AlmMapBuilder::new(4, 4);
+        builder.add_fraction("Player", 0, 0, 0);
+        builder.add_bridge(0x100, 0, u32::MAX, 1, 0);
+        let mut map = builder.build();
+        let dropped = map.transform(MapTransform::MirrorHorizontally, None);
+        assert!(matches!(dropped[..], [DroppedEntity::Structure(_)]));
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::HashSet;
+use super::*;
+use crate::game_database::GameDatabase;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MapIssue {
+    UnitOutOfBounds { unit_id: u16, x: u32, y: u32 }, // tile coordinates
+    StructureOutOfBounds { structure_id: u16, x: u32, y: u32 },
+    OverlappingStructures { first_id: u16, second_id: u16 },
+    UnitFractionOutOfRange { unit_id: u16, fraction_id: u32 },
+    StructureFractionOutOfRange { structure_id: u16, fraction_id: u32 },
+    SackWithoutUnit { unit_id: u32 },
+    DuplicateUnitId { unit_id: u16 },
+    DuplicateStructureId { structure_id: u16 },
+    CountMismatch { kind: SectionKind, declared: u32, loaded: usize },
+    EmptyBridge { structure_id: u16 },
+    SectionSizeMismatch { kind: SectionKind, expected: usize, found: usize }
+}
+impl std::fmt::Display for MapIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MapIssue::UnitOutOfBounds { unit_id, x, y } => write!(f, "unit {} at ({}, {}) is outside the map", unit_id, x, y),
+            MapIssue::StructureOutOfBounds { structure_id, x, y } => {
+                write!(f, "structure {} at ({}, {}) doesn't fit in the map", structure_id, x, y)
+            },
+            MapIssue::OverlappingStructures { first_id, second_id } => {
+                write!(f, "structures {} and {} overlap", first_id, second_id)
+            },
+            MapIssue::UnitFractionOutOfRange { unit_id, fraction_id } => {
+                write!(f, "unit {} belongs to missing fraction {}", unit_id, fraction_id)
+            },
+            MapIssue::StructureFractionOutOfRange { structure_id, fraction_id } => {
+                write!(f, "structure {} belongs to missing fraction {}", structure_id, fraction_id)
+            },
+            MapIssue::SackWithoutUnit { unit_id } => write!(f, "sack refers to missing unit {}", unit_id),
+            MapIssue::DuplicateUnitId { unit_id } => write!(f, "unit id {} is used more than once", unit_id),
+            MapIssue::DuplicateStructureId { structure_id } => {
+                write!(f, "structure id {} is used more than once", structure_id)
+            },
+            MapIssue::CountMismatch { kind, declared, loaded } => {
+                write!(f, "general info declares {} {:?} entries but {} are loaded", declared, kind, loaded)
+            },
+            MapIssue::EmptyBridge { structure_id } => write!(f, "bridge {} has a zero span", structure_id),
+            MapIssue::SectionSizeMismatch { kind, expected, found } => {
+                write!(f, "{:?} section has {} cells instead of {}", kind, found, expected)
+            }
+        }
+    }
+}
+
+impl AlmMap {
+    // Structures other than bridges take a single tile, their size is in data.bin
+    pub fn validate(&self) -> Vec<MapIssue> {
+        self.issues(StructureEntry::footprint)
+    }
+
+    pub fn validate_with_database(&self, database: &GameDatabase) -> Vec<MapIssue> {
+        self.issues(|structure| database.structure_footprint(structure))
+    }
+
+    fn issues<F: Fn(&StructureEntry) -> (u32, u32)>(&self, footprint: F) -> Vec<MapIssue> {
+        let mut issues = Vec::new();
+        let info = &self.general_info;
+        let (width, height) = (info.width, info.height);
+        let cell_count = width as usize * height as usize;
+
+        if let Some(tiles) = &self.tiles {
+            if tiles.tiles.len() != cell_count {
+                issues.push(MapIssue::SectionSizeMismatch {
+                    kind: SectionKind::Tiles,
+                    expected: cell_count,
+                    found: tiles.tiles.len()
+                });
+            }
+        }
+        if let Some(height_map) = &self.height_map {
+            if height_map.heights.len() != cell_count {
+                issues.push(MapIssue::SectionSizeMismatch {
+                    kind: SectionKind::HeightMap,
+                    expected: cell_count,
+                    found: height_map.heights.len()
+                });
+            }
+        }
+        if let Some(map_objects) = &self.map_objects {
+            if map_objects.objects.len() != cell_count {
+                issues.push(MapIssue::SectionSizeMismatch {
+                    kind: SectionKind::MapObjects,
+                    expected: cell_count,
+                    found: map_objects.objects.len()
+                });
+            }
+        }
+
+        let counts = [
+            (SectionKind::Fractions, info.fraction_count, self.fractions.as_ref().map(|s| s.fractions.len())),
+            (SectionKind::Structures, info.structure_count, self.structures.as_ref().map(|s| s.structures.len())),
+            (SectionKind::Units, info.unit_count, self.units.as_ref().map(|s| s.units.len())),
+            (SectionKind::Triggers, info.logic_count, self.triggers.as_ref().map(|s| s.triggers.len())),
+            (SectionKind::Sacks, info.sack_count, self.sacks.as_ref().map(|s| s.sacks.len()))
+        ];
+        for &(kind, declared, loaded) in counts.iter() {
+            if let Some(loaded) = loaded.filter(|&loaded| loaded != declared as usize) {
+                issues.push(MapIssue::CountMismatch { kind, declared, loaded });
+            }
+        }
+
+        let structures = self.structures.as_ref().map_or(&[][..], |s| &s.structures[..]);
+        let mut structure_ids = HashSet::new();
+        let mut footprints = Vec::with_capacity(structures.len());
+        for structure in structures.iter() {
+            let structure_id = structure.id;
+            let (x, y) = structure.tile_position();
+            if structure.is_bridge() && (structure.bridge_info.width == 0 || structure.bridge_info.height == 0) {
+                issues.push(MapIssue::EmptyBridge { structure_id });
+            }
+            let (span_x, span_y) = footprint(structure);
+            if x as u64 + span_x as u64 > width as u64 || y as u64 + span_y as u64 > height as u64 {
+                issues.push(MapIssue::StructureOutOfBounds { structure_id, x, y });
+            }
+            if structure.fraction_id >= info.fraction_count {
+                issues.push(MapIssue::StructureFractionOutOfRange { structure_id, fraction_id: structure.fraction_id });
+            }
+            if !structure_ids.insert(structure_id) {
+                issues.push(MapIssue::DuplicateStructureId { structure_id });
+            }
+            footprints.push((structure_id, x as u64, y as u64, span_x as u64, span_y as u64));
+        }
+        for (idx, &(first_id, x1, y1, w1, h1)) in footprints.iter().enumerate() {
+            for &(second_id, x2, y2, w2, h2) in footprints[idx + 1..].iter() {
+                if x1 < x2 + w2 && x2 < x1 + w1 && y1 < y2 + h2 && y2 < y1 + h1 {
+                    issues.push(MapIssue::OverlappingStructures { first_id, second_id });
+                }
+            }
+        }
+
+        let units = self.units.as_ref().map_or(&[][..], |s| &s.units[..]);
+        let mut unit_ids = HashSet::new();
+        for unit in units.iter() {
+            let unit_id = unit.unit_id;
+            let (x, y) = unit.tile_position();
+            if x >= width || y >= height {
+                issues.push(MapIssue::UnitOutOfBounds { unit_id, x, y });
+            }
+            if unit.fraction_id >= info.fraction_count {
+                issues.push(MapIssue::UnitFractionOutOfRange { unit_id, fraction_id: unit.fraction_id });
+            }
+            if !unit_ids.insert(unit_id as u32) {
+                issues.push(MapIssue::DuplicateUnitId { unit_id });
+            }
+        }
+
+        // Sacks lying on the ground have no owner
+        for sack in self.sacks.iter().flat_map(|sacks| sacks.sacks.iter()) {
+            if sack.unit_id != 0 && !unit_ids.contains(&sack.unit_id) {
+                issues.push(MapIssue::SackWithoutUnit { unit_id: sack.unit_id });
+            }
+        }
+        issues
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alm::*;
+    use crate::data_bin::{DataBinContent, StructureInfo, StructureSection};
+    use crate::game_database::GameDatabase;
+
+    #[test]
+    fn test_structure_footprints_from_database() {
+        let mut builder = AlmMapBuilder::new(6, 6);
+        let fraction = builder.add_fraction("Player", 0, 0, 0);
+        for &(x, y) in [(1, 1), (3, 1), (4, 4)].iter() {
+            builder.add_structure(StructureEntry {
+                x_coord: x << 8,
+                y_coord: y << 8,
+                type_id: 1,
+                fraction_id: fraction,
+                ..Default::default()
+            });
+        }
+        let map = builder.build();
+        assert!(map.validate().is_empty());
+
+        let mut info = StructureInfo::default();
+        info.details.size_x = 3;
+        info.details.size_y = 2;
+        let database = GameDatabase::new(DataBinContent {
+            shape_section: None,
+            item_section: None,
+            magic_item_section: None,
+            parameter_section: None,
+            spell_section: None,
+            structure_section: Some(StructureSection { data: vec![StructureInfo::default(), info] }),
+            unit_section: None,
+            human_section: None
+        });
+        assert_eq!(map.validate_with_database(&database), vec![
+            MapIssue::StructureOutOfBounds { structure_id: 3, x: 4, y: 4 },
+            MapIssue::OverlappingStructures { first_id: 1, second_id: 2 }
+        ]);
+    }
+
+    #[test]
+    fn test_entry_issues() {
+        let mut builder = AlmMapBuilder::new(4, 4);
+        let player = builder.add_fraction("Player", 0, 0, 0);
+        builder.add_unit(UnitEntry::new(0x100, 0x100, 1, player));
+        builder.add_unit(UnitEntry::new(0x300, 0x300, 1, 5));
+        for x in [0, 2] {
+            builder.add_structure(StructureEntry { x_coord: x << 8, type_id: 1, fraction_id: player, ..Default::default() });
+        }
+        builder.add_bridge(0, 0x200, 0, 1, player);
+        builder.add_sack(SackEntry { unit_id: 42, x_coord: 0, y_coord: 0, money: 0, items: Vec::new() });
+        let mut map = builder.build();
+        assert_eq!(map.validate(), vec![
+            MapIssue::EmptyBridge { structure_id: 3 },
+            MapIssue::UnitFractionOutOfRange { unit_id: 2, fraction_id: 5 },
+            MapIssue::SackWithoutUnit { unit_id: 42 }
+        ]);
+
+        let units = &mut map.units.as_mut().unwrap().units;
+        units.push(units[0].clone());
+        let structures = &mut map.structures.as_mut().unwrap().structures;
+        structures[1].id = structures[0].id;
+        map.height_map.as_mut().unwrap().heights.pop();
+        assert_eq!(map.validate(), vec![
+            MapIssue::SectionSizeMismatch { kind: SectionKind::HeightMap, expected: 16, found: 15 },
+            MapIssue::CountMismatch { kind: SectionKind::Units, declared: 2, loaded: 3 },
+            MapIssue::DuplicateStructureId { structure_id: 1 },
+            MapIssue::EmptyBridge { structure_id: 3 },
+            MapIssue::UnitFractionOutOfRange { unit_id: 2, fraction_id: 5 },
+            MapIssue::DuplicateUnitId { unit_id: 1 },
+            MapIssue::SackWithoutUnit { unit_id: 42 }
+        ]);
+    }
+}
@@ -0,0 +1,189 @@
+use crate::images::rgba_image::{RgbaImage, split_argb, join_argb};
+use crate::game_database::GameDatabase;
+use super::*;
+
+const GRASS_COLOR: u32 = 0xFF_4A_7A_2C;
+const SAND_COLOR: u32 = 0xFF_C8_B0_70;
+const ROCK_COLOR: u32 = 0xFF_7A_72_66;
+const WATER_COLOR: u32 = 0xFF_2A_4C_8C;
+const OBJECT_COLOR: u32 = 0xFF_1E_3A_14;
+const NEUTRAL_COLOR: u32 = 0xFF_C0_C0_C0;
+const IMPASSABLE_SHADE: f32 = 0.7;
+
+// Indexed by FractionEntry::color_id
+const FRACTION_COLORS: [u32; 16] = [
+    0xFF_E0_20_20, 0xFF_20_60_E0, 0xFF_20_C0_20, 0xFF_E0_E0_20,
+    0xFF_E0_80_20, 0xFF_A0_20_E0, 0xFF_20_E0_E0, 0xFF_F0_F0_F0,
+    0xFF_80_10_10, 0xFF_10_30_80, 0xFF_10_70_10, 0xFF_80_80_10,
+    0xFF_80_40_10, 0xFF_60_10_80, 0xFF_10_80_80, 0xFF_40_40_40
+];
+
+// scale is the size of a tile in pixels
+pub fn render_minimap(map: &AlmMap, scale: usize) -> RgbaImage {
+    render(map, scale, StructureEntry::footprint)
+}
+
+// Structures get their data.bin size
+pub fn render_minimap_with_database(map: &AlmMap, database: &GameDatabase, scale: usize) -> RgbaImage {
+    render(map, scale, |structure| database.structure_footprint(structure))
+}
+
+fn render<F: Fn(&StructureEntry) -> (u32, u32)>(map: &AlmMap, scale: usize, footprint: F) -> RgbaImage {
+    let scale = scale.max(1);
+    let (width, height) = (map.general_info.width as usize, map.general_info.height as usize);
+    let mut image = RgbaImage::new(width * scale, height * scale);
+    let height_at = |x: usize, y: usize| -> f32 {
+        match &map.height_map {
+            Some(height_map) => height_map.heights.get(y * width + x).copied().unwrap_or(0) as f32,
+            None => 0.0
+        }
+    };
+
+    for y in 0..height {
+        for x in 0..width {
+            let tile = map.tiles.as_ref().and_then(|tiles| tiles.tiles.get(y * width + x).copied());
+            let mut color = terrain_color(tile.map_or(TerrainKind::Grass, TileEntry::get_terrain_kind));
+            if let Some(tile) = tile {
+                if !tile.is_passable() && tile.get_terrain_kind() != TerrainKind::Water {
+                    color = shade(color, IMPASSABLE_SHADE);
+                }
+            }
+            // higher ground is brighter, slopes facing the top left corner catch the light
+            let h = height_at(x, y);
+            let slope = h - height_at(x.saturating_sub(1), y.saturating_sub(1));
+            let factor = 0.75 + 0.35 * h / 255.0 + (slope / 64.0).clamp(-0.2, 0.2);
+            fill_rect(&mut image, x * scale, y * scale, scale, scale, shade(color, factor));
+        }
+    }
+
+    if let Some(map_objects) = &map.map_objects {
+        let dot = (scale / 2).max(1);
+        for (x, y, _) in map_objects.iter_objects() {
+            fill_rect(&mut image, x * scale + (scale - dot) / 2, y * scale + (scale - dot) / 2, dot, dot, OBJECT_COLOR);
+        }
+    }
+
+    if let Some(structures) = &map.structures {
+        for structure in structures.structures.iter() {
+            let (x, y) = structure.tile_position();
+            let (footprint_width, footprint_height) = footprint(structure);
+            let color = shade(fraction_color(map, structure.fraction_id), 0.8);
+            fill_rect(
+                &mut image,
+                x as usize * scale,
+                y as usize * scale,
+                footprint_width as usize * scale,
+                footprint_height as usize * scale,
+                color
+            );
+        }
+    }
+
+    if let Some(units) = &map.units {
+        let dot = (scale * 3 / 4).max(1);
+        for unit in units.units.iter() {
+            let (x, y) = unit.tile_position();
+            let color = fraction_color(map, unit.fraction_id);
+            fill_rect(
+                &mut image,
+                x as usize * scale + (scale - dot) / 2,
+                y as usize * scale + (scale - dot) / 2,
+                dot,
+                dot,
+                color
+            );
+        }
+    }
+    image
+}
+
+pub fn fraction_color(map: &AlmMap, fraction_id: u32) -> u32 {
+    map.fractions
+        .as_ref()
+        .and_then(|fractions| fractions.fractions.get(fraction_id as usize))
+        .map_or(NEUTRAL_COLOR, |fraction| FRACTION_COLORS[fraction.color_id as usize % FRACTION_COLORS.len()])
+}
+
+pub(crate) fn terrain_color(kind: TerrainKind) -> u32 {
+    match kind {
+        TerrainKind::Grass => GRASS_COLOR,
+        TerrainKind::Sand => SAND_COLOR,
+        TerrainKind::Rock => ROCK_COLOR,
+        TerrainKind::Water => WATER_COLOR
+    }
+}
+
+pub(crate) fn shade(color: u32, factor: f32) -> u32 {
+    let (a, r, g, b) = split_argb(color);
+    let scale = |channel: u8| (channel as f32 * factor).round().clamp(0.0, 255.0) as u8;
+    join_argb(a, scale(r), scale(g), scale(b))
+}
+
+// Clipped to the image
+fn fill_rect(image: &mut RgbaImage, x: usize, y: usize, width: usize, height: usize, color: u32) {
+    for pixel_y in y..(y + height).min(image.height) {
+        for pixel_x in x..(x + width).min(image.width) {
+            image.set_pixel(pixel_x, pixel_y, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alm::*;
+    use crate::data_bin::{DataBinContent, StructureInfo, StructureSection};
+    use crate::game_database::GameDatabase;
+
+    #[test]
+    fn test_minimap_pixels() {
+        let mut builder = AlmMapBuilder::new(4, 3);
+        builder
+            .paint_tile(3, 2, TileEntry::new(3, 15, 0, false))
+            .paint_tile(0, 1, TileEntry::new(2, 15, 0, false))
+            .place_object(0, 2, ObjectId(5));
+        let player = builder.add_fraction("Player", 1, 0, 0);
+        builder.add_unit(UnitEntry::new(0x100, 0x100, 1, player));
+        builder.add_bridge(0x200, 0, 2, 1, player);
+        let image = render_minimap(&builder.build(), 2);
+        assert_eq!((image.width, image.height), (8, 6));
+
+        // flat ground at height 0 is drawn at 0.75 of the terrain color, impassable land darker
+        assert_eq!(image.get_pixel(1, 0), shade(terrain_color(TerrainKind::Grass), 0.75));
+        assert_eq!(image.get_pixel(7, 5), shade(terrain_color(TerrainKind::Water), 0.75));
+        assert_eq!(image.get_pixel(1, 2), shade(shade(terrain_color(TerrainKind::Rock), 0.7), 0.75));
+        // objects are a dot of half a tile in its middle
+        assert_eq!(image.get_pixel(0, 4), 0xFF_1E_3A_14);
+        assert_eq!(image.get_pixel(1, 5), shade(terrain_color(TerrainKind::Grass), 0.75));
+        // the bridge covers its two tiles, units are a dot in their fraction color
+        let fraction = 0xFF_20_60_E0;
+        for x in 4..8 {
+            assert_eq!(image.get_pixel(x, 1), shade(fraction, 0.8));
+        }
+        assert_eq!(image.get_pixel(2, 2), fraction);
+        assert_eq!(image.get_pixel(3, 3), shade(terrain_color(TerrainKind::Grass), 0.75));
+    }
+
+    #[test]
+    fn test_minimap_structure_size_from_database() {
+        let mut info = StructureInfo::default();
+        info.details.size_x = 2;
+        info.details.size_y = 2;
+        let database = GameDatabase::new(DataBinContent {
+            shape_section: None,
+            item_section: None,
+            magic_item_section: None,
+            parameter_section: None,
+            spell_section: None,
+            structure_section: Some(StructureSection { data: vec![StructureInfo::default(), info] }),
+            unit_section: None,
+            human_section: None
+        });
+        let mut builder = AlmMapBuilder::new(3, 3);
+        builder.add_structure(StructureEntry { x_coord: 0x100, y_coord: 0x100, type_id: 1, ..Default::default() });
+        let map = builder.build();
+        let structure_color = shade(fraction_color(&map, 0), 0.8);
+        assert_eq!(render_minimap(&map, 1).get_pixel(2, 2), shade(terrain_color(TerrainKind::Grass), 0.75));
+        let image = render_minimap_with_database(&map, &database, 1);
+        assert_eq!([image.get_pixel(1, 1), image.get_pixel(2, 2)], [structure_color; 2]);
+    }
+}
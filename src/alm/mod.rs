@@ -8,6 +8,20 @@ mod units_section;
 mod triggers_section;
 mod sacks_section;
 mod effects_section;
+mod index;
+mod builder;
+mod minimap;
+mod terrain_renderer;
+pub mod navigation;
+mod trigger_script;
+pub mod trigger_vm;
+mod trigger_validation;
+mod trigger_arguments;
+mod trigger_graph;
+mod map_validation;
+mod map_diff;
+mod map_transform;
+mod terrain_painter;
 
 pub use {
     general_map_info_section::*,
@@ -19,11 +33,23 @@ pub use {
     units_section::*,
     triggers_section::*,
     sacks_section::*,
-    effects_section::*
+    effects_section::*,
+    index::*,
+    builder::*,
+    minimap::*,
+    terrain_renderer::*,
+    trigger_script::*,
+    trigger_validation::*,
+    trigger_arguments::*,
+    trigger_graph::*,
+    map_validation::*,
+    map_diff::*,
+    map_transform::*,
+    terrain_painter::*
 };
 use bin_serialization_rs::{Reflectable, SerializationReflector, Endianness};
 use std::convert::TryFrom;
-use std::io::{ErrorKind, Read, Cursor, Seek, SeekFrom};
+use std::io::{ErrorKind, Read, Write, Cursor};
 
 #[derive(Copy, Clone, PartialEq, Debug, num_enum::TryFromPrimitive)]
 #[repr(u32)]
@@ -39,14 +65,37 @@ pub enum SectionKind {
     Sacks,
     Effects
 }
+// not derived, num_enum would take a #[default] variant for every unknown value
+#[allow(clippy::derivable_impls)]
 impl Default for SectionKind {
     fn default() -> Self {
         SectionKind::General
     }
 }
+impl SectionKind {
+    pub const ALL: [SectionKind; 10] = [
+        SectionKind::General,
+        SectionKind::Tiles,
+        SectionKind::HeightMap,
+        SectionKind::MapObjects,
+        SectionKind::Structures,
+        SectionKind::Fractions,
+        SectionKind::Units,
+        SectionKind::Triggers,
+        SectionKind::Sacks,
+        SectionKind::Effects
+    ];
+}
+
+pub const ALM_SIGNATURE: u32 = 0x0052_374D; // "M7R"
+pub const ALM_HEADER_SIZE: u32 = 0x14;
+pub const SECTION_HEADER_SIZE: u32 = 0x14;
+const DEFAULT_SECTION_ID: u32 = 7;
 
 #[derive(Debug)]
 pub struct AlmMap {
+    pub header: AlmHeader,
+    pub section_headers: Vec<SectionHeader>, // in file order
     pub general_info: GeneralMapInfoSection,
     pub tiles: Option<TilesSection>,
     pub height_map: Option<HeightMapSection>,
@@ -56,104 +105,253 @@ pub struct AlmMap {
     pub units: Option<UnitsSection>,
     pub triggers: Option<TriggersSection>,
     pub sacks: Option<SacksSection>,
-    pub effects: Option<EffectsSection>
+    pub effects: Option<EffectsSection>,
+    pub unknown_sections: Vec<RawSection>,
+    pub warnings: Vec<AlmWarning>
 }
 impl AlmMap {
+    // Only the general section is mandatory; anything else that can't be read
+    // ends up in unknown_sections and warnings instead of failing the whole map
     pub fn read<TStream: Read + AsRef<[u8]>>(stream: &mut Cursor<TStream>) -> std::io::Result<Self> {
-        let alm_header = AlmHeader::deserialize(stream, Endianness::LittleEndian)?;
-        let general_map_info_header = SectionHeader::deserialize(stream, Endianness::LittleEndian)?;
-        assert_eq!(general_map_info_header.section_kind, SectionKind::General);
-        let mut position_before_section_read = stream.position();
-        let general_info = GeneralMapInfoSection::deserialize(stream, Endianness::LittleEndian)?;
-        stream.seek(SeekFrom::Start(position_before_section_read + general_map_info_header.data_size as u64))?;
-        let (
-            mut tiles,
-            mut height_map,
-            mut map_objects,
-            mut structures,
-            mut fractions,
-            mut units,
-            mut triggers,
-            mut sacks,
-            mut effects
-        ) = (
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None
-        );
-
-        for _ in 1..alm_header.section_count {
-            let next_section_header = SectionHeader::deserialize(stream, Endianness::LittleEndian)?;
-            position_before_section_read = stream.position();
-            match next_section_header.section_kind {
-                SectionKind::Tiles => {
-                    println!("read tiles section");
-                    tiles = Some(TilesSection::read(stream, &general_info)?);
-                },
-                SectionKind::HeightMap => {
-                    println!("read heightmap section");
-                    height_map = Some(HeightMapSection::read(stream, &general_info)?);
-                },
-                SectionKind::MapObjects => {
-                    println!("read map objects section");
-                    map_objects = Some(MapObjectsSection::read(stream, &general_info)?);
-                },
-                SectionKind::Structures => {
-                    println!("read structures section");
-                    structures = Some(StructuresSection::read(stream, &general_info)?);
-                },
-                SectionKind::Fractions => {
-                    println!("read fractions section");
-                    fractions = Some(FractionsSection::read(stream, &general_info)?);
-                },
-                SectionKind::Units => {
-                    println!("read units section");
-                    units = Some(UnitsSection::read(stream, &general_info)?);
-                },
-                SectionKind::Triggers => {
-                    println!("read triggers section");
-                    triggers = Some(TriggersSection::read_from_stream(stream, Endianness::LittleEndian)?);
+        let header = AlmHeader::deserialize(stream, Endianness::LittleEndian)?;
+        let mut warnings = Vec::new();
+        let raw_sections = read_raw_sections(stream, header.section_count, &mut warnings)?;
+
+        let general_position = raw_sections
+            .iter()
+            .position(|section| section.header.kind() == Some(SectionKind::General))
+            .ok_or_else(|| std::io::Error::from(ErrorKind::InvalidInput))?;
+        if general_position != 0 {
+            warnings.push(AlmWarning::GeneralSectionNotFirst { position: general_position });
+        }
+        let general_info = GeneralMapInfoSection::deserialize(
+            &mut Cursor::new(&raw_sections[general_position].data[..]),
+            Endianness::LittleEndian
+        )?;
+
+        let mut map = Self {
+            header,
+            section_headers: raw_sections.iter().map(|section| section.header.clone()).collect(),
+            general_info,
+            tiles: None,
+            height_map: None,
+            map_objects: None,
+            structures: None,
+            fractions: None,
+            units: None,
+            triggers: None,
+            sacks: None,
+            effects: None,
+            unknown_sections: Vec::new(),
+            warnings
+        };
+        let mut loaded_kinds = Vec::new();
+        for (position, section) in raw_sections.into_iter().enumerate() {
+            if position == general_position {
+                continue;
+            }
+            match section.header.kind() {
+                None => {
+                    map.warnings.push(AlmWarning::UnknownSection { position, kind: section.kind });
+                    map.unknown_sections.push(section);
                 },
-                SectionKind::Sacks => {
-                    println!("read sacks section");
-                    sacks = Some(SacksSection::read(stream, &general_info)?);
+                Some(SectionKind::General) => {
+                    map.warnings.push(AlmWarning::DuplicateSection { position, kind: SectionKind::General });
+                    map.unknown_sections.push(section);
                 },
-                SectionKind::Effects => {
-                    println!("read effects section");
-                    effects = Some(EffectsSection::read_from_stream(stream, Endianness::LittleEndian)?);
+                // only the first readable copy is loaded, the others are kept raw so write() gives them back
+                Some(kind) if loaded_kinds.contains(&kind) => {
+                    map.warnings.push(AlmWarning::DuplicateSection { position, kind });
+                    map.unknown_sections.push(section);
                 },
-                _ => unreachable!()
+                Some(kind) => {
+                    if let Err(error) = map.load_section(kind, &section.data) {
+                        map.warnings.push(AlmWarning::UnreadableSection { position, kind, error: error.to_string() });
+                        map.unknown_sections.push(section);
+                    } else {
+                        loaded_kinds.push(kind);
+                    }
+                }
             }
-            stream.seek(SeekFrom::Start(position_before_section_read + next_section_header.data_size as u64))?;
         }
-        Ok(Self {
-            general_info,
-            tiles,
-            height_map,
-            map_objects,
-            structures,
-            fractions,
-            units,
-            triggers,
-            sacks,
-            effects
-        })
+        Ok(map)
     }
+
+    // Sections go in the order they were read in, known ones first otherwise.
+    // Unknown sections are written back untouched
+    pub fn write<TStream: Write>(&self, stream: &mut TStream) -> std::io::Result<()> {
+        let mut sections: Vec<(SectionHeader, Vec<u8>)> = Vec::new();
+        let mut written_kinds = Vec::new();
+        let mut unknown_sections = self.unknown_sections.iter().peekable();
+        for header in self.section_headers.iter() {
+            if let Some(kind) = header.kind() {
+                if !written_kinds.contains(&kind) {
+                    if let Some(data) = self.section_data(kind)? {
+                        sections.push((header.clone(), data));
+                        written_kinds.push(kind);
+                        continue;
+                    }
+                }
+            }
+            if let Some(unknown) = unknown_sections.next_if(|unknown| unknown.kind == header.section_kind) {
+                sections.push((unknown.header.clone(), unknown.data.clone()));
+            }
+        }
+        for &kind in SectionKind::ALL.iter() {
+            if written_kinds.contains(&kind) {
+                continue;
+            }
+            if let Some(data) = self.section_data(kind)? {
+                let header = SectionHeader {
+                    some_id: DEFAULT_SECTION_ID,
+                    header_size: SECTION_HEADER_SIZE,
+                    section_kind: kind as u32,
+                    ..Default::default()
+                };
+                sections.push((header, data));
+            }
+        }
+        for unknown in unknown_sections {
+            sections.push((unknown.header.clone(), unknown.data.clone()));
+        }
+
+        let mut header = self.header.clone();
+        header.section_count = sections.len() as u32;
+        header.serialize(stream, Endianness::LittleEndian)?;
+        for (mut section_header, data) in sections {
+            section_header.data_size = data.len() as u32;
+            section_header.serialize(stream, Endianness::LittleEndian)?;
+            stream.write_all(&data)?;
+        }
+        Ok(())
+    }
+
+    fn section_data(&self, kind: SectionKind) -> std::io::Result<Option<Vec<u8>>> {
+        let mut data = Vec::new();
+        let stream = &mut data;
+        match kind {
+            SectionKind::General => self.general_info.clone().serialize(stream, Endianness::LittleEndian)?,
+            SectionKind::Tiles => match &self.tiles {
+                None => return Ok(None),
+                Some(section) => section.write(stream)?
+            },
+            SectionKind::HeightMap => match &self.height_map {
+                None => return Ok(None),
+                Some(section) => section.write(stream)?
+            },
+            SectionKind::MapObjects => match &self.map_objects {
+                None => return Ok(None),
+                Some(section) => section.write(stream)?
+            },
+            SectionKind::Structures => match &self.structures {
+                None => return Ok(None),
+                Some(section) => section.write(stream)?
+            },
+            SectionKind::Fractions => match &self.fractions {
+                None => return Ok(None),
+                Some(section) => section.write(stream)?
+            },
+            SectionKind::Units => match &self.units {
+                None => return Ok(None),
+                Some(section) => section.write(stream)?
+            },
+            SectionKind::Triggers => match &self.triggers {
+                None => return Ok(None),
+                Some(section) => section.write_to_stream(stream, Endianness::LittleEndian)?
+            },
+            SectionKind::Sacks => match &self.sacks {
+                None => return Ok(None),
+                Some(section) => section.write(stream)?
+            },
+            SectionKind::Effects => match &self.effects {
+                None => return Ok(None),
+                Some(section) => section.write_to_stream(stream, Endianness::LittleEndian)?
+            }
+        }
+        Ok(Some(data))
+    }
+
+    fn load_section(&mut self, kind: SectionKind, data: &[u8]) -> std::io::Result<()> {
+        let stream = &mut Cursor::new(data);
+        let general_info = &self.general_info;
+        match kind {
+            SectionKind::General => {},
+            SectionKind::Tiles => self.tiles = Some(TilesSection::read(stream, general_info)?),
+            SectionKind::HeightMap => self.height_map = Some(HeightMapSection::read(stream, general_info)?),
+            SectionKind::MapObjects => self.map_objects = Some(MapObjectsSection::read(stream, general_info)?),
+            SectionKind::Structures => self.structures = Some(StructuresSection::read(stream, general_info)?),
+            SectionKind::Fractions => self.fractions = Some(FractionsSection::read(stream, general_info)?),
+            SectionKind::Units => self.units = Some(UnitsSection::read(stream, general_info)?),
+            SectionKind::Triggers => {
+                self.triggers = Some(TriggersSection::read_from_stream(stream, Endianness::LittleEndian)?)
+            },
+            SectionKind::Sacks => self.sacks = Some(SacksSection::read(stream, general_info)?),
+            SectionKind::Effects => {
+                self.effects = Some(EffectsSection::read_from_stream(stream, Endianness::LittleEndian)?)
+            }
+        }
+        Ok(())
+    }
+}
+
+// A section kept byte for byte, either of a kind this crate doesn't know or one that failed to parse
+#[derive(Clone, Debug)]
+pub struct RawSection {
+    pub kind: u32,
+    pub header: SectionHeader,
+    pub data: Vec<u8>
 }
 
-#[derive(Clone, Default)]
-struct AlmHeader {
-    signature: u32,
-    header_size: u32,
-    mysterious_size: u32,
+#[derive(Clone, Debug, PartialEq)]
+pub enum AlmWarning {
+    // positions are indexes into AlmMap::section_headers
+    TruncatedSection { position: usize, declared_size: u32, available_size: u32 },
+    TruncatedSectionTable { declared_count: u32, read_count: u32 },
+    GeneralSectionNotFirst { position: usize },
+    UnknownSection { position: usize, kind: u32 },
+    DuplicateSection { position: usize, kind: SectionKind },
+    UnreadableSection { position: usize, kind: SectionKind, error: String }
+}
+
+fn read_raw_sections<TStream: Read>(
+    stream: &mut TStream,
     section_count: u32,
-    random_seed: u32
+    warnings: &mut Vec<AlmWarning>
+) -> std::io::Result<Vec<RawSection>> {
+    let mut sections = Vec::with_capacity(section_count.min(0x100) as usize);
+    for position in 0..section_count as usize {
+        let header = match SectionHeader::deserialize(stream, Endianness::LittleEndian) {
+            Ok(header) => header,
+            Err(error) if error.kind() == ErrorKind::UnexpectedEof => {
+                warnings.push(AlmWarning::TruncatedSectionTable {
+                    declared_count: section_count,
+                    read_count: position as u32
+                });
+                break;
+            },
+            Err(error) => return Err(error)
+        };
+        let mut data = Vec::new();
+        stream.by_ref().take(header.data_size as u64).read_to_end(&mut data)?;
+        if data.len() < header.data_size as usize {
+            warnings.push(AlmWarning::TruncatedSection {
+                position,
+                declared_size: header.data_size,
+                available_size: data.len() as u32
+            });
+        }
+        sections.push(RawSection { kind: header.section_kind, header, data });
+    }
+    Ok(sections)
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct AlmHeader {
+    pub signature: u32,
+    pub header_size: u32,
+    pub mysterious_size: u32,
+    pub section_count: u32,
+    pub random_seed: u32
 }
 impl Reflectable for AlmHeader {
     fn reflect<TSerializationReflector: SerializationReflector>(
@@ -167,26 +365,117 @@ impl Reflectable for AlmHeader {
     }
 }
 
-#[derive(Clone, Default)]
-struct SectionHeader {
-    _some_id: u32,
-    header_size: u32,
-    data_size: u32,
-    section_kind: SectionKind,
-    random_seed: u32
+// section_kind stays raw so that kinds unknown to SectionKind survive a read
+#[derive(Clone, Default, Debug)]
+pub struct SectionHeader {
+    pub some_id: u32,
+    pub header_size: u32,
+    pub data_size: u32,
+    pub section_kind: u32,
+    pub random_seed: u32
+}
+impl SectionHeader {
+    pub fn kind(&self) -> Option<SectionKind> {
+        SectionKind::try_from(self.section_kind).ok()
+    }
 }
 impl Reflectable for SectionHeader {
     fn reflect<TSerializationReflector: SerializationReflector>(
         &mut self, reflector: &mut TSerializationReflector
     ) -> std::io::Result<()> {
-        reflector.reflect_u32(&mut self._some_id)?;
+        reflector.reflect_u32(&mut self.some_id)?;
         reflector.reflect_u32(&mut self.header_size)?;
         reflector.reflect_u32(&mut self.data_size)?;
-        let mut sec_kind = self.section_kind as u32;
-        reflector.reflect_u32(&mut sec_kind)?;
-        self.section_kind =
-            SectionKind::try_from(sec_kind)
-            .map_err(|_| std::io::Error::from(ErrorKind::InvalidInput))?;
+        reflector.reflect_u32(&mut self.section_kind)?;
         reflector.reflect_u32(&mut self.random_seed)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alm::*;
+    use std::io::Cursor;
+
+    fn write_map(map: &AlmMap) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        map.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    // Offset and total size (header included) of every section in a written map
+    fn section_spans(bytes: &[u8]) -> Vec<(u32, usize, usize)> {
+        let word = |offset: usize| u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+        let mut spans = Vec::new();
+        let mut offset = ALM_HEADER_SIZE as usize;
+        while offset < bytes.len() {
+            let size = SECTION_HEADER_SIZE as usize + word(offset + 8) as usize;
+            spans.push((word(offset + 12), offset, size));
+            offset += size;
+        }
+        spans
+    }
+
+    #[test]
+    fn test_duplicate_section_survives_write() {
+        let mut builder = AlmMapBuilder::new(4, 4);
+        builder.set_height(1, 1, 10);
+        let bytes = write_map(&builder.build());
+
+        // a second height map section with other heights right after the first one
+        let (_, offset, size) = *section_spans(&bytes)
+            .iter()
+            .find(|(kind, _, _)| *kind == SectionKind::HeightMap as u32)
+            .unwrap();
+        let mut duplicate = bytes[offset..offset + size].to_vec();
+        for height in duplicate[SECTION_HEADER_SIZE as usize..].iter_mut() {
+            *height = 42;
+        }
+        let mut input = bytes[..offset + size].to_vec();
+        input.extend_from_slice(&duplicate);
+        input.extend_from_slice(&bytes[offset + size..]);
+        let section_count = u32::from_le_bytes([input[12], input[13], input[14], input[15]]) + 1;
+        input[12..16].copy_from_slice(&section_count.to_le_bytes());
+
+        let map = AlmMap::read(&mut Cursor::new(&input[..])).unwrap();
+        assert_eq!(map.height_map.as_ref().unwrap().heights[5], 10);
+        assert_eq!(map.unknown_sections.len(), 1);
+        assert!(map.warnings.iter().any(|warning| matches!(
+            warning,
+            AlmWarning::DuplicateSection { kind: SectionKind::HeightMap, .. }
+        )));
+        assert_eq!(write_map(&map), input);
+    }
+
+    #[test]
+    fn test_trigger_entry_written_back_as_read() {
+        // a name with box drawing that fills the whole field
+        let mut bytes: Vec<u8> = (0..0x80).map(|idx| [0xC9, 0xCD, 0xBB, b'A', 0xA0][idx % 5]).collect();
+        let words = [1, 0, 2, 3, 0, 0, 4, 0, 0, 0, 2, 1, 7, 1];
+        for word in words.iter() {
+            bytes.extend_from_slice(&u32::to_le_bytes(*word));
+        }
+        let trigger = TriggerEntry::read_from_stream(&mut Cursor::new(&bytes[..]), Endianness::LittleEndian).unwrap();
+        assert!(trigger.name.starts_with("╔═╗Aа"));
+        // the first pair has an operator stored but lacks a check
+        assert!(trigger.check_01_operator.is_none());
+        assert!(trigger.check_23_operator.is_some());
+        let mut written = Vec::new();
+        trigger.write_to_stream(&mut written, Endianness::LittleEndian).unwrap();
+        assert_eq!(written, bytes);
+    }
+
+    #[test]
+    fn test_unknown_values_are_rejected() {
+        use num_enum::TryFromPrimitive;
+        assert!(SectionKind::try_from_primitive(42).is_err());
+        assert!(trigger_enums::ArgumentType::try_from_primitive(42).is_err());
+        assert!(trigger_enums::CheckOperator::try_from_primitive(42).is_err());
+        assert!(trigger_enums::GeneralCheckType::try_from_primitive(0x1000).is_err());
+        assert!(trigger_enums::GeneralInstanceType::try_from_primitive(0x1000).is_err());
+        // right past the last general kind
+        assert!(trigger_enums::CheckType::try_from_primitive(0x19).is_err());
+        assert!(trigger_enums::InstanceType::try_from_primitive(0x24).is_err());
+        assert!(trigger_enums::CheckType::try_from_primitive(0x18).is_ok());
+        assert!(trigger_enums::InstanceType::try_from_primitive(0x23).is_ok());
+    }
+}
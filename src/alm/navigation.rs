@@ -0,0 +1,365 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use crate::data_bin::UnitRecord;
+use crate::game_database::{GameDatabase, structure_footprint};
+use super::*;
+
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+const NEIGHBOURS: [(isize, isize); 8] = [(-1, 0), (1, 0), (0, -1), (0, 1), (-1, -1), (1, -1), (-1, 1), (1, 1)];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MovementType {
+    Walking,
+    Flying,
+    Water
+}
+impl MovementType {
+    // UnitRecord::movement_type: 2 flies, 3 swims, anything else walks
+    pub fn from_record(movement_type: i32) -> Self {
+        match movement_type {
+            2 => MovementType::Flying,
+            3 => MovementType::Water,
+            _ => MovementType::Walking
+        }
+    }
+}
+
+// Token size and movement type of a unit as data.bin describes it
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mover {
+    pub size: usize,
+    pub movement: MovementType
+}
+impl Mover {
+    pub fn from_record(record: &UnitRecord) -> Self {
+        Self {
+            size: record.token_size.max(1) as usize,
+            movement: MovementType::from_record(record.movement_type)
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct NavigationOptions {
+    pub max_height_step: u8, // walkers can't climb more than that between adjacent tiles
+    pub objects_block: bool
+}
+impl Default for NavigationOptions {
+    fn default() -> Self {
+        Self {
+            max_height_step: 24,
+            objects_block: true
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Cell {
+    terrain: TerrainKind,
+    passable: bool,
+    blocked: bool, // by an object or a structure
+    height: u8
+}
+
+pub struct NavigationGrid {
+    pub width: usize,
+    pub height: usize,
+    cells: Vec<Cell>,
+    max_height_step: u8
+}
+impl NavigationGrid {
+    // Without a database only bridges are known, other structures don't block
+    pub fn build(map: &AlmMap, database: Option<&GameDatabase>, options: &NavigationOptions) -> Self {
+        let (width, height) = (map.general_info.width as usize, map.general_info.height as usize);
+        let mut cells: Vec<Cell> = (0..width * height)
+            .map(|idx| {
+                let tile = map.tiles.as_ref().and_then(|tiles| tiles.tiles.get(idx).copied());
+                Cell {
+                    terrain: tile.map_or(TerrainKind::Grass, TileEntry::get_terrain_kind),
+                    passable: tile.map_or(true, TileEntry::is_passable),
+                    blocked: false,
+                    height: map.height_map.as_ref().and_then(|h| h.heights.get(idx).copied()).unwrap_or(0)
+                }
+            })
+            .collect();
+
+        if options.objects_block {
+            if let Some(map_objects) = &map.map_objects {
+                for (x, y, _) in map_objects.iter_objects() {
+                    if x < width && y < height {
+                        cells[y * width + x].blocked = true;
+                    }
+                }
+            }
+        }
+
+        if let Some(structures) = &map.structures {
+            for structure in structures.structures.iter() {
+                let (x, y) = structure.tile_position();
+                let (x, y) = (x as usize, y as usize);
+                let (footprint_width, footprint_height) = structure_footprint(structure, database);
+                let area = (y..(y + footprint_height as usize).min(height))
+                    .flat_map(|cell_y| (x..(x + footprint_width as usize).min(width)).map(move |cell_x| (cell_x, cell_y)));
+                if structure.is_bridge() {
+                    // bridges make water walkable
+                    for (cell_x, cell_y) in area {
+                        let cell = &mut cells[cell_y * width + cell_x];
+                        cell.terrain = TerrainKind::Grass;
+                        cell.passable = true;
+                    }
+                    continue;
+                }
+                let record = database.and_then(|database| database.structure(structure));
+                if record.is_some_and(|info| info.details.passability == 0) {
+                    for (cell_x, cell_y) in area {
+                        cells[cell_y * width + cell_x].blocked = true;
+                    }
+                }
+            }
+        }
+
+        Self {
+            width,
+            height,
+            cells,
+            max_height_step: options.max_height_step
+        }
+    }
+
+    pub fn set_blocked(&mut self, x: usize, y: usize, blocked: bool) {
+        if x < self.width && y < self.height {
+            self.cells[y * self.width + x].blocked = blocked;
+        }
+    }
+
+    pub fn is_walkable(&self, x: usize, y: usize, movement: MovementType) -> bool {
+        if x >= self.width || y >= self.height {
+            return false;
+        }
+        let cell = &self.cells[y * self.width + x];
+        match movement {
+            MovementType::Flying => true,
+            MovementType::Water => cell.terrain == TerrainKind::Water && !cell.blocked,
+            MovementType::Walking => cell.passable && !cell.blocked && cell.terrain != TerrainKind::Water
+        }
+    }
+
+    // A mover occupies size x size tiles with (x, y) being the top left one
+    pub fn fits(&self, x: usize, y: usize, mover: Mover) -> bool {
+        (y..y + mover.size).all(|cell_y| (x..x + mover.size).all(|cell_x| self.is_walkable(cell_x, cell_y, mover.movement)))
+    }
+
+    // Path from start to goal inclusive; diagonal moves never cut corners
+    pub fn find_path(&self, start: (usize, usize), goal: (usize, usize), mover: Mover) -> Option<Vec<(usize, usize)>> {
+        if !self.fits(start.0, start.1, mover) || !self.fits(goal.0, goal.1, mover) {
+            return None;
+        }
+        let index = |(x, y): (usize, usize)| y * self.width + x;
+        let heuristic = |(x, y): (usize, usize)| {
+            let dx = (x as isize - goal.0 as isize).unsigned_abs() as u32;
+            let dy = (y as isize - goal.1 as isize).unsigned_abs() as u32;
+            STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy)
+        };
+        let mut costs = vec![u32::MAX; self.cells.len()];
+        let mut came_from = vec![usize::MAX; self.cells.len()];
+        let mut open = BinaryHeap::new();
+        costs[index(start)] = 0;
+        open.push(Reverse((heuristic(start), 0u32, index(start))));
+
+        while let Some(Reverse((_, cost, current))) = open.pop() {
+            if current == index(goal) {
+                let mut path = vec![goal];
+                let mut step = current;
+                while came_from[step] != usize::MAX {
+                    step = came_from[step];
+                    path.push((step % self.width, step / self.width));
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if cost > costs[current] {
+                continue;
+            }
+            let position = (current % self.width, current / self.width);
+            for (next, step_cost) in self.steps(position, mover) {
+                let next_cost = cost + step_cost;
+                if next_cost < costs[index(next)] {
+                    costs[index(next)] = next_cost;
+                    came_from[index(next)] = current;
+                    open.push(Reverse((next_cost + heuristic(next), next_cost, index(next))));
+                }
+            }
+        }
+        None
+    }
+
+    // Every position a mover fits in gets the id of the region it can reach
+    pub fn regions(&self, mover: Mover) -> RegionMap {
+        let mut labels = vec![None; self.cells.len()];
+        let mut region_count = 0;
+        for start in 0..self.cells.len() {
+            let position = (start % self.width, start / self.width);
+            if labels[start].is_some() || !self.fits(position.0, position.1, mover) {
+                continue;
+            }
+            labels[start] = Some(region_count);
+            let mut queue = VecDeque::new();
+            queue.push_back(position);
+            while let Some(current) = queue.pop_front() {
+                for (next, _) in self.steps(current, mover) {
+                    let next_index = next.1 * self.width + next.0;
+                    if labels[next_index].is_none() {
+                        labels[next_index] = Some(region_count);
+                        queue.push_back(next);
+                    }
+                }
+            }
+            region_count += 1;
+        }
+        RegionMap {
+            width: self.width,
+            height: self.height,
+            labels,
+            region_count
+        }
+    }
+
+    fn steps(&self, (x, y): (usize, usize), mover: Mover) -> Vec<((usize, usize), u32)> {
+        let mut steps = Vec::with_capacity(NEIGHBOURS.len());
+        for &(dx, dy) in NEIGHBOURS.iter() {
+            let (next_x, next_y) = (x as isize + dx, y as isize + dy);
+            if next_x < 0 || next_y < 0 {
+                continue;
+            }
+            let next = (next_x as usize, next_y as usize);
+            if !self.fits(next.0, next.1, mover) || !self.can_climb((x, y), next, mover) {
+                continue;
+            }
+            if dx != 0 && dy != 0 {
+                if !self.fits(next.0, y, mover) || !self.fits(x, next.1, mover) {
+                    continue;
+                }
+                steps.push((next, DIAGONAL_COST));
+            } else {
+                steps.push((next, STRAIGHT_COST));
+            }
+        }
+        steps
+    }
+
+    // Every tile of the footprint is compared with the one it moves onto
+    fn can_climb(&self, from: (usize, usize), to: (usize, usize), mover: Mover) -> bool {
+        if mover.movement != MovementType::Walking {
+            return true;
+        }
+        let height = |x: usize, y: usize| self.cells[y * self.width + x].height;
+        (0..mover.size).all(|dy| {
+            (0..mover.size).all(|dx| {
+                height(from.0 + dx, from.1 + dy).abs_diff(height(to.0 + dx, to.1 + dy)) <= self.max_height_step
+            })
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RegionMap {
+    pub width: usize,
+    pub height: usize,
+    pub labels: Vec<Option<u32>>, // None where the mover doesn't fit
+    pub region_count: u32
+}
+impl RegionMap {
+    pub fn region_of(&self, x: usize, y: usize) -> Option<u32> {
+        if x < self.width && y < self.height {
+            self.labels[y * self.width + x]
+        } else {
+            None
+        }
+    }
+
+    pub fn are_connected(&self, a: (usize, usize), b: (usize, usize)) -> bool {
+        match (self.region_of(a.0, a.1), self.region_of(b.0, b.1)) {
+            (Some(region_a), Some(region_b)) => region_a == region_b,
+            _ => false
+        }
+    }
+
+    pub fn region_sizes(&self) -> Vec<usize> {
+        let mut sizes = vec![0; self.region_count as usize];
+        for label in self.labels.iter().flatten() {
+            sizes[*label as usize] += 1;
+        }
+        sizes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alm::navigation::*;
+
+    const WALKER: Mover = Mover { size: 1, movement: MovementType::Walking };
+
+    fn grid(map: &AlmMap) -> NavigationGrid {
+        NavigationGrid::build(map, None, &NavigationOptions::default())
+    }
+
+    #[test]
+    fn test_path_goes_around_blocked_cells() {
+        let mut grid = grid(&AlmMapBuilder::new(5, 5).build());
+        for y in 0..4 {
+            grid.set_blocked(2, y, true);
+        }
+        let path = grid.find_path((0, 0), (4, 0), WALKER).unwrap();
+        assert_eq!((path[0], path[path.len() - 1]), ((0, 0), (4, 0)));
+        assert!(path.contains(&(2, 4)));
+        assert!(path.iter().all(|&(x, y)| x != 2 || y == 4));
+        // single steps, no corner of the wall is cut
+        for step in path.windows(2) {
+            let ((x1, y1), (x2, y2)) = (step[0], step[1]);
+            assert!(x1.abs_diff(x2) <= 1 && y1.abs_diff(y2) <= 1);
+            assert!(grid.is_walkable(x1, y2, WALKER.movement) && grid.is_walkable(x2, y1, WALKER.movement));
+        }
+
+        grid.set_blocked(2, 4, true);
+        assert_eq!(grid.find_path((0, 0), (4, 0), WALKER), None);
+        assert_eq!(grid.find_path((0, 0), (2, 2), WALKER), None);
+    }
+
+    #[test]
+    fn test_climbing_checks_the_whole_footprint() {
+        let mut builder = AlmMapBuilder::new(6, 3);
+        builder.set_height(3, 1, 100);
+        let grid = grid(&builder.build());
+        // a single tile walker steps around the peak, a 2x2 one can't avoid covering it
+        assert!(grid.find_path((0, 0), (4, 0), WALKER).is_some());
+        assert_eq!(grid.find_path((0, 0), (4, 0), Mover { size: 2, ..WALKER }), None);
+    }
+
+    // Water runs down column 3, the bridge crosses it on row 2
+    fn river(bridge: bool) -> AlmMap {
+        let mut builder = AlmMapBuilder::new(7, 4);
+        builder.fill_tiles(3, 0, 1, 4, TileEntry::new(3, 15, 0, false));
+        if bridge {
+            builder.add_bridge(0x300, 0x200, 1, 1, 0);
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn test_water_splits_regions_and_bridges_join_them() {
+        let regions = grid(&river(false)).regions(WALKER);
+        assert_eq!(regions.region_count, 2);
+        assert_eq!(regions.region_sizes(), vec![12, 12]);
+        assert!(!regions.are_connected((0, 0), (6, 3)));
+        assert_eq!(regions.region_of(3, 1), None);
+
+        let swimmer = Mover { size: 1, movement: MovementType::Water };
+        assert_eq!(grid(&river(false)).regions(swimmer).region_sizes(), vec![4]);
+
+        let regions = grid(&river(true)).regions(WALKER);
+        assert_eq!(regions.region_count, 1);
+        assert!(regions.are_connected((0, 0), (6, 3)));
+        assert_eq!(regions.region_of(3, 2), Some(0));
+    }
+}
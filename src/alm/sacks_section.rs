@@ -1,5 +1,5 @@
 use bin_serialization_rs::{Reflectable, SerializationReflector, Endianness};
-use std::io::{Result, Read};
+use std::io::{Result, Read, Write};
 use crate::shared_types::{U32Wrapper};
 
 #[derive(Clone, Default, Debug)]
@@ -45,6 +45,18 @@ impl SackEntry {
             items
         })
     }
+
+    pub fn write_to_stream<TStream: Write>(&self, stream: &mut TStream, endianness: Endianness) -> Result<()> {
+        U32Wrapper(self.items.len() as u32).serialize(stream, endianness)?;
+        U32Wrapper(self.unit_id).serialize(stream, endianness)?;
+        U32Wrapper(self.x_coord).serialize(stream, endianness)?;
+        U32Wrapper(self.y_coord).serialize(stream, endianness)?;
+        U32Wrapper(self.money).serialize(stream, endianness)?;
+        for item in self.items.iter() {
+            item.clone().serialize(stream, endianness)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -66,4 +78,11 @@ impl SacksSection {
             sacks
         })
     }
+
+    pub(crate) fn write<TStream: Write>(&self, stream: &mut TStream) -> Result<()> {
+        for sack in self.sacks.iter() {
+            sack.write_to_stream(stream, Endianness::LittleEndian)?;
+        }
+        Ok(())
+    }
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-use std::io::{Result, Read};
+use std::io::{Result, Read, Write};
 use bin_serialization_rs::{Reflectable, SerializationReflector, Endianness};
 
 #[derive(Default, Clone, Debug)]
@@ -25,6 +25,24 @@ pub struct StructureEntry {
     pub id: u16,
     pub bridge_info: BridgeInfo
 }
+impl StructureEntry {
+    // Same 8.8 fixed point as unit coordinates
+    pub fn tile_position(&self) -> (u32, u32) {
+        (self.x_coord >> 8, self.y_coord >> 8)
+    }
+    pub fn is_bridge(&self) -> bool {
+        self.type_id == 33
+    }
+    // Tiles covered as far as the map tells: bridges store their span, anything else
+    // takes a single tile since its size is in data.bin. Never smaller than a tile
+    pub fn footprint(&self) -> (u32, u32) {
+        if self.is_bridge() {
+            (self.bridge_info.width.max(1), self.bridge_info.height.max(1))
+        } else {
+            (1, 1)
+        }
+    }
+}
 impl Reflectable for StructureEntry {
     fn reflect<TSerializationReflector: SerializationReflector>(
         &mut self, reflector: &mut TSerializationReflector
@@ -61,4 +79,11 @@ impl StructuresSection {
             structures
         })
     }
+
+    pub(crate) fn write<TStream: Write>(&self, stream: &mut TStream) -> Result<()> {
+        for structure in self.structures.iter() {
+            structure.clone().serialize(stream, Endianness::LittleEndian)?;
+        }
+        Ok(())
+    }
 }
\ No newline at end of file
@@ -0,0 +1,194 @@
+use super::*;
+
+// Tile corners, as bits of the corner mask
+pub const CORNER_TOP_LEFT: u8 = 0x1;
+pub const CORNER_TOP_RIGHT: u8 = 0x2;
+pub const CORNER_BOTTOM_RIGHT: u8 = 0x4;
+pub const CORNER_BOTTOM_LEFT: u8 = 0x8;
+pub const ALL_CORNERS: u8 = 0xF;
+
+// Picks the tile of a cell from its terrain and the corners that terrain covers;
+// the others show a terrain lower in the overlay order underneath
+pub trait TerrainTileLayout {
+    fn tile(&self, terrain: TerrainKind, corner_mask: u8, x: usize, y: usize) -> TileEntry;
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct TerrainBrush {
+    pub terrain: TerrainKind,
+    pub radius: usize // 0 paints a single cell
+}
+
+impl AlmMap {
+    // Cells within the brush circle get its terrain, then they and their neighbours are retiled.
+    // Does nothing on a map without tiles
+    pub fn paint_terrain<TLayout: TerrainTileLayout>(
+        &mut self,
+        x: usize,
+        y: usize,
+        brush: TerrainBrush,
+        layout: &TLayout
+    ) {
+        let width = self.general_info.width as usize;
+        if let Some(tiles) = &mut self.tiles {
+            paint_terrain(&mut tiles.tiles, width, x, y, brush, layout);
+        }
+    }
+
+    pub fn retile_terrain<TLayout: TerrainTileLayout>(&mut self, layout: &TLayout) {
+        let width = self.general_info.width as usize;
+        if let Some(tiles) = &mut self.tiles {
+            let height = tiles.tiles.len().checked_div(width).unwrap_or(0);
+            retile(&mut tiles.tiles, width, 0, 0, width, height, layout);
+        }
+    }
+}
+
+pub(crate) fn paint_terrain<TLayout: TerrainTileLayout>(
+    tiles: &mut [TileEntry],
+    width: usize,
+    x: usize,
+    y: usize,
+    brush: TerrainBrush,
+    layout: &TLayout
+) {
+    if width == 0 {
+        return;
+    }
+    let height = tiles.len() / width;
+    if x >= width || y >= height {
+        return;
+    }
+    let (left, top) = (x.saturating_sub(brush.radius), y.saturating_sub(brush.radius));
+    let (right, bottom) = ((x + brush.radius).min(width - 1), (y + brush.radius).min(height - 1));
+    let radius_squared = brush.radius * brush.radius;
+    for cell_y in top..=bottom {
+        for cell_x in left..=right {
+            let (dx, dy) = (cell_x.abs_diff(x), cell_y.abs_diff(y));
+            if dx * dx + dy * dy <= radius_squared {
+                // the real tile comes from retile, this only records the terrain
+                tiles[cell_y * width + cell_x] = layout.tile(brush.terrain, ALL_CORNERS, cell_x, cell_y);
+            }
+        }
+    }
+    let (left, top) = (left.saturating_sub(1), top.saturating_sub(1));
+    let (right, bottom) = ((right + 1).min(width - 1), (bottom + 1).min(height - 1));
+    retile(tiles, width, left, top, right - left + 1, bottom - top + 1, layout);
+}
+
+// A corner takes the lowest terrain of the cells around it, so higher terrains
+// are drawn over lower ones along their borders
+fn retile<TLayout: TerrainTileLayout>(
+    tiles: &mut [TileEntry],
+    width: usize,
+    left: usize,
+    top: usize,
+    rect_width: usize,
+    rect_height: usize,
+    layout: &TLayout
+) {
+    let height = tiles.len().checked_div(width).unwrap_or(0);
+    let terrains: Vec<TerrainKind> = tiles.iter().map(|tile| tile.get_terrain_kind()).collect();
+    let terrain_at = |x: isize, y: isize| {
+        let inside = (0..width as isize).contains(&x) && (0..height as isize).contains(&y);
+        inside.then(|| terrains[y as usize * width + x as usize])
+    };
+    let corner = |x: isize, y: isize| {
+        [(x - 1, y - 1), (x, y - 1), (x - 1, y), (x, y)]
+            .iter()
+            .filter_map(|&(cell_x, cell_y)| terrain_at(cell_x, cell_y))
+            .min_by_key(|&terrain| overlay_order(terrain))
+    };
+    for y in top..(top + rect_height).min(height) {
+        for x in left..(left + rect_width).min(width) {
+            let terrain = terrains[y * width + x];
+            let (cell_x, cell_y) = (x as isize, y as isize);
+            let corners = [
+                (CORNER_TOP_LEFT, corner(cell_x, cell_y)),
+                (CORNER_TOP_RIGHT, corner(cell_x + 1, cell_y)),
+                (CORNER_BOTTOM_RIGHT, corner(cell_x + 1, cell_y + 1)),
+                (CORNER_BOTTOM_LEFT, corner(cell_x, cell_y + 1))
+            ];
+            let corner_mask = corners
+                .iter()
+                .filter(|(_, corner_terrain)| *corner_terrain == Some(terrain))
+                .fold(0, |mask, (bit, _)| mask | bit);
+            // cells with less than two walkable corners are mostly water or rock
+            let walkable_corners = corners
+                .iter()
+                .filter(|(_, corner_terrain)| corner_terrain.is_some_and(is_walkable))
+                .count();
+            let tile = layout.tile(terrain, corner_mask, x, y);
+            let passable = is_walkable(terrain) && walkable_corners >= 2;
+            tiles[y * width + x] = TileEntry::new(
+                tile.get_terrain_id(),
+                tile.get_tile_column_id(),
+                tile.get_tile_row_id(),
+                passable
+            );
+        }
+    }
+}
+
+fn overlay_order(terrain: TerrainKind) -> u8 {
+    match terrain {
+        TerrainKind::Water => 0,
+        TerrainKind::Sand => 1,
+        TerrainKind::Grass => 2,
+        TerrainKind::Rock => 3
+    }
+}
+
+fn is_walkable(terrain: TerrainKind) -> bool {
+    matches!(terrain, TerrainKind::Grass | TerrainKind::Sand)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alm::*;
+
+    // Column is the corner mask, terrain ids as in TileEntry
+    struct MaskLayout;
+    impl TerrainTileLayout for MaskLayout {
+        fn tile(&self, terrain: TerrainKind, corner_mask: u8, _x: usize, _y: usize) -> TileEntry {
+            let terrain_id = match terrain {
+                TerrainKind::Grass => 0,
+                TerrainKind::Sand => 1,
+                TerrainKind::Rock => 2,
+                TerrainKind::Water => 3
+            };
+            TileEntry::new(terrain_id, corner_mask, 0, false)
+        }
+    }
+
+    #[test]
+    fn test_single_cell_blends_into_its_neighbours() {
+        let mut builder = AlmMapBuilder::new(5, 5);
+        builder.paint_terrain(2, 2, TerrainBrush { terrain: TerrainKind::Sand, radius: 0 }, &MaskLayout);
+        let mut map = builder.build();
+        let columns = |map: &AlmMap| -> Vec<u8> {
+            map.tiles.as_ref().unwrap().tiles.iter().map(|tile| tile.get_tile_column_id()).collect()
+        };
+        // cells past the border of the brush keep the tiles they had
+        assert_eq!(columns(&map), vec![
+            0, 0, 0, 0, 0,
+            0, 0xB, 0x3, 0x7, 0,
+            0, 0x9, 15, 0x6, 0,
+            0, 0xD, 0xC, 0xE, 0,
+            0, 0, 0, 0, 0
+        ]);
+        map.retile_terrain(&MaskLayout);
+        assert_eq!(columns(&map), vec![
+            15, 15, 15, 15, 15,
+            15, 0xB, 0x3, 0x7, 15,
+            15, 0x9, 15, 0x6, 15,
+            15, 0xD, 0xC, 0xE, 15,
+            15, 15, 15, 15, 15
+        ]);
+        let tiles = &map.tiles.as_ref().unwrap().tiles;
+        assert!(matches!(tiles[2 * 5 + 2].get_terrain_kind(), TerrainKind::Sand));
+        assert!(tiles.iter().enumerate().filter(|&(idx, _)| idx != 2 * 5 + 2).all(|(_, tile)| {
+            matches!(tile.get_terrain_kind(), TerrainKind::Grass) && tile.is_passable()
+        }));
+    }
+}
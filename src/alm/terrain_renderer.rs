@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Result};
+use crate::images::palette_resolver::FileSource;
+use crate::images::rgba_image::RgbaImage;
+use crate::images::sprite::BmpSprite;
+use super::*;
+use super::minimap::{shade, terrain_color};
+
+pub const DEFAULT_TILE_SIZE: usize = 32;
+const DEFAULT_SUN_ANGLE: f32 = 45.0;
+
+pub trait TerrainTileset {
+    fn tile_size(&self) -> usize;
+    fn tile_image(&self, tile: TileEntry) -> Option<&RgbaImage>;
+}
+
+// The game keeps every tileset column in a vertical strip of tiles, one bmp per column
+pub struct StripTileset {
+    pub tile_size: usize,
+    pub strips: HashMap<(u8, u8), Vec<RgbaImage>> // (terrain id, column) -> tiles top to bottom
+}
+impl StripTileset {
+    pub fn load<TSource: FileSource, F: Fn(u8, u8) -> String>(
+        source: &TSource,
+        tile_size: usize,
+        strip_path: F
+    ) -> Result<Self> {
+        let mut strips = HashMap::new();
+        for terrain_id in 0..4u8 {
+            for column in 0..16u8 {
+                let bytes = match source.read_file(&strip_path(terrain_id, column)) {
+                    None => continue,
+                    Some(bytes) => bytes
+                };
+                let sprite = BmpSprite::read_from(&mut Cursor::new(&bytes[..]))?;
+                if let Some(strip) = RgbaImage::from_bmp_sprite(&sprite) {
+                    strips.insert((terrain_id, column), split_strip(&strip, tile_size));
+                }
+            }
+        }
+        Ok(Self { tile_size, strips })
+    }
+}
+impl TerrainTileset for StripTileset {
+    fn tile_size(&self) -> usize {
+        self.tile_size
+    }
+
+    fn tile_image(&self, tile: TileEntry) -> Option<&RgbaImage> {
+        self.strips
+            .get(&(tile.get_terrain_id(), tile.get_tile_column_id()))
+            .and_then(|strip| strip.get(tile.get_tile_row_id() as usize))
+    }
+}
+
+// "terrain/tile1-00.bmp" is the first grass column
+pub fn default_strip_path(terrain_id: u8, column: u8) -> String {
+    format!("terrain/tile{}-{:02}.bmp", terrain_id + 1, column)
+}
+
+fn split_strip(strip: &RgbaImage, tile_size: usize) -> Vec<RgbaImage> {
+    let mut tiles = Vec::new();
+    let mut top = 0;
+    while tile_size > 0 && top + tile_size <= strip.height {
+        let mut tile = RgbaImage::new(tile_size, tile_size);
+        for y in 0..tile_size {
+            for x in 0..tile_size.min(strip.width) {
+                tile.set_pixel(x, y, strip.get_pixel(x, top + y));
+            }
+        }
+        tiles.push(tile);
+        top += tile_size;
+    }
+    tiles
+}
+
+#[derive(Clone, Debug)]
+pub struct TerrainRenderOptions {
+    pub height_scale: f32, // screen pixels per height unit
+    pub lighting: bool
+}
+impl Default for TerrainRenderOptions {
+    fn default() -> Self {
+        Self {
+            height_scale: 0.25,
+            lighting: true
+        }
+    }
+}
+
+// Heights are taken per tile corner, the terrain is shifted up by them and
+// drawn back to front so that hills hide whatever lies behind them
+pub fn render_terrain<TTileset: TerrainTileset>(
+    map: &AlmMap,
+    tileset: &TTileset,
+    options: &TerrainRenderOptions
+) -> RgbaImage {
+    let tile_size = tileset.tile_size().max(1);
+    let (width, height) = (map.general_info.width as usize, map.general_info.height as usize);
+    let (image_width, image_height) = (width * tile_size, height * tile_size);
+    let mut image = RgbaImage::new(image_width, image_height);
+    if width == 0 || height == 0 {
+        return image;
+    }
+    let field = HeightField::new(map, width, height);
+    let light = Lighting::new(&map.general_info, &field, tile_size as f32, options);
+
+    let texel = |x: usize, y: usize| -> u32 {
+        let (tile_x, tile_y) = (x / tile_size, y / tile_size);
+        let tile = map.tiles.as_ref().and_then(|tiles| tiles.tiles.get(tile_y * width + tile_x).copied());
+        match tile {
+            None => terrain_color(TerrainKind::Grass),
+            Some(tile) => match tileset.tile_image(tile) {
+                Some(tile_image) => tile_image.get_pixel(x % tile_size, y % tile_size),
+                None => terrain_color(tile.get_terrain_kind())
+            }
+        }
+    };
+
+    for x in 0..image_width {
+        let mut covered_from = image_height as isize;
+        for y in (0..image_height).rev() {
+            let (u, v) = (x as f32 / tile_size as f32, y as f32 / tile_size as f32);
+            let screen_y = y as isize - (field.sample(u, v) * options.height_scale).round() as isize;
+            if screen_y >= covered_from {
+                continue;
+            }
+            let mut color = texel(x, y);
+            if options.lighting {
+                color = shade(color, light.sample(u, v));
+            }
+            for fill_y in screen_y.max(0)..covered_from {
+                image.set_pixel(x, fill_y as usize, color);
+            }
+            covered_from = screen_y.max(0);
+            if covered_from == 0 {
+                break;
+            }
+        }
+    }
+    image
+}
+
+struct HeightField {
+    width: usize,
+    height: usize,
+    heights: Vec<f32>
+}
+impl HeightField {
+    fn new(map: &AlmMap, width: usize, height: usize) -> Self {
+        let heights = match &map.height_map {
+            Some(height_map) => (0..width * height)
+                .map(|idx| height_map.heights.get(idx).copied().unwrap_or(0) as f32)
+                .collect(),
+            None => vec![0.0; width * height]
+        };
+        Self { width, height, heights }
+    }
+
+    fn at(&self, x: isize, y: isize) -> f32 {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        self.heights[y * self.width + x]
+    }
+
+    // Bilinear in between the corners; coordinates are in tiles
+    fn sample(&self, u: f32, v: f32) -> f32 {
+        bilinear(u, v, |x, y| self.at(x, y))
+    }
+}
+
+struct Lighting {
+    width: usize,
+    height: usize,
+    factors: Vec<f32> // per tile corner
+}
+impl Lighting {
+    // negative_sun_angle is taken as the sun elevation in degrees with the sun in the top left;
+    // darkness dims everything, contrast (100 being neutral) exaggerates slope shading
+    fn new(info: &GeneralMapInfoSection, field: &HeightField, tile_size: f32, options: &TerrainRenderOptions) -> Self {
+        let elevation = if info.negative_sun_angle.abs() > f32::EPSILON {
+            info.negative_sun_angle.abs().min(90.0)
+        } else {
+            DEFAULT_SUN_ANGLE
+        }.to_radians();
+        let horizontal = elevation.cos() * std::f32::consts::FRAC_1_SQRT_2;
+        let sun = (-horizontal, -horizontal, elevation.sin());
+        let flat_intensity = sun.2.max(0.01);
+        let brightness = 1.0 - (info.darkness.min(255) as f32 / 255.0) * 0.75;
+        let contrast = if info.contrast == 0 { 1.0 } else { info.contrast as f32 / 100.0 };
+
+        let mut factors = Vec::with_capacity(field.width * field.height);
+        for y in 0..field.height as isize {
+            for x in 0..field.width as isize {
+                let dx = (field.at(x + 1, y) - field.at(x - 1, y)) * options.height_scale / (2.0 * tile_size);
+                let dy = (field.at(x, y + 1) - field.at(x, y - 1)) * options.height_scale / (2.0 * tile_size);
+                let length = (dx * dx + dy * dy + 1.0).sqrt();
+                let normal = (-dx / length, -dy / length, 1.0 / length);
+                let intensity = (normal.0 * sun.0 + normal.1 * sun.1 + normal.2 * sun.2).max(0.0);
+                let relative = 1.0 + (intensity / flat_intensity - 1.0) * contrast;
+                factors.push((relative * brightness).max(0.0));
+            }
+        }
+        Self {
+            width: field.width,
+            height: field.height,
+            factors
+        }
+    }
+
+    fn sample(&self, u: f32, v: f32) -> f32 {
+        bilinear(u, v, |x, y| {
+            let x = x.clamp(0, self.width as isize - 1) as usize;
+            let y = y.clamp(0, self.height as isize - 1) as usize;
+            self.factors[y * self.width + x]
+        })
+    }
+}
+
+fn bilinear<F: Fn(isize, isize) -> f32>(u: f32, v: f32, at: F) -> f32 {
+    let (x0, y0) = (u.floor(), v.floor());
+    let (fx, fy) = (u - x0, v - y0);
+    let (x0, y0) = (x0 as isize, y0 as isize);
+    let top = at(x0, y0) * (1.0 - fx) + at(x0 + 1, y0) * fx;
+    let bottom = at(x0, y0 + 1) * (1.0 - fx) + at(x0 + 1, y0 + 1) * fx;
+    top * (1.0 - fy) + bottom * fy
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alm::terrain_renderer::*;
+
+    const SAND: u32 = 0xFF_AA_55_00;
+
+    struct SandTileset(RgbaImage);
+    impl TerrainTileset for SandTileset {
+        fn tile_size(&self) -> usize {
+            2
+        }
+
+        fn tile_image(&self, tile: TileEntry) -> Option<&RgbaImage> {
+            (tile.get_terrain_id() == 1).then_some(&self.0)
+        }
+    }
+
+    #[test]
+    fn test_flat_map() {
+        let mut sand = RgbaImage::new(2, 2);
+        sand.pixels.fill(SAND);
+        let tileset = SandTileset(sand);
+        let map = |darkness: u32| {
+            let mut builder = AlmMapBuilder::new(2, 2);
+            builder
+                .paint_tile(1, 0, TileEntry::new(1, 0, 0, true))
+                .set_lighting(0.0, darkness, 0);
+            builder.build()
+        };
+        // tiles without an image fall back to the minimap colour
+        let grass = terrain_color(TerrainKind::Grass);
+        let expected = |grass: u32, sand: u32| vec![
+            grass, grass, sand, sand,
+            grass, grass, sand, sand,
+            grass, grass, grass, grass,
+            grass, grass, grass, grass
+        ];
+
+        let unlit = TerrainRenderOptions { lighting: false, ..Default::default() };
+        let image = render_terrain(&map(0), &tileset, &unlit);
+        assert_eq!((image.width, image.height), (4, 4));
+        assert_eq!(image.pixels, expected(grass, SAND));
+        // a flat map is lit evenly, only darkness dims it
+        assert_eq!(render_terrain(&map(0), &tileset, &TerrainRenderOptions::default()).pixels, expected(grass, SAND));
+        let dark = render_terrain(&map(255), &tileset, &TerrainRenderOptions::default());
+        assert_eq!(dark.pixels, expected(shade(grass, 0.25), shade(SAND, 0.25)));
+    }
+}
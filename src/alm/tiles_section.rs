@@ -1,16 +1,48 @@
-use std::io::Read;
+use std::io::{Read, Write};
 use crate::shared_types::U16Wrapper;
 use bin_serialization_rs::{Endianness, Reflectable};
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TerrainKind {
+    Grass,
+    Sand,
+    Rock,
+    Water
+}
+impl TerrainKind {
+    pub fn from_id(terrain_id: u8) -> Self {
+        match terrain_id & 0x03 {
+            0 => TerrainKind::Grass,
+            1 => TerrainKind::Sand,
+            2 => TerrainKind::Rock,
+            _ => TerrainKind::Water
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct TileEntry(u16);
 impl TileEntry {
+    pub fn new(terrain_id: u8, tile_column_id: u8, tile_row_id: u8, passable: bool) -> Self {
+        let high = (terrain_id & 0x03) as u16 | if passable { 0x20 } else { 0 };
+        let low = ((tile_column_id & 0x0F) as u16) << 4 | (tile_row_id & 0x0F) as u16;
+        TileEntry(high * 0x100 + low)
+    }
+    pub fn from_raw(raw: u16) -> Self {
+        TileEntry(raw)
+    }
+    pub fn raw(self) -> u16 {
+        self.0
+    }
     pub fn is_passable(self) -> bool {
         ((self.0 / 0x100) & 0x20) != 0
     }
     pub fn get_terrain_id(self) -> u8 {
         ((self.0 / 0x100) & 0x03) as u8
     }
+    pub fn get_terrain_kind(self) -> TerrainKind {
+        TerrainKind::from_id(self.get_terrain_id())
+    }
     pub fn get_tile_column_id(self) -> u8 {
         ((self.0 & 0xF0) / 0x10) as u8
     }
@@ -39,4 +71,11 @@ impl TilesSection {
             tiles
         })
     }
+
+    pub(crate) fn write<TStream: Write>(&self, stream: &mut TStream) -> std::io::Result<()> {
+        for tile in self.tiles.iter() {
+            U16Wrapper(tile.0).serialize(stream, Endianness::LittleEndian)?;
+        }
+        Ok(())
+    }
 }
\ No newline at end of file
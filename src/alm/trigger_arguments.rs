@@ -0,0 +1,446 @@
+use super::*;
+use super::trigger_enums::*;
+
+const ARGUMENT_COUNT: usize = 10;
+
+// New entries get the arguments in the first slots in this order, the remaining slots stay Unknown, 0 and unnamed
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArgumentSlot {
+    pub name: &'static str,
+    pub argument_type: ArgumentType,
+    pub value: u32
+}
+
+// A field of the kind with fewer slots of its type than it needs
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArgumentLayoutError {
+    pub field: &'static str,
+    pub argument_type: ArgumentType,
+    pub index: usize // among the slots of that type
+}
+impl std::fmt::Display for ArgumentLayoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no {:?} argument #{} for {}", self.argument_type, self.index, self.field)
+    }
+}
+impl std::error::Error for ArgumentLayoutError {}
+
+trait ArgumentValue {
+    fn from_raw(value: u32) -> Self;
+    fn to_raw(self) -> u32;
+}
+impl ArgumentValue for u32 {
+    fn from_raw(value: u32) -> Self {
+        value
+    }
+
+    fn to_raw(self) -> u32 {
+        self
+    }
+}
+impl ArgumentValue for i32 {
+    fn from_raw(value: u32) -> Self {
+        value as i32
+    }
+
+    fn to_raw(self) -> u32 {
+        self as u32
+    }
+}
+
+// Numbers are signed, everything else (ids and coordinates) is unsigned
+macro_rules! argument_field_type {
+    (Number) => { i32 };
+    ($other:ident) => { u32 };
+}
+
+macro_rules! typed_arguments {
+    ($name:ident, $kind_type:ty, {
+        $($variant:ident [$($kind:tt)+] { $($field:ident: $argument_type:ident),* }),* $(,)?
+    }) => {
+        #[derive(Clone, Debug, PartialEq)]
+        pub enum $name {
+            $($variant { $($field: argument_field_type!($argument_type)),* }),*
+        }
+        impl $name {
+            // Slots of types the kind doesn't take are ignored
+            pub fn from_raw(
+                kind: $kind_type,
+                values: &[u32; ARGUMENT_COUNT],
+                types: &[ArgumentType]
+            ) -> std::result::Result<Self, ArgumentLayoutError> {
+                match kind {
+                    $($($kind)+ => {
+                        let _taken: &mut Vec<ArgumentType> = &mut Vec::new();
+                        Ok(Self::$variant {
+                            $($field: ArgumentValue::from_raw(
+                                take_field(stringify!($field), ArgumentType::$argument_type, _taken, values, types)?
+                            )),*
+                        })
+                    }),*
+                }
+            }
+
+            pub fn kind(&self) -> $kind_type {
+                match self {
+                    $(Self::$variant { .. } => $($kind)+),*
+                }
+            }
+
+            pub fn slots(&self) -> Vec<ArgumentSlot> {
+                match self {
+                    $(Self::$variant { $($field),* } => vec![$(ArgumentSlot {
+                        name: stringify!($field),
+                        argument_type: ArgumentType::$argument_type,
+                        value: ArgumentValue::to_raw(*$field)
+                    }),*]),*
+                }
+            }
+
+            // Every kind with distinct argument values
+            #[cfg(test)]
+            fn samples() -> Vec<Self> {
+                let mut _next = 0u32;
+                vec![$(Self::$variant {
+                    $($field: ArgumentValue::from_raw({ _next += 1; _next * 7 })),*
+                }),*]
+            }
+        }
+    };
+}
+
+typed_arguments!(CheckArguments, CheckType, {
+    Unknown [CheckType::General(GeneralCheckType::Unknown)] {},
+    GroupUnitCount [CheckType::General(GeneralCheckType::GroupUnitCount)] { group: Group },
+    IsUnitInABox [CheckType::General(GeneralCheckType::IsUnitInABox)] { unit: Unit, x1: X, y1: Y, x2: X, y2: Y },
+    IsUnitInACircle [CheckType::General(GeneralCheckType::IsUnitInACircle)] { unit: Unit, x: X, y: Y, radius: Number },
+    GetUnitParameter [CheckType::General(GeneralCheckType::GetUnitParameter)] { unit: Unit, parameter: Number },
+    IsUnitAlive [CheckType::General(GeneralCheckType::IsUnitAlive)] { unit: Unit },
+    GetDistanceBetweenUnits [CheckType::General(GeneralCheckType::GetDistanceBetweenUnits)] { unit1: Unit, unit2: Unit },
+    GetDistanceFromPointToUnit [CheckType::General(GeneralCheckType::GetDistanceFromPointToUnit)] {
+        x: X, y: Y, unit: Unit
+    },
+    HowManyUnitsFractionHave [CheckType::General(GeneralCheckType::HowManyUnitsFractionHave)] { fraction: Fraction },
+    IsUnitAttacked [CheckType::General(GeneralCheckType::IsUnitAttacked)] { unit: Unit },
+    GetDiplomacy [CheckType::General(GeneralCheckType::GetDiplomacy)] { a: Fraction, b: Fraction },
+    CheckSack [CheckType::General(GeneralCheckType::CheckSack)] { unit: Unit },
+    GetDistanceToNearestFractionUnit [CheckType::General(GeneralCheckType::GetDistanceToNearestFractionUnit)] {
+        unit: Unit, fraction: Fraction
+    },
+    GetDistanceFromPointToUnitWithItem [CheckType::General(GeneralCheckType::GetDistanceFromPointToUnitWithItem)] {
+        x: X, y: Y, item_id: Item
+    },
+    IsItemInSack [CheckType::General(GeneralCheckType::IsItemInSack)] { unit: Unit, item_id: Item },
+    Vip [CheckType::General(GeneralCheckType::Vip)] { unit: Unit },
+    CheckVariable [CheckType::General(GeneralCheckType::CheckVariable)] { variable: Number },
+    HowManyStructuresFractionHave [CheckType::General(GeneralCheckType::HowManyStructuresFractionHave)] {
+        fraction: Fraction
+    },
+    GetStructureHealth [CheckType::General(GeneralCheckType::GetStructureHealth)] { structure: Structure },
+    Teleport [CheckType::General(GeneralCheckType::Teleport)] { unit: Unit },
+    CheckScenarioVariable [CheckType::General(GeneralCheckType::CheckScenarioVariable)] { variable: Number },
+    CheckSubObjective [CheckType::General(GeneralCheckType::CheckSubObjective)] { objective: Number },
+    SpellInArea [CheckType::General(GeneralCheckType::SpellInArea)] { x: X, y: Y, radius: Number, spell: Number },
+    SpellOnUnit [CheckType::General(GeneralCheckType::SpellOnUnit)] { unit: Unit, spell: Number },
+    IsUnitInPoint [CheckType::General(GeneralCheckType::IsUnitInPoint)] { unit: Unit, x: X, y: Y },
+    Constant [CheckType::Constant] { value: Number }
+});
+
+typed_arguments!(InstanceArguments, InstanceType, {
+    Unknown [InstanceType::General(GeneralInstanceType::Unknown)] {},
+    IncrementMissionStage [InstanceType::General(GeneralInstanceType::IncrementMissionStage)] {},
+    SendMessage [InstanceType::General(GeneralInstanceType::SendMessage)] { message: Number },
+    SetVariableValue [InstanceType::General(GeneralInstanceType::SetVariableValue)] { variable: Number, value: Number },
+    ForceMissionComplete [InstanceType::General(GeneralInstanceType::ForceMissionComplete)] {},
+    ForceMissionFailed [InstanceType::General(GeneralInstanceType::ForceMissionFailed)] {},
+    Command [InstanceType::General(GeneralInstanceType::Command)] { unit: Unit, command: Number, x: X, y: Y },
+    KeepFormation [InstanceType::General(GeneralInstanceType::KeepFormation)] { group: Group },
+    IncrementVariable [InstanceType::General(GeneralInstanceType::IncrementVariable)] { variable: Number },
+    SetDiplomacy [InstanceType::General(GeneralInstanceType::SetDiplomacy)] { a: Fraction, b: Fraction, state: Number },
+    GiveItem [InstanceType::General(GeneralInstanceType::GiveItem)] { unit: Unit, item_id: Item, wielded: Number },
+    AddItemInUnitsSack [InstanceType::General(GeneralInstanceType::AddItemInUnitsSack)] { unit: Unit, item_id: Item },
+    RemoveItemFromUnitsSack [InstanceType::General(GeneralInstanceType::RemoveItemFromUnitsSack)] {
+        unit: Unit, item_id: Item
+    },
+    HideUnit [InstanceType::General(GeneralInstanceType::HideUnit)] { unit: Unit },
+    ShowUnit [InstanceType::General(GeneralInstanceType::ShowUnit)] { unit: Unit },
+    MetamorphUnit [InstanceType::General(GeneralInstanceType::MetamorphUnit)] { unit: Unit, type_id: Number },
+    ChangeUnitsOwner [InstanceType::General(GeneralInstanceType::ChangeUnitsOwner)] { unit: Unit, fraction: Fraction },
+    DropAll [InstanceType::General(GeneralInstanceType::DropAll)] { unit: Unit },
+    MagicOnArea [InstanceType::General(GeneralInstanceType::MagicOnArea)] { x: X, y: Y, spell: Number },
+    ChangeGroupsOwner [InstanceType::General(GeneralInstanceType::ChangeGroupsOwner)] {
+        group: Group, fraction: Fraction
+    },
+    GiveMoneyToFraction [InstanceType::General(GeneralInstanceType::GiveMoneyToFraction)] {
+        fraction: Fraction, amount: Number
+    },
+    MagicOnUnit [InstanceType::General(GeneralInstanceType::MagicOnUnit)] { unit: Unit, spell: Number },
+    CreateMagicTrigger [InstanceType::General(GeneralInstanceType::CreateMagicTrigger)] { x: X, y: Y, spell: Number },
+    SetStructureHealth [InstanceType::General(GeneralInstanceType::SetStructureHealth)] {
+        structure: Structure, health: Number
+    },
+    MoveUnitImmediate [InstanceType::General(GeneralInstanceType::MoveUnitImmediate)] { unit: Unit, x: X, y: Y },
+    GiveAllItemsFromUnitToUnit [InstanceType::General(GeneralInstanceType::GiveAllItemsFromUnitToUnit)] {
+        from: Unit, to: Unit
+    },
+    TimedSpellOnGround [InstanceType::General(GeneralInstanceType::TimedSpellOnGround)] {
+        x: X, y: Y, spell: Number, duration: Number
+    },
+    ChangeRespawnTime [InstanceType::General(GeneralInstanceType::ChangeRespawnTime)] { group: Group, time: Number },
+    HideGroup [InstanceType::General(GeneralInstanceType::HideGroup)] { group: Group },
+    ShowGroup [InstanceType::General(GeneralInstanceType::ShowGroup)] { group: Group },
+    SetUnitsParameter [InstanceType::General(GeneralInstanceType::SetUnitsParameter)] {
+        unit: Unit, parameter: Number, value: Number
+    },
+    SetScenarioVariable [InstanceType::General(GeneralInstanceType::SetScenarioVariable)] {
+        variable: Number, value: Number
+    },
+    SetSubObjective [InstanceType::General(GeneralInstanceType::SetSubObjective)] { objective: Number, state: Number },
+    SetMusicOrder [InstanceType::General(GeneralInstanceType::SetMusicOrder)] { order: Number },
+    RemoveItemFromAll [InstanceType::General(GeneralInstanceType::RemoveItemFromAll)] { item_id: Item },
+    StopGroup [InstanceType::General(GeneralInstanceType::StopGroup)] { group: Group },
+    StartHere [InstanceType::StartHere] {},
+    RespawnGroup [InstanceType::RespawnGroup] { group: Group },
+    ChangeMusicTo [InstanceType::ChangeMusicTo] { music: Number }
+});
+
+// Editors don't keep a fixed slot order, so a field is the n-th slot of its type,
+// n counting the fields of that type before it. Constant slots count as numbers
+pub(crate) fn nth_argument(
+    values: &[u32; ARGUMENT_COUNT],
+    types: &[ArgumentType],
+    argument_type: ArgumentType,
+    n: usize
+) -> Option<u32> {
+    nth_slot(types, argument_type, n).and_then(|slot| values.get(slot).copied())
+}
+
+fn nth_slot(types: &[ArgumentType], argument_type: ArgumentType, n: usize) -> Option<usize> {
+    types
+        .iter()
+        .enumerate()
+        .filter(|(_, &slot_type)| {
+            slot_type == argument_type || (argument_type == ArgumentType::Number && slot_type == ArgumentType::Constant)
+        })
+        .nth(n)
+        .map(|(slot, _)| slot)
+}
+
+fn take_field(
+    field: &'static str,
+    argument_type: ArgumentType,
+    taken: &mut Vec<ArgumentType>,
+    values: &[u32; ARGUMENT_COUNT],
+    types: &[ArgumentType]
+) -> std::result::Result<u32, ArgumentLayoutError> {
+    let index = taken.iter().filter(|&&taken_type| taken_type == argument_type).count();
+    taken.push(argument_type);
+    nth_argument(values, types, argument_type, index).ok_or(ArgumentLayoutError { field, argument_type, index })
+}
+
+// A field is written to the slot it's read from, a field the entry has no slot for takes the first free one.
+// Slots of no field keep their values, types and names, unless the kind changed and they belonged to the old one.
+// Names already given are kept, new slots get the field names
+fn store_slots(
+    slots: &[ArgumentSlot],
+    values: &mut [u32; ARGUMENT_COUNT],
+    types: &mut Vec<ArgumentType>,
+    names: &mut Vec<String>,
+    keep_other_slots: bool
+) {
+    types.resize(ARGUMENT_COUNT, ArgumentType::Unknown);
+    names.resize(ARGUMENT_COUNT, String::new());
+    if !keep_other_slots {
+        values.iter_mut().for_each(|value| *value = 0);
+        types.iter_mut().for_each(|argument_type| *argument_type = ArgumentType::Unknown);
+        names.iter_mut().for_each(|name| name.clear());
+    }
+    for (idx, argument) in slots.iter().enumerate() {
+        let n = slots[..idx].iter().filter(|other| other.argument_type == argument.argument_type).count();
+        if let Some(slot) = nth_slot(types, argument.argument_type, n) {
+            values[slot] = argument.value;
+            if names[slot].is_empty() {
+                names[slot] = argument.name.to_string();
+            }
+            continue;
+        }
+        let free_slot = (0..ARGUMENT_COUNT).find(|&slot| {
+            types[slot] == ArgumentType::Unknown && values[slot] == 0 && names[slot].is_empty()
+        });
+        match free_slot {
+            Some(slot) => {
+                values[slot] = argument.value;
+                types[slot] = argument.argument_type;
+                names[slot] = argument.name.to_string();
+            },
+            // no room left, lay the slots out from the start
+            None => return store_slots(slots, values, types, names, false)
+        }
+    }
+}
+
+impl CheckEntry {
+    pub fn from_arguments(name: &str, id: u32, arguments: &CheckArguments) -> Self {
+        let mut check = Self {
+            name: name.to_string(),
+            check_type: arguments.kind(),
+            id,
+            execute_once: 0,
+            argument_values: [0; ARGUMENT_COUNT],
+            argument_types: Vec::new(),
+            argument_names: Vec::new()
+        };
+        check.set_arguments(arguments);
+        check
+    }
+
+    pub fn arguments(&self) -> std::result::Result<CheckArguments, ArgumentLayoutError> {
+        CheckArguments::from_raw(self.check_type, &self.argument_values, &self.argument_types)
+    }
+
+    pub fn set_arguments(&mut self, arguments: &CheckArguments) {
+        let same_kind = self.check_type.to_primitive() == arguments.kind().to_primitive();
+        self.check_type = arguments.kind();
+        store_slots(
+            &arguments.slots(),
+            &mut self.argument_values,
+            &mut self.argument_types,
+            &mut self.argument_names,
+            same_kind
+        );
+    }
+}
+
+impl InstanceEntry {
+    pub fn from_arguments(name: &str, id: u32, arguments: &InstanceArguments) -> Self {
+        let mut instance = Self {
+            name: name.to_string(),
+            instance_type: arguments.kind(),
+            id,
+            execute_once: 0,
+            argument_values: [0; ARGUMENT_COUNT],
+            argument_types: Vec::new(),
+            argument_names: Vec::new()
+        };
+        instance.set_arguments(arguments);
+        instance
+    }
+
+    pub fn arguments(&self) -> std::result::Result<InstanceArguments, ArgumentLayoutError> {
+        InstanceArguments::from_raw(self.instance_type, &self.argument_values, &self.argument_types)
+    }
+
+    pub fn set_arguments(&mut self, arguments: &InstanceArguments) {
+        let same_kind = self.instance_type.to_primitive() == arguments.kind().to_primitive();
+        self.instance_type = arguments.kind();
+        store_slots(
+            &arguments.slots(),
+            &mut self.argument_values,
+            &mut self.argument_types,
+            &mut self.argument_names,
+            same_kind
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alm::*;
+    use crate::alm::trigger_enums::*;
+    use crate::alm::trigger_vm::Arguments;
+    use bin_serialization_rs::Endianness;
+
+    // Slot order as editors may leave it: grouped by type, with an unrelated slot in front.
+    // Slots of the same type keep their order, that's what tells them apart
+    fn shuffle(values: &mut [u32; 10], types: &mut [ArgumentType], names: &mut [String], used: usize) {
+        let mut slots: Vec<(u32, ArgumentType, String)> = (0..used)
+            .map(|slot| (values[slot], types[slot], names[slot].clone()))
+            .collect();
+        slots.sort_by_key(|(_, argument_type, _)| std::cmp::Reverse(*argument_type as u32));
+        slots.insert(0, (99, ArgumentType::Unknown, String::new()));
+        for (slot, (value, argument_type, name)) in slots.into_iter().enumerate() {
+            values[slot] = value;
+            types[slot] = argument_type;
+            names[slot] = name;
+        }
+    }
+
+    // The typed fields agree with what the VM and the validator read
+    fn assert_agrees(arguments: &Arguments, slots: &[ArgumentSlot]) {
+        for (idx, slot) in slots.iter().enumerate() {
+            let n = slots[..idx].iter().filter(|other| other.argument_type == slot.argument_type).count();
+            assert_eq!(arguments.nth(slot.argument_type, n), slot.value, "{}", slot.name);
+        }
+    }
+
+    #[test]
+    fn test_check_arguments_round_trip() {
+        for sample in CheckArguments::samples() {
+            let mut check = CheckEntry::from_arguments("check", 1, &sample);
+            assert_eq!(check.check_type.to_primitive(), sample.kind().to_primitive());
+            assert_eq!(check.arguments(), Ok(sample.clone()));
+            let used = sample.slots().len();
+            shuffle(&mut check.argument_values, &mut check.argument_types, &mut check.argument_names, used);
+            assert_eq!(check.arguments(), Ok(sample.clone()));
+            assert_agrees(&Arguments::of_check(&check), &sample.slots());
+        }
+    }
+
+    #[test]
+    fn test_instance_arguments_round_trip() {
+        for sample in InstanceArguments::samples() {
+            let mut instance = InstanceEntry::from_arguments("instance", 1, &sample);
+            assert_eq!(instance.instance_type.to_primitive(), sample.kind().to_primitive());
+            assert_eq!(instance.arguments(), Ok(sample.clone()));
+            let used = sample.slots().len();
+            shuffle(&mut instance.argument_values, &mut instance.argument_types, &mut instance.argument_names, used);
+            assert_eq!(instance.arguments(), Ok(sample.clone()));
+            assert_agrees(&Arguments::of_instance(&instance), &sample.slots());
+        }
+    }
+
+    #[test]
+    fn test_missing_argument() {
+        let mut check = CheckEntry::from_arguments("check", 1, &CheckArguments::GetDiplomacy { a: 1, b: 2 });
+        check.argument_types[1] = ArgumentType::Number;
+        assert_eq!(
+            check.arguments(),
+            Err(ArgumentLayoutError { field: "b", argument_type: ArgumentType::Fraction, index: 1 })
+        );
+        // a constant slot still reads as a number
+        check.set_arguments(&CheckArguments::Constant { value: -5 });
+        check.argument_types[0] = ArgumentType::Constant;
+        assert_eq!(check.arguments(), Ok(CheckArguments::Constant { value: -5 }));
+    }
+
+    #[test]
+    fn test_extra_slots_survive_set_arguments() {
+        let bytes = |check: &CheckEntry| {
+            let mut bytes = Vec::new();
+            check.write_to_stream(&mut bytes, Endianness::LittleEndian).unwrap();
+            bytes
+        };
+        let mut check = CheckEntry::from_arguments("check", 1, &CheckArguments::IsUnitInACircle {
+            unit: 3, x: 10, y: 20, radius: 4
+        });
+        // editor leftovers around the typed slots
+        check.argument_values[4] = 77;
+        check.argument_names[4] = "junk".to_string();
+        check.argument_values[6] = 5;
+        check.argument_types[6] = ArgumentType::Number;
+        check.argument_names[6] = "second number".to_string();
+        check.argument_names[1] = "renamed".to_string();
+        let original = bytes(&check);
+
+        check.set_arguments(&check.arguments().unwrap());
+        assert_eq!(bytes(&check), original);
+
+        check.set_arguments(&CheckArguments::IsUnitInACircle { unit: 3, x: 11, y: 20, radius: 4 });
+        assert_eq!((check.argument_values[1], &check.argument_names[1][..]), (11, "renamed"));
+        assert_eq!((check.argument_values[6], &check.argument_names[4][..]), (5, "junk"));
+        // another kind drops the old layout
+        check.set_arguments(&CheckArguments::IsUnitAlive { unit: 8 });
+        assert_eq!(check.argument_values, [8, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(check.argument_names[0], "unit");
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use super::*;
+use super::trigger_enums::*;
+use super::trigger_vm::Arguments;
+use super::trigger_script::{check_type_name, escape, instance_type_name, operator_symbol};
+
+// Things checks read and instances write; they become the shared nodes of the graph
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Resource {
+    Unit(u32),
+    Group(u32),
+    Variable(u32),
+    ScenarioVariable(u32),
+    SubObjective(u32),
+    MissionStage
+}
+impl Resource {
+    fn node(self) -> String {
+        match self {
+            Resource::Unit(id) => format!("unit_{}", id),
+            Resource::Group(id) => format!("group_{}", id),
+            Resource::Variable(id) => format!("variable_{}", id),
+            Resource::ScenarioVariable(id) => format!("scenario_variable_{}", id),
+            Resource::SubObjective(id) => format!("sub_objective_{}", id),
+            Resource::MissionStage => "mission_stage".to_string()
+        }
+    }
+
+    fn label(self) -> String {
+        match self {
+            Resource::Unit(id) => format!("unit {}", id),
+            Resource::Group(id) => format!("group {}", id),
+            Resource::Variable(id) => format!("variable {}", id),
+            Resource::ScenarioVariable(id) => format!("scenario variable {}", id),
+            Resource::SubObjective(id) => format!("sub-objective {}", id),
+            Resource::MissionStage => "mission stage".to_string()
+        }
+    }
+
+    // Units and groups live in the map, the rest only changes through instances
+    fn is_state(self) -> bool {
+        !matches!(self, Resource::Unit(_) | Resource::Group(_))
+    }
+
+    // Scenario variables come from earlier missions, so only these are known to start at 0
+    fn is_mission_local(self) -> bool {
+        matches!(self, Resource::Variable(_) | Resource::SubObjective(_) | Resource::MissionStage)
+    }
+}
+
+// Triggers are boxes, checks ellipses, instances hexagons and shared state circles.
+// Dashed edges go from a trigger changing some state to the triggers checking it;
+// triggers depending on state nothing changes are greyed out as dead branches
+pub fn export_trigger_graph(section: &TriggersSection) -> String {
+    let check_reads: BTreeMap<u32, BTreeSet<Resource>> = section.checks
+        .iter()
+        .map(|check| (check.id, check_reads(check)))
+        .collect();
+    let instance_writes: BTreeMap<u32, BTreeSet<Resource>> = section.instances
+        .iter()
+        .map(|instance| (instance.id, instance_writes(instance)))
+        .collect();
+    let written: BTreeSet<Resource> = instance_writes.values().flatten().copied().collect();
+
+    let mut trigger_reads = Vec::with_capacity(section.triggers.len());
+    let mut trigger_writes = Vec::with_capacity(section.triggers.len());
+    for trigger in section.triggers.iter() {
+        let reads: BTreeSet<Resource> = trigger.check_identifiers
+            .iter()
+            .filter_map(|id| check_reads.get(id))
+            .flatten()
+            .copied()
+            .collect();
+        let writes: BTreeSet<Resource> = trigger.instance_identifiers
+            .iter()
+            .filter_map(|id| instance_writes.get(id))
+            .flatten()
+            .copied()
+            .collect();
+        trigger_reads.push(reads);
+        trigger_writes.push(writes);
+    }
+
+    let mut dot = String::new();
+    writeln!(dot, "digraph triggers {{").unwrap();
+    writeln!(dot, "    rankdir=LR;").unwrap();
+    for (idx, trigger) in section.triggers.iter().enumerate() {
+        let dead = trigger_reads[idx]
+            .iter()
+            .any(|&resource| resource.is_mission_local() && !written.contains(&resource));
+        let style = if dead { ", style=filled, fillcolor=gray80, fontcolor=gray40" } else { "" };
+        writeln!(dot, "    trigger_{} [shape=box, label=\"{}\"{}];", idx, escape(&trigger.name), style).unwrap();
+    }
+    for check in section.checks.iter() {
+        writeln!(
+            dot,
+            "    check_{} [shape=ellipse, label=\"#{} {}\\n{}{}\"];",
+            check.id,
+            check.id,
+            escape(&check.name),
+            check_type_name(check.check_type),
+            layout_note(check.arguments())
+        ).unwrap();
+    }
+    for instance in section.instances.iter() {
+        writeln!(
+            dot,
+            "    instance_{} [shape=hexagon, label=\"#{} {}\\n{}{}\"];",
+            instance.id,
+            instance.id,
+            escape(&instance.name),
+            instance_type_name(instance.instance_type),
+            layout_note(instance.arguments())
+        ).unwrap();
+    }
+    let resources: BTreeSet<Resource> = check_reads.values().chain(instance_writes.values()).flatten().copied().collect();
+    for resource in resources.iter() {
+        writeln!(dot, "    {} [shape=circle, label=\"{}\"];", resource.node(), resource.label()).unwrap();
+    }
+
+    for (idx, trigger) in section.triggers.iter().enumerate() {
+        let operators = [trigger.check_01_operator, trigger.check_23_operator, trigger.check_45_operator];
+        for (ids, operator) in trigger.check_identifiers.chunks(2).zip(operators.iter()) {
+            let label = operator.map_or("?", operator_symbol);
+            for &id in ids.iter().filter(|&&id| check_reads.contains_key(&id)) {
+                writeln!(dot, "    check_{} -> trigger_{} [label=\"{}\"];", id, idx, label).unwrap();
+            }
+        }
+        for &id in trigger.instance_identifiers.iter().filter(|&&id| instance_writes.contains_key(&id)) {
+            writeln!(dot, "    trigger_{} -> instance_{};", idx, id).unwrap();
+        }
+    }
+    for (id, reads) in check_reads.iter() {
+        for resource in reads.iter() {
+            writeln!(dot, "    {} -> check_{};", resource.node(), id).unwrap();
+        }
+    }
+    for (id, writes) in instance_writes.iter() {
+        for resource in writes.iter() {
+            writeln!(dot, "    instance_{} -> {};", id, resource.node()).unwrap();
+        }
+    }
+    for (from, writes) in trigger_writes.iter().enumerate() {
+        for (to, reads) in trigger_reads.iter().enumerate() {
+            for resource in writes.intersection(reads).filter(|resource| resource.is_state()) {
+                writeln!(
+                    dot,
+                    "    trigger_{} -> trigger_{} [style=dashed, label=\"{}\"];",
+                    from,
+                    to,
+                    resource.label()
+                ).unwrap();
+            }
+        }
+    }
+    writeln!(dot, "}}").unwrap();
+    dot
+}
+
+impl TriggersSection {
+    pub fn to_dot(&self) -> String {
+        export_trigger_graph(self)
+    }
+}
+
+fn unit_and_group_arguments(types: &[ArgumentType], values: &[u32; 10]) -> BTreeSet<Resource> {
+    types
+        .iter()
+        .zip(values.iter())
+        .filter_map(|(argument_type, &value)| match argument_type {
+            ArgumentType::Unit => Some(Resource::Unit(value)),
+            ArgumentType::Group => Some(Resource::Group(value)),
+            _ => None
+        })
+        .collect()
+}
+
+// Read the way the VM reads them, so a slot missing from the kind layout doesn't hide
+// the resource; the layout error shows up on the node instead
+fn check_reads(check: &CheckEntry) -> BTreeSet<Resource> {
+    let mut reads = unit_and_group_arguments(&check.argument_types, &check.argument_values);
+    let first_number = Arguments::of_check(check).number(0) as u32;
+    match check.check_type {
+        CheckType::General(GeneralCheckType::CheckVariable) => reads.insert(Resource::Variable(first_number)),
+        CheckType::General(GeneralCheckType::CheckScenarioVariable) => {
+            reads.insert(Resource::ScenarioVariable(first_number))
+        },
+        CheckType::General(GeneralCheckType::CheckSubObjective) => reads.insert(Resource::SubObjective(first_number)),
+        _ => false
+    };
+    reads
+}
+
+fn instance_writes(instance: &InstanceEntry) -> BTreeSet<Resource> {
+    let mut writes = unit_and_group_arguments(&instance.argument_types, &instance.argument_values);
+    let first_number = Arguments::of_instance(instance).number(0) as u32;
+    match instance.instance_type {
+        InstanceType::General(GeneralInstanceType::SetVariableValue)
+        | InstanceType::General(GeneralInstanceType::IncrementVariable) => {
+            writes.insert(Resource::Variable(first_number))
+        },
+        InstanceType::General(GeneralInstanceType::SetScenarioVariable) => {
+            writes.insert(Resource::ScenarioVariable(first_number))
+        },
+        InstanceType::General(GeneralInstanceType::SetSubObjective) => {
+            writes.insert(Resource::SubObjective(first_number))
+        },
+        InstanceType::General(GeneralInstanceType::IncrementMissionStage) => writes.insert(Resource::MissionStage),
+        _ => false
+    };
+    writes
+}
+
+fn layout_note<T>(arguments: std::result::Result<T, ArgumentLayoutError>) -> String {
+    arguments.err().map_or(String::new(), |error| format!("\\n({})", escape(&error.to_string())))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alm::*;
+    use crate::alm::trigger_enums::*;
+
+    #[test]
+    fn test_broken_layout_keeps_writes() {
+        let script = "check \"Var\" = CheckVariable(variable: Number = 5);\n\
+                      check \"Zero\" = Constant(value: Number = 0);\n\
+                      instance \"Set\" = SetVariableValue(variable: Number = 5, value: Number = 1);\n\
+                      instance \"Fail\" = ForceMissionFailed();\n\
+                      trigger \"Start\" { if \"Zero\" == \"Zero\"; do \"Set\"; }\n\
+                      trigger \"Next\" { if \"Var\" != \"Zero\"; do \"Fail\"; }\n";
+        let mut section = compile_triggers(script).unwrap();
+        let set = section.instances.iter_mut().find(|instance| instance.name == "Set").unwrap();
+        set.argument_types[1] = ArgumentType::Unknown;
+        assert!(set.arguments().is_err());
+        let set_id = set.id;
+
+        let dot = section.to_dot();
+        assert!(dot.contains(&format!("instance_{} -> variable_5;", set_id)));
+        assert!(dot.contains("trigger_0 -> trigger_1 [style=dashed"));
+        assert!(dot.contains("(no Number argument #1 for value)"));
+        assert!(!dot.contains("fillcolor=gray80"));
+    }
+}
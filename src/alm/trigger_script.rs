@@ -0,0 +1,928 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use super::*;
+use super::trigger_enums::*;
+use crate::shared_types::encode_cp866;
+use num_enum::TryFromPrimitive;
+
+// Script layout:
+//
+//   instance #1 "Greeting" once = SendMessage(message: Number = 12);
+//   check #1 "Hero" = IsUnitAlive(hero: Unit = 1);
+//   check #2 "Dead" = Constant(value: Number = 0);
+//
+//   trigger "Lose" once {
+//       if "Hero" == "Dead";
+//       do "Greeting";
+//   }
+//
+// Checks and instances are referenced by name when it's unique, by #id otherwise.
+// Unused trailing argument slots are left out. Every argument the kind takes must be there
+// with its type, as CheckArguments and InstanceArguments read them; other slots are kept as they are.
+
+pub fn decompile_triggers(section: &TriggersSection) -> String {
+    let mut script = String::new();
+    let instance_refs = references(section.instances.iter().map(|i| (i.id, i.name.as_str())));
+    let check_refs = references(section.checks.iter().map(|c| (c.id, c.name.as_str())));
+
+    for instance in section.instances.iter() {
+        let _ = writeln!(
+            script,
+            "instance #{} \"{}\"{} = {}({});",
+            instance.id,
+            escape(&instance.name),
+            once_flag(instance.execute_once),
+            instance_type_name(instance.instance_type),
+            arguments(&instance.argument_values, &instance.argument_types, &instance.argument_names)
+        );
+    }
+    if !section.instances.is_empty() {
+        script.push('\n');
+    }
+    for check in section.checks.iter() {
+        let _ = writeln!(
+            script,
+            "check #{} \"{}\"{} = {}({});",
+            check.id,
+            escape(&check.name),
+            once_flag(check.execute_once),
+            check_type_name(check.check_type),
+            arguments(&check.argument_values, &check.argument_types, &check.argument_names)
+        );
+    }
+
+    for trigger in section.triggers.iter() {
+        script.push('\n');
+        let _ = writeln!(script, "trigger \"{}\"{} {{", escape(&trigger.name), once_flag(trigger.run_once));
+        let operators = [trigger.check_01_operator, trigger.check_23_operator, trigger.check_45_operator];
+        for (pair, operator) in trigger.check_identifiers.chunks(2).zip(operators.iter()) {
+            if pair[0] == 0 && pair[1] == 0 {
+                continue;
+            }
+            let _ = writeln!(
+                script,
+                "    if {} {} {};",
+                reference(&check_refs, pair[0]),
+                operator.map_or("?", operator_symbol),
+                reference(&check_refs, pair[1])
+            );
+        }
+        for &instance_id in trigger.instance_identifiers.iter().filter(|&&id| id != 0) {
+            let _ = writeln!(script, "    do {};", reference(&instance_refs, instance_id));
+        }
+        script.push_str("}\n");
+    }
+    script
+}
+
+impl TriggersSection {
+    pub fn to_script(&self) -> String {
+        decompile_triggers(self)
+    }
+}
+
+// A trigger with its checks and instances written out in place, so it reads the same whatever ids they got.
+// Pairs and instance slots are labelled by position, empty ones are left out
+pub(crate) fn trigger_fields(section: &TriggersSection, trigger: &TriggerEntry) -> Vec<(&'static str, String)> {
+    const CONDITIONS: [&str; 3] = ["condition 1", "condition 2", "condition 3"];
+    const ACTIONS: [&str; 4] = ["action 1", "action 2", "action 3", "action 4"];
+    let check = |id: u32| match section.checks.iter().find(|check| check.id == id) {
+        Some(check) => format!(
+            "(\"{}\"{} {}({}))",
+            escape(&check.name),
+            once_flag(check.execute_once),
+            check_type_name(check.check_type),
+            arguments(&check.argument_values, &check.argument_types, &check.argument_names)
+        ),
+        None => format!("#{}", id)
+    };
+    let instance = |id: u32| match section.instances.iter().find(|instance| instance.id == id) {
+        Some(instance) => format!(
+            "\"{}\"{} {}({})",
+            escape(&instance.name),
+            once_flag(instance.execute_once),
+            instance_type_name(instance.instance_type),
+            arguments(&instance.argument_values, &instance.argument_types, &instance.argument_names)
+        ),
+        None => format!("#{}", id)
+    };
+
+    let mut fields = vec![("run_once", trigger.run_once.to_string())];
+    let operators = [trigger.check_01_operator, trigger.check_23_operator, trigger.check_45_operator];
+    for (idx, (pair, operator)) in trigger.check_identifiers.chunks(2).zip(operators.iter()).enumerate() {
+        if pair[0] == 0 && pair[1] == 0 {
+            continue;
+        }
+        let condition = format!("{} {} {}", check(pair[0]), operator.map_or("?", operator_symbol), check(pair[1]));
+        fields.push((CONDITIONS[idx], condition));
+    }
+    for (idx, &id) in trigger.instance_identifiers.iter().enumerate().filter(|(_, &id)| id != 0) {
+        fields.push((ACTIONS[idx], instance(id)));
+    }
+    fields
+}
+
+pub(crate) fn instance_type_name(instance_type: InstanceType) -> String {
+    match instance_type {
+        InstanceType::General(general) => format!("{:?}", general),
+        other => format!("{:?}", other)
+    }
+}
+
+pub(crate) fn check_type_name(check_type: CheckType) -> String {
+    match check_type {
+        CheckType::General(general) => format!("{:?}", general),
+        CheckType::Constant => "Constant".to_string()
+    }
+}
+
+pub(crate) fn operator_symbol(operator: CheckOperator) -> &'static str {
+    match operator {
+        CheckOperator::Equals => "==",
+        CheckOperator::NotEquals => "!=",
+        CheckOperator::GreaterThan => ">",
+        CheckOperator::LowerThan => "<",
+        CheckOperator::GreaterThanEquals => ">=",
+        CheckOperator::LowerThanEquals => "<="
+    }
+}
+
+fn once_flag(value: u32) -> String {
+    match value {
+        0 => String::new(),
+        1 => " once".to_string(),
+        other => format!(" once({})", other)
+    }
+}
+
+fn arguments(values: &[u32; 10], types: &[ArgumentType], names: &[String]) -> String {
+    let is_used = |i: usize| {
+        values[i] != 0
+            || types.get(i).is_some_and(|t| !matches!(t, ArgumentType::Unknown))
+            || names.get(i).is_some_and(|n| !n.is_empty())
+    };
+    let used_count = (0..values.len()).rev().find(|&i| is_used(i)).map_or(0, |i| i + 1);
+    (0..used_count)
+        .map(|i| {
+            let argument_type = types.get(i).copied().unwrap_or_default();
+            let name = names.get(i).map_or("", String::as_str);
+            format!("{}: {:?} = {}", argument_name(name), argument_type, argument_value(values[i], argument_type))
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Names that aren't plain identifiers get quoted
+fn argument_name(name: &str) -> String {
+    let is_identifier = !name.is_empty()
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+        && !name.starts_with(|c: char| c.is_ascii_digit());
+    if is_identifier {
+        name.to_string()
+    } else {
+        format!("\"{}\"", escape(name))
+    }
+}
+
+fn argument_value(value: u32, argument_type: ArgumentType) -> String {
+    match argument_type {
+        ArgumentType::Number => (value as i32).to_string(),
+        _ => value.to_string()
+    }
+}
+
+fn references<'a, I: Iterator<Item = (u32, &'a str)>>(entries: I) -> HashMap<u32, String> {
+    let entries: Vec<_> = entries.collect();
+    let mut name_usage: HashMap<&str, usize> = HashMap::new();
+    for &(_, name) in entries.iter() {
+        *name_usage.entry(name).or_insert(0) += 1;
+    }
+    entries
+        .iter()
+        .map(|&(id, name)| {
+            if !name.is_empty() && name_usage[name] == 1 {
+                (id, format!("\"{}\"", escape(name)))
+            } else {
+                (id, format!("#{}", id))
+            }
+        })
+        .collect()
+}
+
+fn reference(references: &HashMap<u32, String>, id: u32) -> String {
+    references.get(&id).cloned().unwrap_or_else(|| format!("#{}", id))
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+const NAME_CAPACITY: usize = 0x3F;
+const TRIGGER_NAME_CAPACITY: usize = 0x7F;
+const ARGUMENT_COUNT: usize = 10;
+const MAX_INSTANCES_PER_TRIGGER: usize = 4;
+const MAX_CONDITIONS_PER_TRIGGER: usize = 3;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScriptError {
+    pub line: usize,
+    pub message: String
+}
+impl std::fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+impl std::error::Error for ScriptError {}
+
+// Ids given with #id are kept, the rest get the next free ones. All the errors found are reported
+pub fn compile_triggers(script: &str) -> Result<TriggersSection, Vec<ScriptError>> {
+    let tokens = tokenize(script)?;
+    let mut parser = Parser { tokens, position: 0, errors: Vec::new() };
+    let items = parser.parse_script();
+    let mut errors = parser.errors;
+
+    let mut instances = Vec::new();
+    let mut checks = Vec::new();
+    let mut triggers = Vec::new();
+    for item in items {
+        match item {
+            Item::Instance(declaration) => instances.push(declaration),
+            Item::Check(declaration) => checks.push(declaration),
+            Item::Trigger(trigger) => triggers.push(trigger)
+        }
+    }
+    let instance_ids = assign_ids(&instances, &mut errors);
+    let check_ids = assign_ids(&checks, &mut errors);
+    let instance_lookup = lookup(&instances, &instance_ids);
+    let check_lookup = lookup(&checks, &check_ids);
+
+    let mut section = TriggersSection {
+        instances: Vec::with_capacity(instances.len()),
+        checks: Vec::with_capacity(checks.len()),
+        triggers: Vec::with_capacity(triggers.len())
+    };
+    for (declaration, &id) in instances.iter().zip(instance_ids.iter()) {
+        let instance_type = match find_instance_type(&declaration.type_name) {
+            Some(instance_type) => instance_type,
+            None => {
+                errors.push(error(declaration.line, format!("unknown instance type {}", declaration.type_name)));
+                continue;
+            }
+        };
+        let (argument_values, argument_types, argument_names) = compile_arguments(declaration, &mut errors);
+        let instance = InstanceEntry {
+            name: declaration.name.clone(),
+            instance_type,
+            id,
+            execute_once: declaration.once,
+            argument_values,
+            argument_types,
+            argument_names
+        };
+        if let Err(layout_error) = instance.arguments() {
+            errors.push(error(declaration.line, format!("{}: {}", declaration.type_name, layout_error)));
+        }
+        section.instances.push(instance);
+    }
+    for (declaration, &id) in checks.iter().zip(check_ids.iter()) {
+        let check_type = match find_check_type(&declaration.type_name) {
+            Some(check_type) => check_type,
+            None => {
+                errors.push(error(declaration.line, format!("unknown check type {}", declaration.type_name)));
+                continue;
+            }
+        };
+        let (argument_values, argument_types, argument_names) = compile_arguments(declaration, &mut errors);
+        let check = CheckEntry {
+            name: declaration.name.clone(),
+            check_type,
+            id,
+            execute_once: declaration.once,
+            argument_values,
+            argument_types,
+            argument_names
+        };
+        if let Err(layout_error) = check.arguments() {
+            errors.push(error(declaration.line, format!("{}: {}", declaration.type_name, layout_error)));
+        }
+        section.checks.push(check);
+    }
+    for trigger in triggers.iter() {
+        if let Some(entry) = compile_trigger(trigger, &check_lookup, &instance_lookup, &mut errors) {
+            section.triggers.push(entry);
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(section)
+    } else {
+        errors.sort_by_key(|e| e.line);
+        Err(errors)
+    }
+}
+
+impl TriggersSection {
+    pub fn from_script(script: &str) -> Result<Self, Vec<ScriptError>> {
+        compile_triggers(script)
+    }
+}
+
+fn error(line: usize, message: String) -> ScriptError {
+    ScriptError { line, message }
+}
+
+fn find_instance_type(name: &str) -> Option<InstanceType> {
+    let special = [InstanceType::StartHere, InstanceType::RespawnGroup, InstanceType::ChangeMusicTo];
+    (0..=0x27u32)
+        .filter_map(|n| GeneralInstanceType::try_from_primitive(n).ok())
+        .map(InstanceType::General)
+        .chain(special.iter().copied())
+        .find(|&instance_type| instance_type_name(instance_type) == name)
+}
+
+fn find_check_type(name: &str) -> Option<CheckType> {
+    (0..=0x1Bu32)
+        .filter_map(|n| GeneralCheckType::try_from_primitive(n).ok())
+        .map(CheckType::General)
+        .chain(std::iter::once(CheckType::Constant))
+        .find(|&check_type| check_type_name(check_type) == name)
+}
+
+fn find_argument_type(name: &str) -> Option<ArgumentType> {
+    (0..=9u32)
+        .filter_map(|n| ArgumentType::try_from_primitive(n).ok())
+        .find(|argument_type| format!("{:?}", argument_type) == name)
+}
+
+fn find_operator(symbol: &str) -> Option<CheckOperator> {
+    (0..=5u32)
+        .filter_map(|n| CheckOperator::try_from_primitive(n).ok())
+        .find(|&operator| operator_symbol(operator) == symbol)
+}
+
+// Range check of an argument value against its declared type
+fn encode_argument(value: i64, argument_type: ArgumentType) -> Result<u32, String> {
+    let (min, max) = match argument_type {
+        ArgumentType::Number => (i32::MIN as i64, i32::MAX as i64),
+        ArgumentType::Unknown => (i32::MIN as i64, u32::MAX as i64),
+        _ => (0, u32::MAX as i64)
+    };
+    if value < min || value > max {
+        return Err(format!("{} is out of range for {:?} ({}..={})", value, argument_type, min, max));
+    }
+    Ok(value as u32)
+}
+
+fn compile_arguments(
+    declaration: &Declaration,
+    errors: &mut Vec<ScriptError>
+) -> ([u32; ARGUMENT_COUNT], Vec<ArgumentType>, Vec<String>) {
+    let mut values = [0u32; ARGUMENT_COUNT];
+    let mut types = vec![ArgumentType::Unknown; ARGUMENT_COUNT];
+    let mut names = vec![String::new(); ARGUMENT_COUNT];
+    if declaration.arguments.len() > ARGUMENT_COUNT {
+        errors.push(error(declaration.line, format!("at most {} arguments are allowed", ARGUMENT_COUNT)));
+    }
+    for (i, argument) in declaration.arguments.iter().take(ARGUMENT_COUNT).enumerate() {
+        let argument_type = match find_argument_type(&argument.type_name) {
+            Some(argument_type) => argument_type,
+            None => {
+                errors.push(error(argument.line, format!("unknown argument type {}", argument.type_name)));
+                continue;
+            }
+        };
+        match encode_argument(argument.value, argument_type) {
+            Ok(value) => values[i] = value,
+            Err(message) => errors.push(error(argument.line, format!("argument {}: {}", argument.name, message)))
+        }
+        if encode_cp866(&argument.name).len() > NAME_CAPACITY {
+            errors.push(error(argument.line, format!("argument name {} is longer than {} bytes", argument.name, NAME_CAPACITY)));
+        }
+        types[i] = argument_type;
+        names[i] = argument.name.clone();
+    }
+    (values, types, names)
+}
+
+fn assign_ids(declarations: &[Declaration], errors: &mut Vec<ScriptError>) -> Vec<u32> {
+    let mut taken: Vec<u32> = Vec::new();
+    for declaration in declarations.iter() {
+        if let Some(id) = declaration.id {
+            if id == 0 {
+                errors.push(error(declaration.line, "id 0 is reserved for empty slots".to_string()));
+            } else if taken.contains(&id) {
+                errors.push(error(declaration.line, format!("id #{} is used twice", id)));
+            }
+            taken.push(id);
+        }
+        if encode_cp866(&declaration.name).len() > NAME_CAPACITY {
+            errors.push(error(declaration.line, format!("name is longer than {} bytes", NAME_CAPACITY)));
+        }
+    }
+    let mut next_id = 1;
+    declarations
+        .iter()
+        .map(|declaration| match declaration.id {
+            Some(id) => id,
+            None => {
+                while taken.contains(&next_id) {
+                    next_id += 1;
+                }
+                taken.push(next_id);
+                next_id
+            }
+        })
+        .collect()
+}
+
+// Names resolve only when unique, like the decompiler produces them
+fn lookup(declarations: &[Declaration], ids: &[u32]) -> (HashMap<String, Option<u32>>, Vec<u32>) {
+    let mut by_name = HashMap::new();
+    for (declaration, &id) in declarations.iter().zip(ids.iter()) {
+        by_name
+            .entry(declaration.name.clone())
+            .and_modify(|entry: &mut Option<u32>| *entry = None)
+            .or_insert(Some(id));
+    }
+    (by_name, ids.to_vec())
+}
+
+fn resolve(
+    reference: &Reference,
+    lookup: &(HashMap<String, Option<u32>>, Vec<u32>),
+    kind: &str,
+    line: usize,
+    errors: &mut Vec<ScriptError>
+) -> u32 {
+    match reference {
+        Reference::Id(0) => 0,
+        Reference::Id(id) => {
+            if !lookup.1.contains(id) {
+                errors.push(error(line, format!("there is no {} #{}", kind, id)));
+            }
+            *id
+        },
+        Reference::Name(name) => match lookup.0.get(name) {
+            Some(Some(id)) => *id,
+            Some(None) => {
+                errors.push(error(line, format!("{} name \"{}\" is ambiguous, use its #id", kind, name)));
+                0
+            },
+            None => {
+                errors.push(error(line, format!("there is no {} named \"{}\"", kind, name)));
+                0
+            }
+        }
+    }
+}
+
+fn compile_trigger(
+    trigger: &TriggerDeclaration,
+    checks: &(HashMap<String, Option<u32>>, Vec<u32>),
+    instances: &(HashMap<String, Option<u32>>, Vec<u32>),
+    errors: &mut Vec<ScriptError>
+) -> Option<TriggerEntry> {
+    let errors_before = errors.len();
+    if encode_cp866(&trigger.name).len() > TRIGGER_NAME_CAPACITY {
+        errors.push(error(trigger.line, format!("trigger name is longer than {} bytes", TRIGGER_NAME_CAPACITY)));
+    }
+    if trigger.conditions.len() > MAX_CONDITIONS_PER_TRIGGER {
+        errors.push(error(trigger.line, format!("at most {} conditions are allowed", MAX_CONDITIONS_PER_TRIGGER)));
+    }
+    if trigger.actions.len() > MAX_INSTANCES_PER_TRIGGER {
+        errors.push(error(trigger.line, format!("at most {} instances are allowed", MAX_INSTANCES_PER_TRIGGER)));
+    }
+    let mut check_identifiers = [0u32; 6];
+    let mut operators = [None; MAX_CONDITIONS_PER_TRIGGER];
+    for (i, condition) in trigger.conditions.iter().take(MAX_CONDITIONS_PER_TRIGGER).enumerate() {
+        check_identifiers[i * 2] = resolve(&condition.left, checks, "check", condition.line, errors);
+        check_identifiers[i * 2 + 1] = resolve(&condition.right, checks, "check", condition.line, errors);
+        operators[i] = match condition.operator.as_str() {
+            "?" => None,
+            symbol => find_operator(symbol)
+        };
+    }
+    let mut instance_identifiers = [0u32; MAX_INSTANCES_PER_TRIGGER];
+    for (i, action) in trigger.actions.iter().take(MAX_INSTANCES_PER_TRIGGER).enumerate() {
+        instance_identifiers[i] = resolve(&action.target, instances, "instance", action.line, errors);
+    }
+    if errors.len() > errors_before {
+        return None;
+    }
+    Some(TriggerEntry {
+        name: trigger.name.clone(),
+        check_identifiers,
+        instance_identifiers,
+        check_01_operator: operators[0],
+        check_23_operator: operators[1],
+        check_45_operator: operators[2],
+        operator_values: [NO_OPERATOR; 3],
+        run_once: trigger.once
+    })
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Identifier(String),
+    Text(String),
+    Number(i64),
+    Id(u32),
+    Symbol(&'static str)
+}
+
+const SYMBOLS: [&str; 15] = ["==", "!=", ">=", "<=", ">", "<", "?", "=", "(", ")", ",", ":", ";", "{", "}"];
+
+fn tokenize(script: &str) -> Result<Vec<(Token, usize)>, Vec<ScriptError>> {
+    let mut tokens = Vec::new();
+    let mut errors = Vec::new();
+    let chars: Vec<char> = script.chars().collect();
+    let (mut i, mut line) = (0, 1);
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '/' && chars.get(i + 1) == Some(&'/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '"' {
+            let start_line = line;
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None | Some('\n') => {
+                        errors.push(error(start_line, "unterminated string".to_string()));
+                        break;
+                    },
+                    Some('"') => {
+                        i += 1;
+                        break;
+                    },
+                    Some('\\') if i + 1 < chars.len() => {
+                        text.push(chars[i + 1]);
+                        i += 2;
+                    },
+                    Some(&other) => {
+                        text.push(other);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push((Token::Text(text), start_line));
+        } else if c == '#' || c == '-' || c.is_ascii_digit() {
+            let start = i;
+            i += 1;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let literal: String = chars[start..i].iter().collect();
+            let token = if c == '#' {
+                literal[1..].parse::<u32>().map(Token::Id).ok()
+            } else {
+                literal.parse::<i64>().map(Token::Number).ok()
+            };
+            match token {
+                Some(token) => tokens.push((token, line)),
+                None => errors.push(error(line, format!("malformed number {}", literal)))
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((Token::Identifier(chars[start..i].iter().collect()), line));
+        } else {
+            let rest: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+            match SYMBOLS.iter().find(|symbol| rest.starts_with(*symbol)) {
+                Some(symbol) => {
+                    tokens.push((Token::Symbol(symbol), line));
+                    i += symbol.len();
+                },
+                None => {
+                    errors.push(error(line, format!("unexpected character '{}'", c)));
+                    i += 1;
+                }
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(tokens)
+    } else {
+        Err(errors)
+    }
+}
+
+struct Argument {
+    name: String,
+    type_name: String,
+    value: i64,
+    line: usize
+}
+
+struct Declaration {
+    id: Option<u32>,
+    name: String,
+    once: u32,
+    type_name: String,
+    arguments: Vec<Argument>,
+    line: usize
+}
+
+enum Reference {
+    Id(u32),
+    Name(String)
+}
+
+struct Condition {
+    left: Reference,
+    operator: String,
+    right: Reference,
+    line: usize
+}
+
+struct Action {
+    target: Reference,
+    line: usize
+}
+
+struct TriggerDeclaration {
+    name: String,
+    once: u32,
+    conditions: Vec<Condition>,
+    actions: Vec<Action>,
+    line: usize
+}
+
+enum Item {
+    Instance(Declaration),
+    Check(Declaration),
+    Trigger(TriggerDeclaration)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    errors: Vec<ScriptError>
+}
+impl Parser {
+    fn parse_script(&mut self) -> Vec<Item> {
+        let mut items = Vec::new();
+        while self.position < self.tokens.len() {
+            let line = self.line();
+            let item = match self.next() {
+                Some(Token::Identifier(keyword)) if keyword == "instance" => self.declaration(line).map(Item::Instance),
+                Some(Token::Identifier(keyword)) if keyword == "check" => self.declaration(line).map(Item::Check),
+                Some(Token::Identifier(keyword)) if keyword == "trigger" => self.trigger(line).map(Item::Trigger),
+                _ => Err(error(line, "expected instance, check or trigger".to_string()))
+            };
+            match item {
+                Ok(item) => items.push(item),
+                Err(e) => {
+                    self.errors.push(e);
+                    self.recover();
+                }
+            }
+        }
+        items
+    }
+
+    // Skips to the start of the next top level item
+    fn recover(&mut self) {
+        while let Some((token, _)) = self.tokens.get(self.position) {
+            if let Token::Identifier(keyword) = token {
+                if keyword == "instance" || keyword == "check" || keyword == "trigger" {
+                    return;
+                }
+            }
+            self.position += 1;
+        }
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .or_else(|| self.tokens.last())
+            .map_or(1, |(_, line)| *line)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).map(|(token, _)| token.clone());
+        self.position += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), ScriptError> {
+        let line = self.line();
+        match self.next() {
+            Some(Token::Symbol(found)) if found == symbol => Ok(()),
+            _ => Err(error(line, format!("expected '{}'", symbol)))
+        }
+    }
+
+    fn text(&mut self) -> Result<String, ScriptError> {
+        let line = self.line();
+        match self.next() {
+            Some(Token::Text(text)) => Ok(text),
+            _ => Err(error(line, "expected a quoted name".to_string()))
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, ScriptError> {
+        let line = self.line();
+        match self.next() {
+            Some(Token::Identifier(identifier)) => Ok(identifier),
+            _ => Err(error(line, "expected a name".to_string()))
+        }
+    }
+
+    fn once_flag(&mut self) -> Result<u32, ScriptError> {
+        if self.peek() != Some(&Token::Identifier("once".to_string())) {
+            return Ok(0);
+        }
+        self.position += 1;
+        if self.peek() != Some(&Token::Symbol("(")) {
+            return Ok(1);
+        }
+        self.position += 1;
+        let line = self.line();
+        let value = match self.next() {
+            Some(Token::Number(value)) if value >= 0 && value <= u32::MAX as i64 => value as u32,
+            _ => return Err(error(line, "expected a non negative number".to_string()))
+        };
+        self.expect(")")?;
+        Ok(value)
+    }
+
+    fn reference(&mut self) -> Result<Reference, ScriptError> {
+        let line = self.line();
+        match self.next() {
+            Some(Token::Id(id)) => Ok(Reference::Id(id)),
+            Some(Token::Text(name)) => Ok(Reference::Name(name)),
+            _ => Err(error(line, "expected a quoted name or #id".to_string()))
+        }
+    }
+
+    fn declaration(&mut self, line: usize) -> Result<Declaration, ScriptError> {
+        let id = match self.peek() {
+            Some(&Token::Id(id)) => {
+                self.position += 1;
+                Some(id)
+            },
+            _ => None
+        };
+        let name = self.text()?;
+        let once = self.once_flag()?;
+        self.expect("=")?;
+        let type_name = self.identifier()?;
+        self.expect("(")?;
+        let mut arguments = Vec::new();
+        if self.peek() != Some(&Token::Symbol(")")) {
+            loop {
+                arguments.push(self.argument()?);
+                if self.peek() == Some(&Token::Symbol(",")) {
+                    self.position += 1;
+                } else {
+                    break;
+                }
+            }
+        }
+        self.expect(")")?;
+        self.expect(";")?;
+        Ok(Declaration { id, name, once, type_name, arguments, line })
+    }
+
+    fn argument(&mut self) -> Result<Argument, ScriptError> {
+        let line = self.line();
+        let name = match self.next() {
+            Some(Token::Identifier(name)) | Some(Token::Text(name)) => name,
+            _ => return Err(error(line, "expected an argument name".to_string()))
+        };
+        self.expect(":")?;
+        let type_name = self.identifier()?;
+        self.expect("=")?;
+        let value_line = self.line();
+        let value = match self.next() {
+            Some(Token::Number(value)) => value,
+            _ => return Err(error(value_line, format!("expected a number for argument {}", name)))
+        };
+        Ok(Argument { name, type_name, value, line })
+    }
+
+    fn trigger(&mut self, line: usize) -> Result<TriggerDeclaration, ScriptError> {
+        let name = self.text()?;
+        let once = self.once_flag()?;
+        self.expect("{")?;
+        let mut conditions = Vec::new();
+        let mut actions = Vec::new();
+        loop {
+            let statement_line = self.line();
+            match self.next() {
+                Some(Token::Symbol("}")) => break,
+                Some(Token::Identifier(keyword)) if keyword == "if" => {
+                    let left = self.reference()?;
+                    let operator_line = self.line();
+                    let operator = match self.next() {
+                        Some(Token::Symbol(symbol)) if symbol == "?" || find_operator(symbol).is_some() => symbol,
+                        _ => return Err(error(operator_line, "expected a comparison operator".to_string()))
+                    };
+                    let right = self.reference()?;
+                    self.expect(";")?;
+                    conditions.push(Condition { left, operator: operator.to_string(), right, line: statement_line });
+                },
+                Some(Token::Identifier(keyword)) if keyword == "do" => {
+                    let target = self.reference()?;
+                    self.expect(";")?;
+                    actions.push(Action { target, line: statement_line });
+                },
+                _ => return Err(error(statement_line, "expected if, do or '}'".to_string()))
+            }
+        }
+        Ok(TriggerDeclaration { name, once, conditions, actions, line })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alm::*;
+    use bin_serialization_rs::Endianness;
+
+    const SCRIPT: &str = "\
+instance #1 \"Say \\\"hi\\\"\" once = SendMessage(message: Number = 12);
+instance #2 \"Start\" = StartHere();
+instance #3 \"Jump\" once(2) = MoveUnitImmediate(unit: Unit = 3, x: X = 300, y: Y = 1024);
+
+check #1 \"Hero\" = IsUnitAlive(hero: Unit = 3);
+check #2 \"dup\" once = Constant(\"my value\": Number = -5);
+check #3 \"dup\" = Constant(value: Number = 0);
+check #4 \"Shuffled\" = IsUnitInACircle(radius: Number = 4, x: X = 700, unit: Unit = 3, y: Y = 9, extra: Unknown = 1);
+
+trigger \"Lose\" once {
+    if \"Hero\" != #2;
+    if #3 ? \"Shuffled\";
+    do \"Say \\\"hi\\\"\";
+    do \"Start\";
+}
+
+trigger \"Empty\" {
+}
+";
+
+    fn section_bytes(section: &TriggersSection) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        section.write_to_stream(&mut bytes, Endianness::LittleEndian).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_compile_decompile_round_trip() {
+        let section = compile_triggers(SCRIPT).unwrap();
+        assert_eq!(section.instances.len(), 3);
+        assert_eq!(section.checks.len(), 4);
+        assert_eq!(section.triggers[0].check_identifiers, [1, 2, 3, 4, 0, 0]);
+        assert_eq!(section.triggers[0].instance_identifiers, [1, 2, 0, 0]);
+        assert_eq!(
+            section.instances[2].arguments(),
+            Ok(InstanceArguments::MoveUnitImmediate { unit: 3, x: 300, y: 1024 })
+        );
+        assert_eq!(
+            section.checks[3].arguments(),
+            Ok(CheckArguments::IsUnitInACircle { unit: 3, x: 700, y: 9, radius: 4 })
+        );
+
+        let script = decompile_triggers(&section);
+        assert_eq!(script, SCRIPT);
+        let recompiled = compile_triggers(&script).unwrap();
+        assert_eq!(section_bytes(&recompiled), section_bytes(&section));
+    }
+
+    #[test]
+    fn test_argument_types_follow_the_kind() {
+        let errors = compile_triggers("check \"A\" = IsUnitAlive(x: Number = 1);").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "IsUnitAlive: no Unit argument #0 for unit");
+        let errors = compile_triggers("instance \"B\" = SetDiplomacy(a: Fraction = 1, state: Number = 2);").unwrap_err();
+        assert_eq!(errors[0].message, "SetDiplomacy: no Fraction argument #1 for b");
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        let script = "instance \"A\" = SendMessage(message: Number = 4294967295);\n\
+                      check \"B\" = IsUnitAlive(unit: Unit = -3);\n\
+                      check \"C\" = Bogus();\n\
+                      trigger \"T\" {\n if \"B\" == \"Z\";\n do \"A\";\n}\n\
+                      instance #7 \"D\" = StartHere();\n\
+                      instance #7 \"E\" = StartHere();";
+        let errors = compile_triggers(script).unwrap_err();
+        let lines: Vec<usize> = errors.iter().map(|error| error.line).collect();
+        assert_eq!(lines, vec![1, 2, 3, 5, 9]);
+    }
+}
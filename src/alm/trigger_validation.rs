@@ -0,0 +1,307 @@
+use std::collections::HashSet;
+use super::*;
+use super::trigger_enums::*;
+use super::trigger_vm::{Arguments, compare};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TriggerLocation {
+    Trigger(usize), // index in TriggersSection::triggers
+    Check(u32),
+    Instance(u32)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TriggerIssueKind {
+    MissingCheck { check_id: u32 },
+    MissingInstance { instance_id: u32 },
+    MissingUnit { unit_id: u32 },
+    MissingGroup { group_id: u32 },
+    MissingFraction { fraction_id: u32 },
+    MissingStructure { structure_id: u32 },
+    MissingItem { item_id: u32 }, // neither in a sack nor given by an instance
+    CoordinateOutOfBounds { argument_type: ArgumentType, value: u32 },
+    PairWithoutOperator { pair: usize }, // the reader drops the operator of half-empty pairs too
+    ConstantCondition { pair: usize, always: bool },
+    CheckNeverChanges { value: i32 },
+    MissionStageWithoutFiringTrigger
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TriggerIssue {
+    pub location: TriggerLocation,
+    pub kind: TriggerIssueKind
+}
+impl std::fmt::Display for TriggerIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.location {
+            TriggerLocation::Trigger(idx) => write!(f, "trigger {}: ", idx)?,
+            TriggerLocation::Check(id) => write!(f, "check #{}: ", id)?,
+            TriggerLocation::Instance(id) => write!(f, "instance #{}: ", id)?
+        }
+        match &self.kind {
+            TriggerIssueKind::MissingCheck { check_id } => write!(f, "there is no check #{}", check_id),
+            TriggerIssueKind::MissingInstance { instance_id } => write!(f, "there is no instance #{}", instance_id),
+            TriggerIssueKind::MissingUnit { unit_id } => write!(f, "there is no unit {}", unit_id),
+            TriggerIssueKind::MissingGroup { group_id } => write!(f, "no unit is in group {}", group_id),
+            TriggerIssueKind::MissingFraction { fraction_id } => write!(f, "there is no fraction {}", fraction_id),
+            TriggerIssueKind::MissingStructure { structure_id } => write!(f, "there is no structure {}", structure_id),
+            TriggerIssueKind::MissingItem { item_id } => write!(f, "item {} is in no sack and never given", item_id),
+            TriggerIssueKind::CoordinateOutOfBounds { argument_type, value } => {
+                write!(f, "{:?} coordinate {} is outside the map", argument_type, value)
+            },
+            TriggerIssueKind::PairWithoutOperator { pair } => {
+                write!(f, "check pair {} has no operator or only one check and is ignored", pair)
+            },
+            TriggerIssueKind::ConstantCondition { pair, always } => write!(f, "check pair {} is always {}", pair, always),
+            TriggerIssueKind::CheckNeverChanges { value } => write!(f, "the result never changes from {}", value),
+            TriggerIssueKind::MissionStageWithoutFiringTrigger => {
+                write!(
+                    f,
+                    "the mission stage increment is only in triggers whose conditions never hold \
+                     (triggers enabling each other are not followed)"
+                )
+            }
+        }
+    }
+}
+
+impl TriggersSection {
+    pub fn validate(&self, map: &AlmMap) -> Vec<TriggerIssue> {
+        let mut issues = Vec::new();
+        let context = ValidationContext::new(self, map);
+
+        for check in self.checks.iter() {
+            let location = TriggerLocation::Check(check.id);
+            validate_arguments(&check.argument_values, &check.argument_types, location, &context, &mut issues);
+            if !matches!(check.check_type, CheckType::Constant) {
+                if let Some(value) = context.known_value(check) {
+                    issues.push(TriggerIssue { location, kind: TriggerIssueKind::CheckNeverChanges { value } });
+                }
+            }
+        }
+        for instance in self.instances.iter() {
+            let location = TriggerLocation::Instance(instance.id);
+            validate_arguments(&instance.argument_values, &instance.argument_types, location, &context, &mut issues);
+        }
+
+        let mut firing_instances = HashSet::new();
+        for (idx, trigger) in self.triggers.iter().enumerate() {
+            let location = TriggerLocation::Trigger(idx);
+            let mut can_fire = true;
+            let operators = [trigger.check_01_operator, trigger.check_23_operator, trigger.check_45_operator];
+            for (pair, (ids, operator)) in trigger.check_identifiers.chunks(2).zip(operators.iter()).enumerate() {
+                for &check_id in ids.iter().filter(|&&id| id != 0) {
+                    if !self.checks.iter().any(|check| check.id == check_id) {
+                        issues.push(TriggerIssue { location, kind: TriggerIssueKind::MissingCheck { check_id } });
+                    }
+                }
+                if ids[0] == 0 && ids[1] == 0 {
+                    continue;
+                }
+                let operator = match operator {
+                    Some(operator) => *operator,
+                    None => {
+                        issues.push(TriggerIssue { location, kind: TriggerIssueKind::PairWithoutOperator { pair } });
+                        continue;
+                    }
+                };
+                // a missing check is reported above and tells nothing about the pair
+                let known = |check_id: u32| {
+                    self.checks.iter().find(|check| check.id == check_id).and_then(|check| context.known_value(check))
+                };
+                if let (Some(left), Some(right)) = (known(ids[0]), known(ids[1])) {
+                    let always = compare(operator, left, right);
+                    can_fire &= always;
+                    issues.push(TriggerIssue { location, kind: TriggerIssueKind::ConstantCondition { pair, always } });
+                }
+            }
+            for &instance_id in trigger.instance_identifiers.iter().filter(|&&id| id != 0) {
+                if !self.instances.iter().any(|instance| instance.id == instance_id) {
+                    issues.push(TriggerIssue { location, kind: TriggerIssueKind::MissingInstance { instance_id } });
+                } else if can_fire {
+                    firing_instances.insert(instance_id);
+                }
+            }
+        }
+
+        // Only conditions that are constant for the whole mission rule a trigger out,
+        // chains of triggers enabling each other are not followed
+        for instance in self.instances.iter() {
+            let increments_stage = matches!(
+                instance.instance_type,
+                InstanceType::General(GeneralInstanceType::IncrementMissionStage)
+            );
+            if increments_stage && !firing_instances.contains(&instance.id) {
+                issues.push(TriggerIssue {
+                    location: TriggerLocation::Instance(instance.id),
+                    kind: TriggerIssueKind::MissionStageWithoutFiringTrigger
+                });
+            }
+        }
+        issues
+    }
+}
+
+struct ValidationContext<'a> {
+    map: &'a AlmMap,
+    written_variables: HashSet<u32>,
+    written_sub_objectives: HashSet<u32>,
+    known_items: HashSet<u32>
+}
+impl<'a> ValidationContext<'a> {
+    fn new(triggers: &TriggersSection, map: &'a AlmMap) -> Self {
+        let mut written_variables = HashSet::new();
+        let mut written_sub_objectives = HashSet::new();
+        let mut known_items: HashSet<u32> = map.sacks
+            .iter()
+            .flat_map(|sacks| sacks.sacks.iter())
+            .flat_map(|sack| sack.items.iter())
+            .map(|item| item.id)
+            .collect();
+        for instance in triggers.instances.iter() {
+            let args = Arguments::of_instance(instance);
+            match instance.instance_type {
+                InstanceType::General(GeneralInstanceType::SetVariableValue)
+                | InstanceType::General(GeneralInstanceType::IncrementVariable) => {
+                    written_variables.insert(args.number(0) as u32);
+                },
+                InstanceType::General(GeneralInstanceType::SetSubObjective) => {
+                    written_sub_objectives.insert(args.number(0) as u32);
+                },
+                InstanceType::General(GeneralInstanceType::GiveItem)
+                | InstanceType::General(GeneralInstanceType::AddItemInUnitsSack) => {
+                    known_items.insert(args.nth(ArgumentType::Item, 0));
+                },
+                _ => {}
+            }
+        }
+        Self { map, written_variables, written_sub_objectives, known_items }
+    }
+
+    // Scenario variables carry over from other missions, so only mission variables
+    // and sub-objectives nobody writes are known to stay 0
+    fn known_value(&self, check: &CheckEntry) -> Option<i32> {
+        let args = Arguments::of_check(check);
+        match check.check_type {
+            CheckType::Constant => Some(args.number(0)),
+            CheckType::General(GeneralCheckType::CheckVariable) => {
+                (!self.written_variables.contains(&(args.number(0) as u32))).then_some(0)
+            },
+            CheckType::General(GeneralCheckType::CheckSubObjective) => {
+                (!self.written_sub_objectives.contains(&(args.number(0) as u32))).then_some(0)
+            },
+            _ => None
+        }
+    }
+
+    fn has_unit(&self, unit_id: u32) -> bool {
+        self.map.units.iter().flat_map(|units| units.units.iter()).any(|unit| unit.unit_id as u32 == unit_id)
+    }
+
+    fn has_group(&self, group_id: u32) -> bool {
+        self.map.units.iter().flat_map(|units| units.units.iter()).any(|unit| unit.group_id == group_id)
+    }
+
+    fn has_fraction(&self, fraction_id: u32) -> bool {
+        self.map.fractions.as_ref().is_some_and(|fractions| (fraction_id as usize) < fractions.fractions.len())
+    }
+
+    fn has_structure(&self, structure_id: u32) -> bool {
+        self.map.structures
+            .iter()
+            .flat_map(|structures| structures.structures.iter())
+            .any(|structure| structure.id as u32 == structure_id)
+    }
+}
+
+fn validate_arguments(
+    values: &[u32; 10],
+    types: &[ArgumentType],
+    location: TriggerLocation,
+    context: &ValidationContext,
+    issues: &mut Vec<TriggerIssue>
+) {
+    let (width, height) = (context.map.general_info.width, context.map.general_info.height);
+    for (&argument_type, &value) in types.iter().zip(values.iter()) {
+        let kind = match argument_type {
+            ArgumentType::Unit if !context.has_unit(value) => TriggerIssueKind::MissingUnit { unit_id: value },
+            ArgumentType::Group if !context.has_group(value) => TriggerIssueKind::MissingGroup { group_id: value },
+            ArgumentType::Fraction if !context.has_fraction(value) => {
+                TriggerIssueKind::MissingFraction { fraction_id: value }
+            },
+            ArgumentType::Structure if !context.has_structure(value) => {
+                TriggerIssueKind::MissingStructure { structure_id: value }
+            },
+            ArgumentType::Item if !context.known_items.contains(&value) => TriggerIssueKind::MissingItem { item_id: value },
+            ArgumentType::X if value >= width => TriggerIssueKind::CoordinateOutOfBounds { argument_type, value },
+            ArgumentType::Y if value >= height => TriggerIssueKind::CoordinateOutOfBounds { argument_type, value },
+            _ => continue
+        };
+        issues.push(TriggerIssue { location, kind });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alm::*;
+    use crate::alm::trigger_enums::*;
+
+    #[test]
+    fn test_every_issue_kind_is_reported() {
+        let mut builder = AlmMapBuilder::new(16, 16);
+        let fraction = builder.add_fraction("Player", 0, 0, 0);
+        let hero = builder.add_unit(UnitEntry::new(5 << 8, 5 << 8, 1, fraction));
+        let mut map = builder.build();
+        let script = format!(
+            "check \"Hero\" = IsUnitAlive(unit: Unit = 77);\n\
+             check \"Group\" = GroupUnitCount(group: Group = 9);\n\
+             check \"Fraction\" = HowManyUnitsFractionHave(fraction: Fraction = 4);\n\
+             check \"Tower\" = GetStructureHealth(structure: Structure = 2);\n\
+             check \"Far\" = IsUnitInPoint(unit: Unit = {hero}, x: X = 16, y: Y = 3);\n\
+             check \"Var\" = CheckVariable(variable: Number = 7);\n\
+             check \"Zero\" = Constant(value: Number = 0);\n\
+             instance \"Stage\" = IncrementMissionStage();\n\
+             instance \"Take\" = RemoveItemFromAll(item_id: Item = 5);\n\
+             trigger \"Dead\" {{ if \"Var\" != \"Zero\"; do \"Stage\"; do \"Take\"; }}\n\
+             trigger \"Broken\" {{ if \"Hero\" == \"Group\"; if \"Fraction\" == \"Tower\"; if \"Far\" == \"Zero\"; }}\n"
+        );
+        let mut triggers = compile_triggers(&script).unwrap();
+        triggers.triggers[0].instance_identifiers[2] = 90;
+        triggers.triggers[1].check_identifiers[3] = 0;
+        triggers.triggers[1].check_23_operator = None;
+        triggers.triggers[1].check_identifiers[5] = 40;
+        // "Zero" against a missing check is no constant condition
+        triggers.triggers[1].check_identifiers[4] = triggers.triggers[0].check_identifiers[1];
+        let check = |name: &str| triggers.checks.iter().find(|check| check.name == name).unwrap().id;
+        let instance = |name: &str| triggers.instances.iter().find(|instance| instance.name == name).unwrap().id;
+
+        let expected = vec![
+            (TriggerLocation::Check(check("Hero")), TriggerIssueKind::MissingUnit { unit_id: 77 }),
+            (TriggerLocation::Check(check("Group")), TriggerIssueKind::MissingGroup { group_id: 9 }),
+            (TriggerLocation::Check(check("Fraction")), TriggerIssueKind::MissingFraction { fraction_id: 4 }),
+            (TriggerLocation::Check(check("Tower")), TriggerIssueKind::MissingStructure { structure_id: 2 }),
+            (
+                TriggerLocation::Check(check("Far")),
+                TriggerIssueKind::CoordinateOutOfBounds { argument_type: ArgumentType::X, value: 16 }
+            ),
+            (TriggerLocation::Check(check("Var")), TriggerIssueKind::CheckNeverChanges { value: 0 }),
+            (TriggerLocation::Instance(instance("Take")), TriggerIssueKind::MissingItem { item_id: 5 }),
+            (TriggerLocation::Trigger(0), TriggerIssueKind::ConstantCondition { pair: 0, always: false }),
+            (TriggerLocation::Trigger(0), TriggerIssueKind::MissingInstance { instance_id: 90 }),
+            (TriggerLocation::Trigger(1), TriggerIssueKind::PairWithoutOperator { pair: 1 }),
+            (TriggerLocation::Trigger(1), TriggerIssueKind::MissingCheck { check_id: 40 }),
+            (TriggerLocation::Instance(instance("Stage")), TriggerIssueKind::MissionStageWithoutFiringTrigger)
+        ];
+        map.triggers = Some(triggers);
+        let issues = map.triggers.as_ref().unwrap().validate(&map);
+        for (location, kind) in expected.iter() {
+            assert!(
+                issues.iter().any(|issue| issue.location == *location && issue.kind == *kind),
+                "{:?} {:?} not reported", location, kind
+            );
+        }
+        assert_eq!(issues.len(), expected.len());
+        let stage = issues.iter().find(|issue| issue.kind == TriggerIssueKind::MissionStageWithoutFiringTrigger).unwrap();
+        assert!(stage.to_string().contains("not followed"));
+    }
+}
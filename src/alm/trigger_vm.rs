@@ -0,0 +1,671 @@
+use std::collections::{HashMap, HashSet};
+use super::*;
+use super::trigger_enums::*;
+
+// The map doesn't store unit health, that comes from data.bin
+const DEFAULT_UNIT_HEALTH: i32 = 100;
+const UNREACHABLE_DISTANCE: i32 = i32::MAX;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MissionOutcome {
+    InProgress,
+    Complete,
+    Failed
+}
+
+#[derive(Clone, Debug)]
+pub struct UnitState {
+    pub unit_id: u16,
+    pub type_id: u16,
+    pub fraction_id: u32,
+    pub group_id: u32,
+    pub x: u32, // tiles
+    pub y: u32,
+    pub health: i32,
+    pub hidden: bool,
+    pub attacked: bool, // reset at the end of every tick
+    pub parameters: HashMap<u32, i32>,
+    pub money: u32,
+    pub items: Vec<ItemEntry>
+}
+impl UnitState {
+    pub fn is_alive(&self) -> bool {
+        self.health > 0
+    }
+
+    fn distance_to(&self, x: u32, y: u32) -> i32 {
+        (self.x as i64 - x as i64).abs().max((self.y as i64 - y as i64).abs()) as i32
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StructureState {
+    pub id: u16,
+    pub type_id: u32,
+    pub fraction_id: u32,
+    pub health: i32
+}
+
+#[derive(Clone, Debug)]
+pub struct FractionState {
+    pub money: u32,
+    pub diplomacy_states: [u16; 0x10]
+}
+
+#[derive(Clone, Debug)]
+pub struct WorldState {
+    pub units: Vec<UnitState>,
+    pub structures: Vec<StructureState>,
+    pub fractions: Vec<FractionState>,
+    pub variables: HashMap<u32, i32>,
+    pub scenario_variables: HashMap<u32, i32>,
+    pub sub_objectives: HashMap<u32, i32>,
+    pub mission_stage: u32,
+    pub messages: Vec<u32>,
+    pub outcome: MissionOutcome
+}
+impl WorldState {
+    pub fn from_map(map: &AlmMap) -> Self {
+        let sacks: Vec<&SackEntry> = map.sacks.iter().flat_map(|sacks| sacks.sacks.iter()).collect();
+        let units = map.units
+            .iter()
+            .flat_map(|units| units.units.iter())
+            .map(|unit| {
+                let (x, y) = unit.tile_position();
+                let sack = sacks.iter().find(|sack| sack.unit_id == unit.unit_id as u32);
+                UnitState {
+                    unit_id: unit.unit_id,
+                    type_id: unit.type_id,
+                    fraction_id: unit.fraction_id,
+                    group_id: unit.group_id,
+                    x,
+                    y,
+                    health: DEFAULT_UNIT_HEALTH,
+                    hidden: false,
+                    attacked: false,
+                    parameters: HashMap::new(),
+                    money: sack.map_or(0, |sack| sack.money),
+                    items: sack.map_or(Vec::new(), |sack| sack.items.clone())
+                }
+            })
+            .collect();
+        let structures = map.structures
+            .iter()
+            .flat_map(|structures| structures.structures.iter())
+            .map(|structure| StructureState {
+                id: structure.id,
+                type_id: structure.type_id,
+                fraction_id: structure.fraction_id,
+                health: structure.health as i32
+            })
+            .collect();
+        let fractions = map.fractions
+            .iter()
+            .flat_map(|fractions| fractions.fractions.iter())
+            .map(|fraction| FractionState {
+                money: fraction.money,
+                diplomacy_states: fraction.diplomacy_states
+            })
+            .collect();
+        Self {
+            units,
+            structures,
+            fractions,
+            variables: HashMap::new(),
+            scenario_variables: HashMap::new(),
+            sub_objectives: HashMap::new(),
+            mission_stage: 0,
+            messages: Vec::new(),
+            outcome: MissionOutcome::InProgress
+        }
+    }
+
+    pub fn unit(&self, unit_id: u32) -> Option<&UnitState> {
+        self.units.iter().find(|unit| unit.unit_id as u32 == unit_id)
+    }
+
+    pub fn unit_mut(&mut self, unit_id: u32) -> Option<&mut UnitState> {
+        self.units.iter_mut().find(|unit| unit.unit_id as u32 == unit_id)
+    }
+
+    pub fn structure(&self, id: u32) -> Option<&StructureState> {
+        self.structures.iter().find(|structure| structure.id as u32 == id)
+    }
+
+    pub fn structure_mut(&mut self, id: u32) -> Option<&mut StructureState> {
+        self.structures.iter_mut().find(|structure| structure.id as u32 == id)
+    }
+
+    // Events a test scripts in between ticks; false when there is no such unit or structure
+    pub fn kill_unit(&mut self, unit_id: u32) -> bool {
+        self.unit_mut(unit_id).map(|unit| unit.health = 0).is_some()
+    }
+
+    pub fn move_unit(&mut self, unit_id: u32, x: u32, y: u32) -> bool {
+        self.unit_mut(unit_id).map(|unit| {
+            unit.x = x;
+            unit.y = y;
+        }).is_some()
+    }
+
+    pub fn attack_unit(&mut self, unit_id: u32) -> bool {
+        self.unit_mut(unit_id).map(|unit| unit.attacked = true).is_some()
+    }
+
+    pub fn destroy_structure(&mut self, id: u32) -> bool {
+        self.structure_mut(id).map(|structure| structure.health = 0).is_some()
+    }
+
+    fn living_units(&self) -> impl Iterator<Item = &UnitState> {
+        self.units.iter().filter(|unit| unit.is_alive())
+    }
+
+    fn alive(&self, unit_id: u32) -> Option<&UnitState> {
+        self.unit(unit_id).filter(|unit| unit.is_alive())
+    }
+}
+
+// Arguments are read the same way as the typed CheckArguments and InstanceArguments,
+// a missing one reads as 0
+pub(crate) struct Arguments<'a> {
+    values: &'a [u32; 10],
+    types: &'a [ArgumentType]
+}
+impl<'a> Arguments<'a> {
+    pub(crate) fn of_check(check: &'a CheckEntry) -> Self {
+        Self { values: &check.argument_values, types: &check.argument_types }
+    }
+
+    pub(crate) fn of_instance(instance: &'a InstanceEntry) -> Self {
+        Self { values: &instance.argument_values, types: &instance.argument_types }
+    }
+
+    pub(crate) fn nth(&self, argument_type: ArgumentType, n: usize) -> u32 {
+        nth_argument(self.values, self.types, argument_type, n).unwrap_or(0)
+    }
+
+    pub(crate) fn number(&self, n: usize) -> i32 {
+        self.nth(ArgumentType::Number, n) as i32
+    }
+
+    fn unit(&self, n: usize) -> u32 {
+        self.nth(ArgumentType::Unit, n)
+    }
+
+    fn group(&self) -> u32 {
+        self.nth(ArgumentType::Group, 0)
+    }
+
+    fn fraction(&self, n: usize) -> u32 {
+        self.nth(ArgumentType::Fraction, n)
+    }
+
+    fn item(&self) -> u32 {
+        self.nth(ArgumentType::Item, 0)
+    }
+
+    fn structure(&self) -> u32 {
+        self.nth(ArgumentType::Structure, 0)
+    }
+
+    fn point(&self, n: usize) -> (u32, u32) {
+        (self.nth(ArgumentType::X, n), self.nth(ArgumentType::Y, n))
+    }
+}
+
+// Runs the triggers of a map against a WorldState, one pass over all the triggers per tick
+pub struct TriggerVm<'a> {
+    pub world: WorldState,
+    pub ticks: u32,
+    triggers: Option<&'a TriggersSection>,
+    checks: HashMap<u32, &'a CheckEntry>,
+    instances: HashMap<u32, &'a InstanceEntry>,
+    fired_triggers: HashSet<usize>,
+    executed_instances: HashSet<u32>,
+    latched_checks: HashMap<u32, i32>
+}
+impl<'a> TriggerVm<'a> {
+    pub fn new(world: WorldState, triggers: &'a TriggersSection) -> Self {
+        Self::with_section(world, Some(triggers))
+    }
+
+    pub fn from_map(map: &'a AlmMap) -> Self {
+        Self::with_section(WorldState::from_map(map), map.triggers.as_ref())
+    }
+
+    fn with_section(world: WorldState, triggers: Option<&'a TriggersSection>) -> Self {
+        let checks = triggers
+            .iter()
+            .flat_map(|section| section.checks.iter())
+            .map(|check| (check.id, check))
+            .collect();
+        let instances = triggers
+            .iter()
+            .flat_map(|section| section.instances.iter())
+            .map(|instance| (instance.id, instance))
+            .collect();
+        Self {
+            world,
+            ticks: 0,
+            triggers,
+            checks,
+            instances,
+            fired_triggers: HashSet::new(),
+            executed_instances: HashSet::new(),
+            latched_checks: HashMap::new()
+        }
+    }
+
+    pub fn outcome(&self) -> MissionOutcome {
+        self.world.outcome
+    }
+
+    // Returns the indices of the triggers that fired
+    pub fn tick(&mut self) -> Vec<usize> {
+        let mut fired = Vec::new();
+        if self.world.outcome != MissionOutcome::InProgress {
+            return fired;
+        }
+        let triggers = self.triggers.map_or(&[][..], |section| &section.triggers[..]);
+        for (idx, trigger) in triggers.iter().enumerate() {
+            if trigger.run_once != 0 && self.fired_triggers.contains(&idx) {
+                continue;
+            }
+            if !self.conditions_hold(trigger) {
+                continue;
+            }
+            self.fired_triggers.insert(idx);
+            fired.push(idx);
+            for &instance_id in trigger.instance_identifiers.iter().filter(|&&id| id != 0) {
+                self.execute(instance_id);
+            }
+        }
+        for unit in self.world.units.iter_mut() {
+            unit.attacked = false;
+        }
+        self.ticks += 1;
+        fired
+    }
+
+    // Ticks until the mission is decided or max_ticks have passed
+    pub fn run(&mut self, max_ticks: u32) -> MissionOutcome {
+        for _ in 0..max_ticks {
+            if self.world.outcome != MissionOutcome::InProgress {
+                break;
+            }
+            self.tick();
+        }
+        self.world.outcome
+    }
+
+    // A pair without an operator is ignored, so is a pair with both ids empty
+    fn conditions_hold(&mut self, trigger: &TriggerEntry) -> bool {
+        let operators = [trigger.check_01_operator, trigger.check_23_operator, trigger.check_45_operator];
+        for (pair, operator) in trigger.check_identifiers.chunks(2).zip(operators.iter()) {
+            let operator = match operator {
+                Some(operator) if pair[0] != 0 || pair[1] != 0 => *operator,
+                _ => continue
+            };
+            let (left, right) = (self.check_value(pair[0]), self.check_value(pair[1]));
+            if !compare(operator, left, right) {
+                return false;
+            }
+        }
+        true
+    }
+
+    // A check with execute_once keeps the first non zero value it produced
+    fn check_value(&mut self, check_id: u32) -> i32 {
+        if let Some(&value) = self.latched_checks.get(&check_id) {
+            return value;
+        }
+        let check = match self.checks.get(&check_id) {
+            Some(&check) => check,
+            None => return 0
+        };
+        let value = evaluate_check(&self.world, check);
+        if check.execute_once != 0 && value != 0 {
+            self.latched_checks.insert(check_id, value);
+        }
+        value
+    }
+
+    fn execute(&mut self, instance_id: u32) {
+        let instance = match self.instances.get(&instance_id) {
+            Some(&instance) => instance,
+            None => return
+        };
+        if instance.execute_once != 0 && !self.executed_instances.insert(instance_id) {
+            return;
+        }
+        apply_instance(&mut self.world, instance);
+    }
+}
+
+pub(crate) fn compare(operator: CheckOperator, left: i32, right: i32) -> bool {
+    match operator {
+        CheckOperator::Equals => left == right,
+        CheckOperator::NotEquals => left != right,
+        CheckOperator::GreaterThan => left > right,
+        CheckOperator::LowerThan => left < right,
+        CheckOperator::GreaterThanEquals => left >= right,
+        CheckOperator::LowerThanEquals => left <= right
+    }
+}
+
+// Spells, teleports and VIPs aren't modelled by the world and always evaluate to 0
+fn evaluate_check(world: &WorldState, check: &CheckEntry) -> i32 {
+    let args = Arguments::of_check(check);
+    let general = match check.check_type {
+        CheckType::Constant => return args.number(0),
+        CheckType::General(general) => general
+    };
+    let flag = |value: bool| value as i32;
+    match general {
+        GeneralCheckType::GroupUnitCount => {
+            world.living_units().filter(|unit| unit.group_id == args.group()).count() as i32
+        },
+        GeneralCheckType::IsUnitInABox => {
+            let ((x1, y1), (x2, y2)) = (args.point(0), args.point(1));
+            flag(world.alive(args.unit(0)).is_some_and(|unit| {
+                (x1.min(x2)..=x1.max(x2)).contains(&unit.x) && (y1.min(y2)..=y1.max(y2)).contains(&unit.y)
+            }))
+        },
+        GeneralCheckType::IsUnitInACircle => {
+            let (x, y) = args.point(0);
+            let radius = args.number(0) as i64;
+            flag(world.alive(args.unit(0)).is_some_and(|unit| {
+                let (dx, dy) = (unit.x as i64 - x as i64, unit.y as i64 - y as i64);
+                dx * dx + dy * dy <= radius * radius
+            }))
+        },
+        GeneralCheckType::GetUnitParameter => world
+            .unit(args.unit(0))
+            .and_then(|unit| unit.parameters.get(&(args.number(0) as u32)).copied())
+            .unwrap_or(0),
+        GeneralCheckType::IsUnitAlive => flag(world.alive(args.unit(0)).is_some()),
+        GeneralCheckType::GetDistanceBetweenUnits => match (world.alive(args.unit(0)), world.alive(args.unit(1))) {
+            (Some(a), Some(b)) => a.distance_to(b.x, b.y),
+            _ => UNREACHABLE_DISTANCE
+        },
+        GeneralCheckType::GetDistanceFromPointToUnit => {
+            let (x, y) = args.point(0);
+            world.alive(args.unit(0)).map_or(UNREACHABLE_DISTANCE, |unit| unit.distance_to(x, y))
+        },
+        GeneralCheckType::HowManyUnitsFractionHave => {
+            world.living_units().filter(|unit| unit.fraction_id == args.fraction(0)).count() as i32
+        },
+        GeneralCheckType::IsUnitAttacked => flag(world.alive(args.unit(0)).is_some_and(|unit| unit.attacked)),
+        GeneralCheckType::GetDiplomacy => world
+            .fractions
+            .get(args.fraction(0) as usize)
+            .and_then(|fraction| fraction.diplomacy_states.get(args.fraction(1) as usize))
+            .map_or(0, |&state| state as i32),
+        GeneralCheckType::CheckSack => world.unit(args.unit(0)).map_or(0, |unit| unit.items.len() as i32),
+        GeneralCheckType::GetDistanceToNearestFractionUnit => match world.alive(args.unit(0)) {
+            Some(from) => world
+                .living_units()
+                .filter(|unit| unit.fraction_id == args.fraction(0) && unit.unit_id != from.unit_id)
+                .map(|unit| from.distance_to(unit.x, unit.y))
+                .min()
+                .unwrap_or(UNREACHABLE_DISTANCE),
+            None => UNREACHABLE_DISTANCE
+        },
+        GeneralCheckType::GetDistanceFromPointToUnitWithItem => {
+            let (x, y) = args.point(0);
+            world
+                .living_units()
+                .filter(|unit| unit.items.iter().any(|item| item.id == args.item()))
+                .map(|unit| unit.distance_to(x, y))
+                .min()
+                .unwrap_or(UNREACHABLE_DISTANCE)
+        },
+        GeneralCheckType::IsItemInSack => flag(
+            world.unit(args.unit(0)).is_some_and(|unit| unit.items.iter().any(|item| item.id == args.item()))
+        ),
+        GeneralCheckType::CheckVariable => world.variables.get(&(args.number(0) as u32)).copied().unwrap_or(0),
+        GeneralCheckType::HowManyStructuresFractionHave => world
+            .structures
+            .iter()
+            .filter(|structure| structure.fraction_id == args.fraction(0) && structure.health > 0)
+            .count() as i32,
+        GeneralCheckType::GetStructureHealth => world.structure(args.structure()).map_or(0, |structure| structure.health),
+        GeneralCheckType::CheckScenarioVariable => {
+            world.scenario_variables.get(&(args.number(0) as u32)).copied().unwrap_or(0)
+        },
+        GeneralCheckType::CheckSubObjective => world.sub_objectives.get(&(args.number(0) as u32)).copied().unwrap_or(0),
+        GeneralCheckType::IsUnitInPoint => {
+            let (x, y) = args.point(0);
+            flag(world.alive(args.unit(0)).is_some_and(|unit| unit.x == x && unit.y == y))
+        },
+        GeneralCheckType::Unknown
+        | GeneralCheckType::Vip
+        | GeneralCheckType::Teleport
+        | GeneralCheckType::SpellInArea
+        | GeneralCheckType::SpellOnUnit => 0
+    }
+}
+
+// Magic, commands, formations and music have no effect on the world
+fn apply_instance(world: &mut WorldState, instance: &InstanceEntry) {
+    let args = Arguments::of_instance(instance);
+    let general = match instance.instance_type {
+        InstanceType::General(general) => general,
+        InstanceType::StartHere | InstanceType::RespawnGroup | InstanceType::ChangeMusicTo => return
+    };
+    match general {
+        GeneralInstanceType::IncrementMissionStage => world.mission_stage += 1,
+        GeneralInstanceType::SendMessage => world.messages.push(args.number(0) as u32),
+        GeneralInstanceType::SetVariableValue => {
+            world.variables.insert(args.number(0) as u32, args.number(1));
+        },
+        GeneralInstanceType::ForceMissionComplete => world.outcome = MissionOutcome::Complete,
+        GeneralInstanceType::ForceMissionFailed => world.outcome = MissionOutcome::Failed,
+        GeneralInstanceType::IncrementVariable => {
+            *world.variables.entry(args.number(0) as u32).or_insert(0) += 1;
+        },
+        GeneralInstanceType::SetDiplomacy => {
+            let (a, b, state) = (args.fraction(0) as usize, args.fraction(1) as usize, args.number(0) as u16);
+            if let Some(diplomacy) = world.fractions.get_mut(a).and_then(|f| f.diplomacy_states.get_mut(b)) {
+                *diplomacy = state;
+            }
+            if let Some(diplomacy) = world.fractions.get_mut(b).and_then(|f| f.diplomacy_states.get_mut(a)) {
+                *diplomacy = state;
+            }
+        },
+        GeneralInstanceType::GiveItem | GeneralInstanceType::AddItemInUnitsSack => {
+            let wielded = if matches!(general, GeneralInstanceType::GiveItem) { args.number(0) as u16 } else { 0 };
+            if let Some(unit) = world.unit_mut(args.unit(0)) {
+                unit.items.push(ItemEntry { id: args.item(), wielded, effect_id: 0 });
+            }
+        },
+        GeneralInstanceType::RemoveItemFromUnitsSack => {
+            if let Some(unit) = world.unit_mut(args.unit(0)) {
+                if let Some(position) = unit.items.iter().position(|item| item.id == args.item()) {
+                    unit.items.remove(position);
+                }
+            }
+        },
+        GeneralInstanceType::HideUnit | GeneralInstanceType::ShowUnit => {
+            if let Some(unit) = world.unit_mut(args.unit(0)) {
+                unit.hidden = matches!(general, GeneralInstanceType::HideUnit);
+            }
+        },
+        GeneralInstanceType::MetamorphUnit => {
+            if let Some(unit) = world.unit_mut(args.unit(0)) {
+                unit.type_id = args.number(0) as u16;
+            }
+        },
+        GeneralInstanceType::ChangeUnitsOwner => {
+            if let Some(unit) = world.unit_mut(args.unit(0)) {
+                unit.fraction_id = args.fraction(0);
+            }
+        },
+        GeneralInstanceType::DropAll => {
+            if let Some(unit) = world.unit_mut(args.unit(0)) {
+                unit.items.clear();
+                unit.money = 0;
+            }
+        },
+        GeneralInstanceType::ChangeGroupsOwner => {
+            let (group, fraction) = (args.group(), args.fraction(0));
+            for unit in world.units.iter_mut().filter(|unit| unit.group_id == group) {
+                unit.fraction_id = fraction;
+            }
+        },
+        GeneralInstanceType::GiveMoneyToFraction => {
+            if let Some(fraction) = world.fractions.get_mut(args.fraction(0) as usize) {
+                fraction.money = fraction.money.saturating_add_signed(args.number(0));
+            }
+        },
+        GeneralInstanceType::SetStructureHealth => {
+            if let Some(structure) = world.structure_mut(args.structure()) {
+                structure.health = args.number(0);
+            }
+        },
+        GeneralInstanceType::MoveUnitImmediate => {
+            let (x, y) = args.point(0);
+            world.move_unit(args.unit(0), x, y);
+        },
+        GeneralInstanceType::GiveAllItemsFromUnitToUnit => {
+            let items = world.unit_mut(args.unit(0)).map(|unit| std::mem::take(&mut unit.items));
+            match (items, world.unit_mut(args.unit(1))) {
+                (Some(items), Some(receiver)) => receiver.items.extend(items),
+                (Some(items), None) => {
+                    // nobody to give them to, put them back
+                    if let Some(unit) = world.unit_mut(args.unit(0)) {
+                        unit.items = items;
+                    }
+                },
+                _ => {}
+            }
+        },
+        GeneralInstanceType::HideGroup | GeneralInstanceType::ShowGroup => {
+            let (group, hidden) = (args.group(), matches!(general, GeneralInstanceType::HideGroup));
+            for unit in world.units.iter_mut().filter(|unit| unit.group_id == group) {
+                unit.hidden = hidden;
+            }
+        },
+        GeneralInstanceType::SetUnitsParameter => {
+            if let Some(unit) = world.unit_mut(args.unit(0)) {
+                unit.parameters.insert(args.number(0) as u32, args.number(1));
+            }
+        },
+        GeneralInstanceType::SetScenarioVariable => {
+            world.scenario_variables.insert(args.number(0) as u32, args.number(1));
+        },
+        GeneralInstanceType::SetSubObjective => {
+            world.sub_objectives.insert(args.number(0) as u32, args.number(1));
+        },
+        GeneralInstanceType::RemoveItemFromAll => {
+            for unit in world.units.iter_mut() {
+                unit.items.retain(|item| item.id != args.item());
+            }
+        },
+        GeneralInstanceType::Unknown
+        | GeneralInstanceType::Command
+        | GeneralInstanceType::KeepFormation
+        | GeneralInstanceType::MagicOnArea
+        | GeneralInstanceType::MagicOnUnit
+        | GeneralInstanceType::CreateMagicTrigger
+        | GeneralInstanceType::TimedSpellOnGround
+        | GeneralInstanceType::ChangeRespawnTime
+        | GeneralInstanceType::SetMusicOrder
+        | GeneralInstanceType::StopGroup => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alm::trigger_vm::*;
+
+    // Hero (unit 1) has to reach the camp at (5, 5)-(6, 6) and stay alive
+    const SCRIPT: &str = "\
+instance \"Win\" = ForceMissionComplete();
+instance \"Lose\" = ForceMissionFailed();
+instance \"Count\" = IncrementVariable(variable: Number = 1);
+instance \"Count once\" once = IncrementVariable(variable: Number = 2);
+instance \"Count latched\" = IncrementVariable(variable: Number = 3);
+
+check \"One\" = Constant(value: Number = 1);
+check \"Zero\" = Constant(value: Number = 0);
+check \"Hero\" = IsUnitAlive(unit: Unit = 1);
+check \"Hero once\" once = IsUnitAlive(unit: Unit = 1);
+check \"In camp\" = IsUnitInABox(unit: Unit = 1, x1: X = 5, y1: Y = 5, x2: X = 6, y2: Y = 6);
+
+trigger \"Reached\" {
+    if \"In camp\" == \"One\";
+    do \"Win\";
+}
+
+trigger \"Died\" {
+    if \"Hero\" == \"Zero\";
+    do \"Lose\";
+}
+
+trigger \"Counter\" once {
+    if \"One\" == \"One\";
+    do \"Count\";
+}
+
+trigger \"Counter once\" {
+    if \"One\" == \"One\";
+    do \"Count once\";
+}
+
+trigger \"Latched\" {
+    if \"Hero once\" == \"One\";
+    do \"Count latched\";
+}
+";
+
+    fn mission() -> AlmMap {
+        let mut builder = AlmMapBuilder::new(8, 8);
+        let player = builder.add_fraction("Player", 1, 0, 0);
+        assert_eq!(builder.add_unit(UnitEntry::new(0x180, 0x180, 1, player)), 1);
+        let mut map = builder.build();
+        map.triggers = Some(compile_triggers(SCRIPT).unwrap());
+        map
+    }
+
+    #[test]
+    fn test_reaching_the_camp_completes() {
+        let map = mission();
+        let mut vm = TriggerVm::from_map(&map);
+        vm.tick();
+        assert_eq!(vm.outcome(), MissionOutcome::InProgress);
+        assert!(vm.world.move_unit(1, 5, 6));
+        assert_eq!(vm.tick(), vec![0, 3, 4]);
+        assert_eq!(vm.outcome(), MissionOutcome::Complete);
+        // a decided mission doesn't run anymore
+        assert!(vm.tick().is_empty());
+    }
+
+    #[test]
+    fn test_losing_the_hero_fails() {
+        let map = mission();
+        let mut vm = TriggerVm::from_map(&map);
+        assert_eq!(vm.run(3), MissionOutcome::InProgress);
+        assert!(vm.world.kill_unit(1));
+        assert!(!vm.world.kill_unit(2));
+        assert_eq!(vm.run(3), MissionOutcome::Failed);
+        assert_eq!(vm.ticks, 4);
+    }
+
+    #[test]
+    fn test_once_flags() {
+        let map = mission();
+        let mut vm = TriggerVm::from_map(&map);
+        assert_eq!(vm.tick(), vec![2, 3, 4]);
+        vm.world.kill_unit(1);
+        // the once check latched its first value, only the Died trigger sees the hero is gone
+        vm.world.outcome = MissionOutcome::InProgress;
+        assert_eq!(vm.tick(), vec![1, 3, 4]);
+        vm.world.outcome = MissionOutcome::InProgress;
+        vm.tick();
+        // run_once trigger fired once, execute_once instance ran once out of three firings
+        assert_eq!(vm.world.variables.get(&1), Some(&1));
+        assert_eq!(vm.world.variables.get(&2), Some(&1));
+        assert_eq!(vm.world.variables.get(&3), Some(&3));
+    }
+}
@@ -1,6 +1,6 @@
 use bin_serialization_rs::{Reflectable, Endianness};
-use std::io::{Result, Read, ErrorKind};
-use crate::shared_types::U32Wrapper;
+use std::io::{Result, Read, Write, ErrorKind};
+use crate::shared_types::{U32Wrapper, encode_cp866_into};
 use num_enum::TryFromPrimitive;
 
 pub mod trigger_enums {
@@ -36,6 +36,8 @@ pub mod trigger_enums {
         SpellOnUnit,
         IsUnitInPoint
     }
+    // not derived, num_enum would take a #[default] variant for every unknown value
+    #[allow(clippy::derivable_impls)]
     impl Default for GeneralCheckType {
         fn default() -> Self {
             Self::Unknown
@@ -49,17 +51,13 @@ pub mod trigger_enums {
     }
     impl num_enum::TryFromPrimitive for CheckType {
         type Primitive = u32;
-        const NAME: &'static str = "InstanceType";
+        const NAME: &'static str = "CheckType";
         fn try_from_primitive(number: Self::Primitive) -> Result<Self, TryFromPrimitiveError<Self>> {
-            if number <= 0x1B {
-                Ok(Self::General(GeneralCheckType::try_from(number).unwrap()))
-            } else {
-                match number {
-                    0x10002 => Ok(Self::Constant),
-                    _ => Err(TryFromPrimitiveError{
-                        number
-                    })
-                }
+            match number {
+                0x10002 => Ok(Self::Constant),
+                _ => GeneralCheckType::try_from(number)
+                    .map(Self::General)
+                    .map_err(|_| TryFromPrimitiveError { number })
             }
         }
     }
@@ -68,8 +66,16 @@ pub mod trigger_enums {
             Self::General(Default::default())
         }
     }
+    impl CheckType {
+        pub fn to_primitive(self) -> u32 {
+            match self {
+                Self::General(general) => general as u32,
+                Self::Constant => 0x10002
+            }
+        }
+    }
 
-    #[derive(num_enum::TryFromPrimitive, Copy, Clone, Debug)]
+    #[derive(num_enum::TryFromPrimitive, Copy, Clone, PartialEq, Debug)]
     #[repr(u32)]
     pub enum ArgumentType {
         Unknown,
@@ -83,6 +89,7 @@ pub mod trigger_enums {
         Item,
         Structure
     }
+    #[allow(clippy::derivable_impls)]
     impl Default for ArgumentType {
         fn default() -> Self {
             ArgumentType::Unknown
@@ -99,6 +106,7 @@ pub mod trigger_enums {
         GreaterThanEquals,
         LowerThanEquals,
     }
+    #[allow(clippy::derivable_impls)]
     impl Default for CheckOperator {
         fn default() -> Self {
             Self::Equals
@@ -145,6 +153,7 @@ pub mod trigger_enums {
         RemoveItemFromAll,
         StopGroup,
     }
+    #[allow(clippy::derivable_impls)]
     impl Default for GeneralInstanceType {
         fn default() -> Self {
             Self::Unknown
@@ -162,17 +171,13 @@ pub mod trigger_enums {
         type Primitive = u32;
         const NAME: &'static str = "InstanceType";
         fn try_from_primitive(number: Self::Primitive) -> Result<Self, TryFromPrimitiveError<Self>> {
-            if number <= 0x27 {
-                Ok(Self::General(GeneralInstanceType::try_from(number).unwrap()))
-            } else {
-                match number {
-                    0x10002 => Ok(Self::StartHere),
-                    0x10003 => Ok(Self::RespawnGroup),
-                    0x10004 => Ok(Self::ChangeMusicTo),
-                    _ => Err(TryFromPrimitiveError{
-                        number
-                    })
-                }
+            match number {
+                0x10002 => Ok(Self::StartHere),
+                0x10003 => Ok(Self::RespawnGroup),
+                0x10004 => Ok(Self::ChangeMusicTo),
+                _ => GeneralInstanceType::try_from(number)
+                    .map(Self::General)
+                    .map_err(|_| TryFromPrimitiveError { number })
             }
         }
     }
@@ -181,6 +186,16 @@ pub mod trigger_enums {
             Self::General(Default::default())
         }
     }
+    impl InstanceType {
+        pub fn to_primitive(self) -> u32 {
+            match self {
+                Self::General(general) => general as u32,
+                Self::StartHere => 0x10002,
+                Self::RespawnGroup => 0x10003,
+                Self::ChangeMusicTo => 0x10004
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -196,7 +211,7 @@ pub struct InstanceEntry {
 impl InstanceEntry {
     pub fn read_from_stream<TStream: Read>(stream: &mut TStream, endianness: Endianness) -> Result<Self> {
         let mut name_buffer = [0u8; 0x40];
-        stream.read(&mut name_buffer)?;
+        stream.read_exact(&mut name_buffer)?;
         let name = cp866_rs::decode_bytes(&name_buffer);
         let instance_type = trigger_enums::InstanceType::try_from_primitive(
             *U32Wrapper::deserialize(stream, endianness)?
@@ -215,7 +230,7 @@ impl InstanceEntry {
             ).map_err(|_| std::io::Error::from(ErrorKind::InvalidInput))?;
         }
         for _ in 0..10 {
-            stream.read(&mut name_buffer)?;
+            stream.read_exact(&mut name_buffer)?;
             argument_names.push(cp866_rs::decode_bytes(&name_buffer));
         }
         Ok(Self {
@@ -228,6 +243,20 @@ impl InstanceEntry {
             argument_names
         })
     }
+
+    pub fn write_to_stream<TStream: Write>(&self, stream: &mut TStream, endianness: Endianness) -> Result<()> {
+        write_logic_entry(
+            stream,
+            endianness,
+            &self.name,
+            self.instance_type.to_primitive(),
+            self.id,
+            self.execute_once,
+            &self.argument_values,
+            &self.argument_types,
+            &self.argument_names
+        )
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -243,7 +272,7 @@ pub struct CheckEntry {
 impl CheckEntry {
     pub fn read_from_stream<TStream: Read>(stream: &mut TStream, endianness: Endianness) -> Result<Self> {
         let mut name_buffer = [0u8; 0x40];
-        stream.read(&mut name_buffer)?;
+        stream.read_exact(&mut name_buffer)?;
         let name = cp866_rs::decode_bytes(&name_buffer);
         let check_type = trigger_enums::CheckType::try_from_primitive(
             *U32Wrapper::deserialize(stream, endianness)?
@@ -262,7 +291,7 @@ impl CheckEntry {
             ).map_err(|_| std::io::Error::from(ErrorKind::InvalidInput))?;
         }
         for _ in 0..10 {
-            stream.read(&mut name_buffer)?;
+            stream.read_exact(&mut name_buffer)?;
             argument_names.push(cp866_rs::decode_bytes(&name_buffer));
         }
         Ok(Self {
@@ -275,8 +304,24 @@ impl CheckEntry {
             argument_names
         })
     }
+
+    pub fn write_to_stream<TStream: Write>(&self, stream: &mut TStream, endianness: Endianness) -> Result<()> {
+        write_logic_entry(
+            stream,
+            endianness,
+            &self.name,
+            self.check_type.to_primitive(),
+            self.id,
+            self.execute_once,
+            &self.argument_values,
+            &self.argument_types,
+            &self.argument_names
+        )
+    }
 }
 
+pub const NO_OPERATOR: u32 = 0xFFFFFFFF;
+
 #[derive(Clone, Debug)]
 pub struct TriggerEntry {
     pub name: String,
@@ -285,13 +330,14 @@ pub struct TriggerEntry {
     pub check_01_operator: Option<trigger_enums::CheckOperator>,
     pub check_23_operator: Option<trigger_enums::CheckOperator>,
     pub check_45_operator: Option<trigger_enums::CheckOperator>,
+    pub operator_values: [u32; 3], // as stored, written back for the pairs that have no operator
     pub run_once: u32
 }
 impl TriggerEntry {
     pub fn read_from_stream<TStream: Read>(stream: &mut TStream, endianness: Endianness) -> Result<Self> {
         let name = {
             let mut name_buffer = [0u8; 0x80];
-            stream.read(&mut name_buffer)?;
+            stream.read_exact(&mut name_buffer)?;
             cp866_rs::decode_bytes(&name_buffer)
         };
         let mut check_identifiers = [0u32; 6];
@@ -305,22 +351,23 @@ impl TriggerEntry {
         let check_01_operator = *U32Wrapper::deserialize(stream, endianness)?;
         let check_23_operator = *U32Wrapper::deserialize(stream, endianness)?;
         let check_45_operator = *U32Wrapper::deserialize(stream, endianness)?;
+        let operator_values = [check_01_operator, check_23_operator, check_45_operator];
 
-        let check_01_operator = if check_01_operator == 0xFFFFFFFF || check_identifiers[0] == 0 || check_identifiers[1] == 0 {
+        let check_01_operator = if check_01_operator == NO_OPERATOR || check_identifiers[0] == 0 || check_identifiers[1] == 0 {
             None
         } else {
             Some(trigger_enums::CheckOperator::try_from_primitive(
                 check_01_operator
             ).map_err(|_| std::io::Error::from(ErrorKind::InvalidInput))?)
         };
-        let check_23_operator = if check_23_operator == 0xFFFFFFFF || check_identifiers[2] == 0 || check_identifiers[3] == 0 {
+        let check_23_operator = if check_23_operator == NO_OPERATOR || check_identifiers[2] == 0 || check_identifiers[3] == 0 {
             None
         } else {
             Some(trigger_enums::CheckOperator::try_from_primitive(
                 check_23_operator
             ).map_err(|_| std::io::Error::from(ErrorKind::InvalidInput))?)
         };
-        let check_45_operator = if check_45_operator == 0xFFFFFFFF || check_identifiers[4] == 0 || check_identifiers[5] == 0 {
+        let check_45_operator = if check_45_operator == NO_OPERATOR || check_identifiers[4] == 0 || check_identifiers[5] == 0 {
             None
         } else {
             Some(trigger_enums::CheckOperator::try_from_primitive(
@@ -335,9 +382,68 @@ impl TriggerEntry {
             check_01_operator,
             check_23_operator,
             check_45_operator,
+            operator_values,
             run_once
         })
     }
+
+    pub fn write_to_stream<TStream: Write>(&self, stream: &mut TStream, endianness: Endianness) -> Result<()> {
+        let mut name_buffer = [0u8; 0x80];
+        encode_cp866_into(&self.name, &mut name_buffer);
+        stream.write_all(&name_buffer)?;
+        for &value in self.check_identifiers.iter().chain(self.instance_identifiers.iter()) {
+            U32Wrapper(value).serialize(stream, endianness)?;
+        }
+        let operators = [self.check_01_operator, self.check_23_operator, self.check_45_operator];
+        for (idx, (operator, pair)) in operators.iter().zip(self.check_identifiers.chunks(2)).enumerate() {
+            // a stored value is kept as long as it doesn't read back as an operator
+            let value = match operator {
+                Some(operator) => *operator as u32,
+                None => {
+                    let stored = self.operator_values[idx];
+                    let reads_as_operator = pair[0] != 0
+                        && pair[1] != 0
+                        && trigger_enums::CheckOperator::try_from_primitive(stored).is_ok();
+                    if reads_as_operator { NO_OPERATOR } else { stored }
+                }
+            };
+            U32Wrapper(value).serialize(stream, endianness)?;
+        }
+        U32Wrapper(self.run_once).serialize(stream, endianness)
+    }
+}
+
+// Instances and checks share the very same layout
+#[allow(clippy::too_many_arguments)]
+fn write_logic_entry<TStream: Write>(
+    stream: &mut TStream,
+    endianness: Endianness,
+    name: &str,
+    type_id: u32,
+    id: u32,
+    execute_once: u32,
+    argument_values: &[u32; 10],
+    argument_types: &[trigger_enums::ArgumentType],
+    argument_names: &[String]
+) -> Result<()> {
+    let mut name_buffer = [0u8; 0x40];
+    encode_cp866_into(name, &mut name_buffer);
+    stream.write_all(&name_buffer)?;
+    U32Wrapper(type_id).serialize(stream, endianness)?;
+    U32Wrapper(id).serialize(stream, endianness)?;
+    U32Wrapper(execute_once).serialize(stream, endianness)?;
+    for &value in argument_values.iter() {
+        U32Wrapper(value).serialize(stream, endianness)?;
+    }
+    for i in 0..10 {
+        let argument_type = argument_types.get(i).copied().unwrap_or_default();
+        U32Wrapper(argument_type as u32).serialize(stream, endianness)?;
+    }
+    for i in 0..10 {
+        encode_cp866_into(argument_names.get(i).map_or("", String::as_str), &mut name_buffer);
+        stream.write_all(&name_buffer)?;
+    }
+    Ok(())
 }
 
 #[derive(Debug)]
@@ -365,4 +471,20 @@ impl TriggersSection {
         }
         Ok(Self { instances, checks, triggers })
     }
+
+    pub fn write_to_stream<TStream: Write>(&self, stream: &mut TStream, endianness: Endianness) -> Result<()> {
+        U32Wrapper(self.instances.len() as u32).serialize(stream, endianness)?;
+        for instance in self.instances.iter() {
+            instance.write_to_stream(stream, endianness)?;
+        }
+        U32Wrapper(self.checks.len() as u32).serialize(stream, endianness)?;
+        for check in self.checks.iter() {
+            check.write_to_stream(stream, endianness)?;
+        }
+        U32Wrapper(self.triggers.len() as u32).serialize(stream, endianness)?;
+        for trigger in self.triggers.iter() {
+            trigger.write_to_stream(stream, endianness)?;
+        }
+        Ok(())
+    }
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-use std::io::{Result, Read};
+use std::io::{Result, Read, Write};
 use bin_serialization_rs::{Reflectable, SerializationReflector, Endianness};
 
 #[derive(Clone, Default, Debug)]
@@ -16,6 +16,21 @@ pub struct UnitEntry {
     pub unit_id: u16,
     pub group_id: u32
 }
+impl UnitEntry {
+    // Coordinates are 8.8 fixed point, the integer part being the tile
+    pub fn tile_position(&self) -> (u32, u32) {
+        (self.x_coord >> 8, self.y_coord >> 8)
+    }
+    pub fn new(x_coord: u32, y_coord: u32, type_id: u16, fraction_id: u32) -> Self {
+        Self {
+            x_coord,
+            y_coord,
+            type_id,
+            fraction_id,
+            ..Default::default()
+        }
+    }
+}
 impl Reflectable for UnitEntry {
     fn reflect<TSerializationReflector: SerializationReflector>(
         &mut self, reflector: &mut TSerializationReflector
@@ -56,4 +71,11 @@ impl UnitsSection {
             units
         })
     }
+
+    pub(crate) fn write<TStream: Write>(&self, stream: &mut TStream) -> Result<()> {
+        for unit in self.units.iter() {
+            unit.clone().serialize(stream, Endianness::LittleEndian)?;
+        }
+        Ok(())
+    }
 }
\ No newline at end of file
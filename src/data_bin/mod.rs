@@ -12,12 +12,12 @@ use std::io::{Seek, Read, SeekFrom};
 use crate::data_bin::section::SectionDefinition;
 pub use crate::data_bin::{
     shape::ShapeSection,
-    parameter::ParameterSection,
-    item::ItemSection,
+    parameter::{ParameterSection, ParameterInfo},
+    item::{ItemSection, ItemInfo},
     magic_item::MagicItemSection,
-    unit::UnitSection,
-    human::HumanSection,
-    structure::StructureSection,
+    unit::{UnitSection, UnitInfo, UnitRecord},
+    human::{HumanSection, HumanInfo},
+    structure::{StructureSection, StructureInfo, StructureRecord},
     spell::SpellSection
 };
 
@@ -40,6 +40,7 @@ impl DataBinContent {
         Some(Section::read(stream))
     }
 
+    #[allow(clippy::unused_io_amount)]
     pub fn read<Stream: Seek + Read>(stream: &mut Stream) -> Self{
         let mut header_buffer = [0u8; 10];
         let mut sections_remain = 8;
@@ -0,0 +1,198 @@
+use crate::alm::{AlmMap, EffectModifier, ItemEntry, ObjectDefinition, ObjectDefinitions, ObjectId, StructureEntry, UnitEntry};
+use crate::data_bin::{DataBinContent, HumanInfo, ItemInfo, ParameterInfo, StructureInfo, UnitInfo};
+
+// Size in tiles of a structure whose data.bin record is not at hand; it covers its anchor tile
+pub const DEFAULT_STRUCTURE_FOOTPRINT: (u32, u32) = (1, 1);
+
+#[derive(Copy, Clone, Debug)]
+pub enum UnitInfoRef<'a> {
+    Human(&'a HumanInfo),
+    Monster(&'a UnitInfo)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UnresolvedReference {
+    UnitType { unit_id: u16, type_id: u16, face: u16 },
+    UnitGraphics { unit_id: u16, type_id: u16 },
+    StructureType { structure_id: u16, type_id: u32 },
+    StructureGraphics { structure_id: u16, type_id: u32 },
+    Item { sack_unit_id: u32, item_id: u32 },
+    Parameter { effect_index: usize, modifier_type: u16 },
+    Object { x: usize, y: usize, object_id: ObjectId }
+}
+
+// data.bin together with the graphics registries (units.reg, structures.reg and objects.reg).
+// A registry left empty is not used for resolving, so nothing is reported against it
+#[derive(Clone, Debug)]
+pub struct GameDatabase {
+    pub data_bin: DataBinContent,
+    pub unit_graphics: ObjectDefinitions,
+    pub structure_graphics: ObjectDefinitions,
+    pub objects: ObjectDefinitions
+}
+impl GameDatabase {
+    pub fn new(data_bin: DataBinContent) -> Self {
+        Self {
+            data_bin,
+            unit_graphics: Default::default(),
+            structure_graphics: Default::default(),
+            objects: Default::default()
+        }
+    }
+
+    // Map units are matched to data.bin records by type and face, humans first
+    pub fn unit(&self, unit: &UnitEntry) -> Option<UnitInfoRef<'_>> {
+        let matches = |type_id: i32, face: i32| type_id == unit.type_id as i32 && face == unit.face_ide as i32;
+        let human = self.data_bin.human_section
+            .iter()
+            .flat_map(|section| section.data.iter())
+            .find(|human| matches(human.details.type_id, human.details.face));
+        if let Some(human) = human {
+            return Some(UnitInfoRef::Human(human));
+        }
+        self.data_bin.unit_section
+            .iter()
+            .flat_map(|section| section.data.iter())
+            .find(|monster| matches(monster.details.type_id, monster.details.face))
+            .map(UnitInfoRef::Monster)
+    }
+
+    pub fn unit_graphics(&self, unit: &UnitEntry) -> Option<&ObjectDefinition> {
+        self.unit_graphics.definitions.get(&(unit.type_id as u32))
+    }
+
+    // Structure types index the data.bin structure list
+    pub fn structure(&self, structure: &StructureEntry) -> Option<&StructureInfo> {
+        self.data_bin.structure_section
+            .as_ref()
+            .and_then(|section| section.data.get(structure.type_id as usize))
+    }
+
+    // Bridges carry their own span, other structures take their size from data.bin
+    pub fn structure_footprint(&self, structure: &StructureEntry) -> (u32, u32) {
+        structure_footprint(structure, Some(self))
+    }
+
+    pub fn structure_graphics(&self, structure: &StructureEntry) -> Option<&ObjectDefinition> {
+        self.structure_graphics.definitions.get(&structure.type_id)
+    }
+
+    // The low byte of an item id is the item type, counted through wieldables, shields and weapons;
+    // the high byte picks the material and rarity
+    pub fn item(&self, item: &ItemEntry) -> Option<&ItemInfo> {
+        let section = self.data_bin.item_section.as_ref()?;
+        section.wieldables
+            .iter()
+            .chain(section.shields.iter())
+            .chain(section.weapons.iter())
+            .nth((item.id & 0xFF) as usize)
+    }
+
+    pub fn parameter(&self, modifier: &EffectModifier) -> Option<&ParameterInfo> {
+        self.data_bin.parameter_section
+            .as_ref()
+            .and_then(|section| section.data.get(modifier.modifier_type as usize))
+    }
+
+    pub fn object(&self, object_id: ObjectId) -> Option<&ObjectDefinition> {
+        self.objects.get(object_id)
+    }
+
+    // Every reference of the map that doesn't resolve, in section order
+    pub fn unresolved_references(&self, map: &AlmMap) -> Vec<UnresolvedReference> {
+        let mut unresolved = Vec::new();
+        if let Some(map_objects) = &map.map_objects {
+            if !self.objects.definitions.is_empty() {
+                for (x, y, object_id) in map_objects.iter_objects() {
+                    if self.object(object_id).is_none() {
+                        unresolved.push(UnresolvedReference::Object { x, y, object_id });
+                    }
+                }
+            }
+        }
+        if let Some(structures) = &map.structures {
+            for structure in structures.structures.iter() {
+                let (structure_id, type_id) = (structure.id, structure.type_id);
+                if self.data_bin.structure_section.is_some() && self.structure(structure).is_none() {
+                    unresolved.push(UnresolvedReference::StructureType { structure_id, type_id });
+                }
+                if !self.structure_graphics.definitions.is_empty() && self.structure_graphics(structure).is_none() {
+                    unresolved.push(UnresolvedReference::StructureGraphics { structure_id, type_id });
+                }
+            }
+        }
+        if let Some(units) = &map.units {
+            let has_unit_records = self.data_bin.human_section.is_some() || self.data_bin.unit_section.is_some();
+            for unit in units.units.iter() {
+                let (unit_id, type_id) = (unit.unit_id, unit.type_id);
+                if has_unit_records && self.unit(unit).is_none() {
+                    unresolved.push(UnresolvedReference::UnitType { unit_id, type_id, face: unit.face_ide });
+                }
+                if !self.unit_graphics.definitions.is_empty() && self.unit_graphics(unit).is_none() {
+                    unresolved.push(UnresolvedReference::UnitGraphics { unit_id, type_id });
+                }
+            }
+        }
+        if let (Some(sacks), Some(_)) = (&map.sacks, &self.data_bin.item_section) {
+            for sack in sacks.sacks.iter() {
+                for item in sack.items.iter().filter(|item| self.item(item).is_none()) {
+                    unresolved.push(UnresolvedReference::Item { sack_unit_id: sack.unit_id, item_id: item.id });
+                }
+            }
+        }
+        if let (Some(effects), Some(_)) = (&map.effects, &self.data_bin.parameter_section) {
+            for (effect_index, effect) in effects.effects.iter().enumerate() {
+                for modifier in effect.modifiers.iter().filter(|modifier| self.parameter(modifier).is_none()) {
+                    unresolved.push(UnresolvedReference::Parameter {
+                        effect_index,
+                        modifier_type: modifier.modifier_type
+                    });
+                }
+            }
+        }
+        unresolved
+    }
+}
+
+// Never smaller than a tile, so that zero span bridges still take some room
+pub fn structure_footprint(structure: &StructureEntry, database: Option<&GameDatabase>) -> (u32, u32) {
+    if structure.is_bridge() {
+        return structure.footprint();
+    }
+    database
+        .and_then(|database| database.structure(structure))
+        .map_or(DEFAULT_STRUCTURE_FOOTPRINT, |info| {
+            (info.details.size_x.max(1) as u32, info.details.size_y.max(1) as u32)
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alm::{BridgeInfo, StructureEntry};
+    use crate::data_bin::{DataBinContent, StructureInfo, StructureSection};
+    use crate::game_database::*;
+
+    #[test]
+    fn test_structure_footprint() {
+        let mut info = StructureInfo::default();
+        info.details.size_x = 3;
+        info.details.size_y = 2;
+        let database = GameDatabase::new(DataBinContent {
+            shape_section: None,
+            item_section: None,
+            magic_item_section: None,
+            parameter_section: None,
+            spell_section: None,
+            structure_section: Some(StructureSection { data: vec![StructureInfo::default(), info] }),
+            unit_section: None,
+            human_section: None
+        });
+        let structure = StructureEntry { type_id: 1, ..Default::default() };
+        assert_eq!(database.structure_footprint(&structure), (3, 2));
+        assert_eq!(structure_footprint(&structure, None), DEFAULT_STRUCTURE_FOOTPRINT);
+        let unknown = StructureEntry { type_id: 7, ..Default::default() };
+        assert_eq!(database.structure_footprint(&unknown), DEFAULT_STRUCTURE_FOOTPRINT);
+        let bridge = StructureEntry { type_id: 33, bridge_info: BridgeInfo { width: 4, height: 0 }, ..Default::default() };
+        assert_eq!(structure_footprint(&bridge, Some(&database)), (4, 1));
+    }
+}
@@ -0,0 +1,406 @@
+use std::collections::HashMap;
+use std::io::{Result, Write};
+use crate::images::ingame_sprite::ImageData;
+use crate::images::png::{self, PngColorType};
+use crate::images::quantization::{PaletteLayout, PaletteMapper};
+use crate::images::rgba_image::RgbaImage;
+use crate::multimedia::SmackerFile;
+
+const GIF_TRAILER: u8 = 0x3B;
+const GIF_EXTENSION: u8 = 0x21;
+const GIF_IMAGE_DESCRIPTOR: u8 = 0x2C;
+const GIF_MAX_CODE_SIZE: u32 = 12;
+
+#[derive(Clone, Debug)]
+pub struct AnimationFrame {
+    pub palette: Vec<u32>,
+    pub palette_indexes: Vec<u8>,
+    pub transparent_index: Option<u8>,
+    pub delay_ms: u32
+}
+impl AnimationFrame {
+    pub fn to_rgba(&self, width: usize, height: usize) -> RgbaImage {
+        let mut image = RgbaImage::from_paletted(width, height, &self.palette, &self.palette_indexes);
+        if let Some(transparent) = self.transparent_index {
+            for (pixel, &idx) in image.pixels.iter_mut().zip(self.palette_indexes.iter()) {
+                if idx == transparent {
+                    *pixel = 0;
+                }
+            }
+        }
+        image
+    }
+}
+
+// All the frames share the same canvas size
+#[derive(Clone, Debug)]
+pub struct Animation {
+    pub width: usize,
+    pub height: usize,
+    pub loop_count: u16, // 0 stands for an endless loop
+    pub frames: Vec<AnimationFrame>
+}
+impl Animation {
+    pub fn from_sprite_frames(
+        image_data: &ImageData,
+        frame_ids: &[usize],
+        palette: &[u32],
+        frame_interval_ms: u32
+    ) -> Self {
+        let decoded: Vec<_> = frame_ids.iter().map(|&id| image_data.decode_frame(id)).collect();
+        let width = decoded.iter().map(|f| f.width).max().unwrap_or(0);
+        let height = decoded.iter().map(|f| f.height).max().unwrap_or(0);
+        let frames = decoded
+            .iter()
+            .map(|frame| {
+                let mut usage = [0usize; 256];
+                for (&value, &opaque) in frame.values.iter().zip(frame.mask.iter()) {
+                    if opaque {
+                        usage[(value & 0xFF) as usize] += 1;
+                    }
+                }
+                let transparent = pick_transparent_index(&usage);
+                let mut frame_palette = palette.to_vec();
+                frame_palette.resize(256, 0xFF_00_00_00);
+                // the least used index could still be in use, so its pixels get the nearest other colour
+                let replacement = if usage[transparent as usize] > 0 {
+                    let layout = PaletteLayout { transparent_index: Some(transparent), reserved_ranges: Vec::new() };
+                    PaletteMapper::new(&frame_palette, &layout).map(|mut mapper| mapper.nearest(frame_palette[transparent as usize]))
+                } else {
+                    None
+                };
+                let mut palette_indexes = vec![transparent; width * height];
+                for y in 0..frame.height {
+                    for x in 0..frame.width {
+                        if frame.is_opaque(x, y) {
+                            let idx = (frame.values[y * frame.width + x] & 0xFF) as u8;
+                            palette_indexes[y * width + x] = match replacement {
+                                Some(replacement) if idx == transparent => replacement,
+                                _ => idx
+                            };
+                        }
+                    }
+                }
+                AnimationFrame {
+                    palette: frame_palette,
+                    palette_indexes,
+                    transparent_index: Some(transparent),
+                    delay_ms: frame_interval_ms
+                }
+            })
+            .collect();
+        Self {
+            width,
+            height,
+            loop_count: 0,
+            frames
+        }
+    }
+
+    // Decodes the whole clip from the first frame on, as frames are deltas of each other
+    pub fn from_smacker(smacker: &mut SmackerFile) -> Result<Self> {
+        let width = smacker.file_info.width as usize;
+        let height = smacker.file_info.height as usize;
+        let delay_ms = smacker.file_info.frame_interval.round() as u32;
+        let mut frames = Vec::with_capacity(smacker.file_info.frames.len());
+        for frame_id in 0..smacker.file_info.frames.len() {
+            smacker.unpack(frame_id, false, true)?;
+            let context = &smacker.file_info.smacker_decode_context;
+            // palettes change between frames, so every frame keeps its own copy
+            frames.push(AnimationFrame {
+                palette: context.palette
+                    .iter()
+                    .map(|&(r, g, b)| 0xFF_00_00_00 | (r as u32) << 16 | (g as u32) << 8 | b as u32)
+                    .collect(),
+                palette_indexes: context.image[..width * height].to_vec(),
+                transparent_index: None,
+                delay_ms
+            });
+        }
+        Ok(Self {
+            width,
+            height,
+            loop_count: 0,
+            frames
+        })
+    }
+
+    pub fn write_gif<TStream: Write>(&self, stream: &mut TStream) -> Result<()> {
+        stream.write_all(b"GIF89a")?;
+        stream.write_all(&(self.width as u16).to_le_bytes())?;
+        stream.write_all(&(self.height as u16).to_le_bytes())?;
+        stream.write_all(&[0x00, 0x00, 0x00])?; // no global color table
+        stream.write_all(&[GIF_EXTENSION, 0xFF, 0x0B])?;
+        stream.write_all(b"NETSCAPE2.0")?;
+        stream.write_all(&[0x03, 0x01])?;
+        stream.write_all(&self.loop_count.to_le_bytes())?;
+        stream.write_all(&[0x00])?;
+        for frame in self.frames.iter() {
+            let delay_cs = ((frame.delay_ms + 5) / 10).min(0xFFFF) as u16;
+            let (flags, transparent) = match frame.transparent_index {
+                Some(idx) => (0x08 | 0x01, idx), // restore to background, transparency on
+                None => (0x04, 0)                // leave in place
+            };
+            stream.write_all(&[GIF_EXTENSION, 0xF9, 0x04, flags])?;
+            stream.write_all(&delay_cs.to_le_bytes())?;
+            stream.write_all(&[transparent, 0x00])?;
+
+            stream.write_all(&[GIF_IMAGE_DESCRIPTOR, 0, 0, 0, 0])?;
+            stream.write_all(&(self.width as u16).to_le_bytes())?;
+            stream.write_all(&(self.height as u16).to_le_bytes())?;
+            stream.write_all(&[0x87])?; // local color table of 256 entries
+            for i in 0..256 {
+                let color = frame.palette.get(i).copied().unwrap_or(0);
+                stream.write_all(&[(color >> 16) as u8, (color >> 8) as u8, color as u8])?;
+            }
+            stream.write_all(&[8])?;
+            for block in lzw_encode(&frame.palette_indexes).chunks(0xFF) {
+                stream.write_all(&[block.len() as u8])?;
+                stream.write_all(block)?;
+            }
+            stream.write_all(&[0x00])?;
+        }
+        stream.write_all(&[GIF_TRAILER])
+    }
+
+    pub fn write_apng<TStream: Write>(&self, stream: &mut TStream) -> Result<()> {
+        let (width, height) = (self.width as u32, self.height as u32);
+        stream.write_all(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A])?;
+        png::write_chunk(stream, b"IHDR", &png::ihdr(width, height, PngColorType::Rgba))?;
+        let mut actl = Vec::with_capacity(8);
+        actl.extend_from_slice(&(self.frames.len() as u32).to_be_bytes());
+        actl.extend_from_slice(&(self.loop_count as u32).to_be_bytes());
+        png::write_chunk(stream, b"acTL", &actl)?;
+        let mut sequence_number = 0u32;
+        for (frame_id, frame) in self.frames.iter().enumerate() {
+            let mut fctl = Vec::with_capacity(26);
+            fctl.extend_from_slice(&sequence_number.to_be_bytes());
+            fctl.extend_from_slice(&width.to_be_bytes());
+            fctl.extend_from_slice(&height.to_be_bytes());
+            fctl.extend_from_slice(&[0u8; 8]); // x and y offsets
+            fctl.extend_from_slice(&(frame.delay_ms.min(0xFFFF) as u16).to_be_bytes());
+            fctl.extend_from_slice(&1000u16.to_be_bytes());
+            fctl.extend_from_slice(&[0, 0]); // no disposal, overwrite the canvas
+            png::write_chunk(stream, b"fcTL", &fctl)?;
+            sequence_number += 1;
+
+            let rgba = png::rgba_bytes(&frame.to_rgba(self.width, self.height));
+            let data = png::zlib_compress(&png::add_filter_bytes(width, PngColorType::Rgba, &rgba));
+            if frame_id == 0 {
+                png::write_chunk(stream, b"IDAT", &data)?;
+            } else {
+                let mut fdat = Vec::with_capacity(data.len() + 4);
+                fdat.extend_from_slice(&sequence_number.to_be_bytes());
+                fdat.extend_from_slice(&data);
+                png::write_chunk(stream, b"fdAT", &fdat)?;
+                sequence_number += 1;
+            }
+        }
+        png::write_chunk(stream, b"IEND", &[])
+    }
+}
+
+fn pick_transparent_index(usage: &[usize; 256]) -> u8 {
+    let mut best = 0;
+    for (idx, &count) in usage.iter().enumerate() {
+        if count < usage[best] {
+            best = idx;
+        }
+    }
+    best as u8
+}
+
+// GIF flavoured LZW with 8 bit roots, codes packed LSB first
+fn lzw_encode(indexes: &[u8]) -> Vec<u8> {
+    let clear_code = 256u32;
+    let end_code = 257u32;
+    let mut out = Vec::new();
+    let mut bit_buffer = 0u32;
+    let mut bit_count = 0u32;
+    let mut emit = |code: u32, code_size: u32, out: &mut Vec<u8>| {
+        bit_buffer |= code << bit_count;
+        bit_count += code_size;
+        while bit_count >= 8 {
+            out.push(bit_buffer as u8);
+            bit_buffer >>= 8;
+            bit_count -= 8;
+        }
+    };
+
+    let mut dictionary: HashMap<(u32, u8), u32> = HashMap::new();
+    let mut next_code = end_code + 1;
+    let mut code_size = 9;
+    emit(clear_code, code_size, &mut out);
+    let mut current: Option<u32> = None;
+    for &idx in indexes {
+        current = match current {
+            None => Some(idx as u32),
+            Some(prefix) => match dictionary.get(&(prefix, idx)) {
+                Some(&code) => Some(code),
+                None => {
+                    emit(prefix, code_size, &mut out);
+                    dictionary.insert((prefix, idx), next_code);
+                    next_code += 1;
+                    if next_code == 1 << GIF_MAX_CODE_SIZE {
+                        emit(clear_code, code_size, &mut out);
+                        dictionary.clear();
+                        next_code = end_code + 1;
+                        code_size = 9;
+                    } else if next_code > 1 << code_size {
+                        code_size += 1;
+                    }
+                    Some(idx as u32)
+                }
+            }
+        };
+    }
+    if let Some(prefix) = current {
+        emit(prefix, code_size, &mut out);
+    }
+    emit(end_code, code_size, &mut out);
+    if bit_count > 0 {
+        out.push(bit_buffer as u8);
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use crate::images::animation::*;
+    use crate::images::png::test::{read_chunks, unfilter, zlib_decompress};
+
+    fn lzw_decode(data: &[u8]) -> Vec<u8> {
+        let (clear_code, end_code) = (256usize, 257usize);
+        let mut dictionary: Vec<Vec<u8>> = Vec::new();
+        let mut code_size = 9;
+        let mut position = 0;
+        let mut previous: Option<usize> = None;
+        let mut out = Vec::new();
+        loop {
+            let code = (0..code_size).fold(0usize, |code, i| {
+                let bit = (data[(position + i) / 8] >> ((position + i) % 8)) & 1;
+                code | (bit as usize) << i
+            });
+            position += code_size;
+            if code == clear_code {
+                dictionary = (0..=255u8).map(|i| vec![i]).collect();
+                dictionary.extend(vec![Vec::new(), Vec::new()]);
+                code_size = 9;
+                previous = None;
+                continue;
+            }
+            if code == end_code {
+                return out;
+            }
+            let entry = match (dictionary.get(code), previous) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(prev)) => {
+                    let mut entry = dictionary[prev].clone();
+                    entry.push(dictionary[prev][0]);
+                    entry
+                },
+                (None, None) => panic!("unknown code {}", code)
+            };
+            if let Some(prev) = previous {
+                let mut added = dictionary[prev].clone();
+                added.push(entry[0]);
+                dictionary.push(added);
+                if dictionary.len() == 1 << code_size && code_size < 12 {
+                    code_size += 1;
+                }
+            }
+            out.extend_from_slice(&entry);
+            previous = Some(code);
+        }
+    }
+
+    // Local colour tables and pixels of every frame
+    fn read_gif_frames(bytes: &[u8]) -> Vec<(Vec<u32>, Vec<u8>)> {
+        assert_eq!(&bytes[..6], b"GIF89a");
+        let mut offset = 13 + 19; // header, screen descriptor and the looping extension
+        let mut frames = Vec::new();
+        while bytes[offset] != GIF_TRAILER {
+            assert_eq!(&bytes[offset..offset + 3], &[GIF_EXTENSION, 0xF9, 0x04]);
+            offset += 8;
+            assert_eq!(bytes[offset], GIF_IMAGE_DESCRIPTOR);
+            offset += 10;
+            let palette: Vec<u32> = bytes[offset..offset + 768]
+                .chunks(3)
+                .map(|c| 0xFF_00_00_00 | (c[0] as u32) << 16 | (c[1] as u32) << 8 | c[2] as u32)
+                .collect();
+            offset += 768 + 1;
+            let mut data = Vec::new();
+            while bytes[offset] != 0 {
+                let len = bytes[offset] as usize;
+                data.extend_from_slice(&bytes[offset + 1..offset + 1 + len]);
+                offset += 1 + len;
+            }
+            offset += 1;
+            frames.push((palette, lzw_decode(&data)));
+        }
+        frames
+    }
+
+    // two frames sharing the indexes but not the palette, as Smacker clips do
+    fn two_palette_animation() -> Animation {
+        let (width, height) = (40, 30);
+        let palette_indexes: Vec<u8> = (0..width * height).map(|i| ((i / 3) % 256) as u8).collect();
+        let frames = (0..2u32)
+            .map(|frame_id| AnimationFrame {
+                palette: (0..256u32).map(|i| 0xFF_00_00_00 | (i * (frame_id + 1)) & 0xFF).collect(),
+                palette_indexes: palette_indexes.clone(),
+                transparent_index: if frame_id == 0 { None } else { Some(5) },
+                delay_ms: 100
+            })
+            .collect();
+        Animation { width, height, loop_count: 0, frames }
+    }
+
+    #[test]
+    fn test_gif_round_trip() {
+        let animation = two_palette_animation();
+        let mut bytes = Vec::new();
+        animation.write_gif(&mut bytes).unwrap();
+        let frames = read_gif_frames(&bytes);
+        assert_eq!(frames.len(), 2);
+        for ((palette, indexes), frame) in frames.iter().zip(animation.frames.iter()) {
+            assert_eq!(palette, &frame.palette);
+            assert_eq!(indexes, &frame.palette_indexes);
+        }
+    }
+
+    #[test]
+    fn test_gif_long_frame_resets_dictionary() {
+        let palette_indexes: Vec<u8> = (0..200 * 200u32).map(|i| (i * 7 / 13 % 251) as u8).collect();
+        let frame = AnimationFrame {
+            palette: vec![0xFF_00_00_00; 256],
+            palette_indexes: palette_indexes.clone(),
+            transparent_index: None,
+            delay_ms: 0
+        };
+        let animation = Animation { width: 200, height: 200, loop_count: 1, frames: vec![frame] };
+        let mut bytes = Vec::new();
+        animation.write_gif(&mut bytes).unwrap();
+        assert_eq!(read_gif_frames(&bytes)[0].1, palette_indexes);
+    }
+
+    #[test]
+    fn test_apng_round_trip() {
+        let animation = two_palette_animation();
+        let mut bytes = Vec::new();
+        animation.write_apng(&mut bytes).unwrap();
+        let chunks = read_chunks(&bytes);
+        let kinds: Vec<&[u8; 4]> = chunks.iter().map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, vec![b"IHDR", b"acTL", b"fcTL", b"IDAT", b"fcTL", b"fdAT", b"IEND"]);
+        let stride = animation.width * 4;
+        let first = unfilter(&zlib_decompress(&chunks[3].1), stride);
+        let second = unfilter(&zlib_decompress(&chunks[5].1[4..]), stride);
+        for (pixels, frame) in [first, second].iter().zip(animation.frames.iter()) {
+            let expected = png::rgba_bytes(&frame.to_rgba(animation.width, animation.height));
+            assert_eq!(pixels, &expected);
+        }
+        // sequence numbers run across fcTL and fdAT chunks
+        assert_eq!(&chunks[4].1[..4], &1u32.to_be_bytes());
+        assert_eq!(&chunks[5].1[..4], &2u32.to_be_bytes());
+    }
+}
@@ -39,10 +39,11 @@ pub struct RawBmp {
     pub raw_data: Vec<u8>
 }
 impl RawBmp {
+    #[allow(clippy::unused_io_amount)]
     pub fn read_from<TStream: Read + Seek>(stream: &mut TStream) -> Result<Option<Self>> {
         let magic = &mut [0u8, 0u8];
         stream.read(magic)?;
-        if magic != &[b'B', b'M'] {
+        if magic != b"BM" {
             return Ok(None); // not a bmp file. Just return None in this case
         }
         stream.seek(SeekFrom::Current(8))?; // ignoring 8 unused bytes
@@ -64,7 +65,7 @@ impl RawBmp {
             let scanline_size = header.width as usize * header.bi_bit_count as usize / 8;
             let remainder = scanline_size % 4;
             let scanline_padding = if remainder == 0 { 0 } else { 4 - remainder };
-            let data_size = (scanline_size + scanline_padding) * header.height.abs() as usize;
+            let data_size = (scanline_size + scanline_padding) * header.height.unsigned_abs() as usize;
             let mut raw_data = vec![0u8; data_size];
             stream.seek(SeekFrom::Start(bfh_pixel_data))?;
             stream.read(&mut raw_data)?;
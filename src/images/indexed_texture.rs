@@ -0,0 +1,249 @@
+use std::io::{Result, Write};
+use crate::alm::FractionEntry;
+use crate::images::ingame_sprite::{DecodedFrame, ImageType};
+use crate::images::png::{write_png, PngColorType};
+use crate::images::quantization::{PaletteLayout, PaletteMapper};
+use crate::multimedia::SmackerDecodeContext;
+
+const LUT_WIDTH: usize = 256;
+
+// Single channel texture of palette indexes, meant to be sampled through a PaletteLut in a shader
+#[derive(Clone, Debug)]
+pub struct IndexTexture {
+    pub width: usize,
+    pub height: usize,
+    pub indexes: Vec<u8>,
+    pub transparent_index: Option<u8>
+}
+impl IndexTexture {
+    // Transparent pixels get transparent_index, opaque ones that happen to use it
+    // are moved to the nearest other palette entry. None for .16/.16a frames, they hold colours and not indexes
+    pub fn from_decoded_frame(frame: &DecodedFrame, palette: &[u32], transparent_index: u8) -> Option<Self> {
+        if frame.image_type != ImageType::Dot256 {
+            return None;
+        }
+        let mut mapper = None;
+        let indexes = frame.values
+            .iter()
+            .zip(frame.mask.iter())
+            .map(|(&value, &opaque)| {
+                let idx = (value & 0xFF) as u8;
+                if !opaque {
+                    transparent_index
+                } else if idx == transparent_index {
+                    let mapper = mapper.get_or_insert_with(|| {
+                        let layout = PaletteLayout {
+                            transparent_index: Some(transparent_index),
+                            reserved_ranges: Vec::new()
+                        };
+                        PaletteMapper::new(palette, &layout)
+                    });
+                    // a palette with the transparent colour alone has nothing to move the pixel to
+                    mapper.as_mut().map_or(idx, |mapper| mapper.nearest(palette[idx as usize]))
+                } else {
+                    idx
+                }
+            })
+            .collect();
+        Some(Self {
+            width: frame.width,
+            height: frame.height,
+            indexes,
+            transparent_index: Some(transparent_index)
+        })
+    }
+
+    // The currently unpacked Smacker frame; its palette goes to PaletteLut::push_smacker_palette
+    pub fn from_smacker(context: &SmackerDecodeContext, width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            indexes: context.image[..width * height].to_vec(),
+            transparent_index: None
+        }
+    }
+
+    pub fn write_png<TStream: Write>(&self, stream: &mut TStream) -> Result<()> {
+        write_png(stream, self.width as u32, self.height as u32, PngColorType::Grayscale, &self.indexes)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PaletteLutRow {
+    pub label: String,
+    pub color_id: Option<u32>,
+    pub palette: Vec<u32>
+}
+
+// 256 x N RGBA texture, one palette variant per row
+#[derive(Clone, Debug, Default)]
+pub struct PaletteLut {
+    pub rows: Vec<PaletteLutRow>,
+    pub transparent_index: Option<u8>
+}
+impl PaletteLut {
+    pub fn new(transparent_index: Option<u8>) -> Self {
+        Self {
+            rows: Vec::new(),
+            transparent_index
+        }
+    }
+
+    // Returns the row number to hand to the shader
+    pub fn push_row(&mut self, label: &str, color_id: Option<u32>, palette: &[u32]) -> usize {
+        let mut palette = palette[..palette.len().min(LUT_WIDTH)].to_vec();
+        palette.resize(LUT_WIDTH, 0xFF_00_00_00);
+        if let Some(transparent) = self.transparent_index {
+            palette[transparent as usize] = 0;
+        }
+        self.rows.push(PaletteLutRow {
+            label: label.to_string(),
+            color_id,
+            palette
+        });
+        self.rows.len() - 1
+    }
+
+    pub fn push_smacker_palette(&mut self, label: &str, context: &SmackerDecodeContext) -> usize {
+        let palette: Vec<u32> = context.palette
+            .iter()
+            .map(|&(r, g, b)| 0xFF_00_00_00 | (r as u32) << 16 | (g as u32) << 8 | b as u32)
+            .collect();
+        self.push_row(label, None, &palette)
+    }
+
+    pub fn row_of_color_id(&self, color_id: u32) -> Option<usize> {
+        self.rows.iter().position(|row| row.color_id == Some(color_id))
+    }
+
+    // One row per distinct color_id, built by the caller from the base palette (team colour ramps etc.).
+    // The returned vector holds the row of every fraction, in fraction order
+    pub fn push_fraction_rows<F: Fn(u32, &[u32]) -> Vec<u32>>(
+        &mut self,
+        base_palette: &[u32],
+        fractions: &[FractionEntry],
+        make_variant: F
+    ) -> Vec<usize> {
+        fractions
+            .iter()
+            .map(|fraction| match self.row_of_color_id(fraction.color_id) {
+                Some(row) => row,
+                None => {
+                    let variant = make_variant(fraction.color_id, base_palette);
+                    let label = format!("color_{}", fraction.color_id);
+                    self.push_row(&label, Some(fraction.color_id), &variant)
+                }
+            })
+            .collect()
+    }
+
+    pub fn write_png<TStream: Write>(&self, stream: &mut TStream) -> Result<()> {
+        let mut bytes = Vec::with_capacity(self.rows.len() * LUT_WIDTH * 4);
+        for row in self.rows.iter() {
+            for &color in row.palette.iter() {
+                bytes.extend_from_slice(&[(color >> 16) as u8, (color >> 8) as u8, color as u8, (color >> 24) as u8]);
+            }
+        }
+        write_png(stream, LUT_WIDTH as u32, self.rows.len() as u32, PngColorType::Rgba, &bytes)
+    }
+
+    // Tiny JSON document telling the engine which texture pairs with which LUT row
+    pub fn write_metadata<TStream: Write>(
+        &self,
+        stream: &mut TStream,
+        index_texture_path: &str,
+        lut_texture_path: &str,
+        fraction_rows: &[usize]
+    ) -> Result<()> {
+        writeln!(stream, "{{")?;
+        writeln!(stream, "  \"index_texture\": \"{}\",", json_escape(index_texture_path))?;
+        writeln!(stream, "  \"lut_texture\": \"{}\",", json_escape(lut_texture_path))?;
+        writeln!(stream, "  \"lut_width\": {},", LUT_WIDTH)?;
+        writeln!(stream, "  \"lut_height\": {},", self.rows.len())?;
+        match self.transparent_index {
+            Some(idx) => writeln!(stream, "  \"transparent_index\": {},", idx)?,
+            None => writeln!(stream, "  \"transparent_index\": null,")?
+        }
+        writeln!(stream, "  \"rows\": [")?;
+        for (row_id, row) in self.rows.iter().enumerate() {
+            let color_id = row.color_id.map_or("null".to_string(), |id| id.to_string());
+            let separator = if row_id + 1 < self.rows.len() { "," } else { "" };
+            writeln!(
+                stream,
+                "    {{ \"row\": {}, \"label\": \"{}\", \"color_id\": {} }}{}",
+                row_id, json_escape(&row.label), color_id, separator
+            )?;
+        }
+        writeln!(stream, "  ],")?;
+        let fraction_rows: Vec<String> = fraction_rows.iter().map(|row| row.to_string()).collect();
+        writeln!(stream, "  \"fraction_rows\": [{}]", fraction_rows.join(", "))?;
+        writeln!(stream, "}}")
+    }
+}
+
+fn json_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alm::FractionEntry;
+    use crate::images::indexed_texture::*;
+
+    const PALETTE: [u32; 3] = [0xFF_00_00_00, 0xFF_10_10_10, 0xFF_FF_FF_FF];
+
+    fn frame(image_type: ImageType) -> DecodedFrame {
+        DecodedFrame {
+            image_type,
+            width: 2,
+            height: 2,
+            values: vec![0, 1, 2, 0],
+            mask: vec![true, true, true, false]
+        }
+    }
+
+    #[test]
+    fn test_index_texture() {
+        let texture = IndexTexture::from_decoded_frame(&frame(ImageType::Dot256), &PALETTE, 0).unwrap();
+        // black is taken by transparency, the opaque black pixel goes to the dark grey
+        assert_eq!(texture.indexes, vec![1, 1, 2, 0]);
+        assert_eq!(texture.transparent_index, Some(0));
+
+        assert!(IndexTexture::from_decoded_frame(&frame(ImageType::Dot16), &PALETTE, 0).is_none());
+        assert!(IndexTexture::from_decoded_frame(&frame(ImageType::Dot16a), &PALETTE, 0).is_none());
+    }
+
+    #[test]
+    fn test_palette_lut_rows() {
+        let mut lut = PaletteLut::new(Some(0));
+        assert_eq!(lut.push_row("base", None, &PALETTE), 0);
+        let row = &lut.rows[0].palette;
+        assert_eq!(row.len(), 256);
+        assert_eq!(&row[..4], &[0, 0xFF_10_10_10, 0xFF_FF_FF_FF, 0xFF_00_00_00]);
+
+        let fraction = |color_id| FractionEntry {
+            color_id,
+            flags: 0,
+            money: 0,
+            name: String::new(),
+            diplomacy_states: [0; 0x10]
+        };
+        let fractions = [fraction(3), fraction(5), fraction(3)];
+        let rows = lut.push_fraction_rows(&PALETTE, &fractions, |color_id, base| {
+            base.iter().map(|&color| color ^ color_id).collect()
+        });
+        assert_eq!(rows, vec![1, 2, 1]);
+        assert_eq!(lut.row_of_color_id(5), Some(2));
+        assert_eq!(lut.rows[2].palette[2], 0xFF_FF_FF_FA);
+        assert_eq!(lut.rows[2].label, "color_5");
+    }
+}
@@ -0,0 +1,202 @@
+use super::{ImageData, DecodedFrame};
+
+// right and bottom are exclusive
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FrameBounds {
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32
+}
+impl FrameBounds {
+    pub fn width(&self) -> u32 {
+        self.right - self.left
+    }
+
+    pub fn height(&self) -> u32 {
+        self.bottom - self.top
+    }
+
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        x >= self.left && x < self.right && y >= self.top && y < self.bottom
+    }
+}
+
+// Coarser masks would not fit the u32 frame coordinates
+pub const MAX_MASK_SCALE_SHIFT: u32 = 31;
+
+// One bit per cell. With scale_shift > 0 every cell covers a (1 << scale_shift)
+// square of frame pixels and is set if any of them is opaque
+#[derive(Clone, Debug)]
+pub struct HitMask {
+    pub width: u32,
+    pub height: u32,
+    pub scale_shift: u32,
+    bits: Vec<u8>
+}
+impl HitMask {
+    // scale_shift is clamped to MAX_MASK_SCALE_SHIFT
+    pub fn build(frame: &DecodedFrame, scale_shift: u32) -> Self {
+        let scale_shift = scale_shift.min(MAX_MASK_SCALE_SHIFT);
+        let cell = 1usize << scale_shift;
+        let width = frame.width.div_ceil(cell);
+        let height = frame.height.div_ceil(cell);
+        let mut bits = vec![0u8; (width * height).div_ceil(8)];
+        for y in 0..frame.height {
+            for x in 0..frame.width {
+                if frame.is_opaque(x, y) {
+                    let bit = (y >> scale_shift) * width + (x >> scale_shift);
+                    bits[bit / 8] |= 1 << (bit % 8);
+                }
+            }
+        }
+        Self {
+            width: width as u32,
+            height: height as u32,
+            scale_shift,
+            bits
+        }
+    }
+
+    // Coordinates are given in frame pixels regardless of the mask resolution
+    pub fn hit_test(&self, x: u32, y: u32) -> bool {
+        let (cx, cy) = (x >> self.scale_shift, y >> self.scale_shift);
+        if cx >= self.width || cy >= self.height {
+            return false;
+        }
+        let bit = (cy * self.width + cx) as usize;
+        self.bits[bit / 8] & (1 << (bit % 8)) != 0
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FrameAnalysis {
+    pub bounds: Option<FrameBounds>,
+    // Both are in pixel indexes: the centroid is the mean opaque pixel, not the middle of its area
+    pub centroid: Option<(f32, f32)>,
+    pub foot_point: Option<(u32, u32)>, // middle of the lowest opaque row, rounded down
+    pub hit_mask: HitMask
+}
+impl FrameAnalysis {
+    pub fn compute(frame: &DecodedFrame, mask_scale_shift: u32) -> Self {
+        let mut bounds: Option<FrameBounds> = None;
+        let (mut sum_x, mut sum_y, mut count) = (0u64, 0u64, 0u64);
+        for y in 0..frame.height {
+            for x in 0..frame.width {
+                if !frame.is_opaque(x, y) {
+                    continue;
+                }
+                let (x32, y32) = (x as u32, y as u32);
+                bounds = Some(match bounds {
+                    None => FrameBounds { left: x32, top: y32, right: x32 + 1, bottom: y32 + 1 },
+                    Some(b) => FrameBounds {
+                        left: b.left.min(x32),
+                        top: b.top.min(y32),
+                        right: b.right.max(x32 + 1),
+                        bottom: b.bottom.max(y32 + 1)
+                    }
+                });
+                sum_x += x as u64;
+                sum_y += y as u64;
+                count += 1;
+            }
+        }
+        let centroid = if count == 0 {
+            None
+        } else {
+            Some((sum_x as f32 / count as f32, sum_y as f32 / count as f32))
+        };
+        let foot_point = bounds.map(|b| {
+            let y = b.bottom - 1;
+            let opaque_xs: Vec<u32> = (b.left..b.right)
+                .filter(|&x| frame.is_opaque(x as usize, y as usize))
+                .collect();
+            let x = opaque_xs.iter().sum::<u32>() / opaque_xs.len() as u32;
+            (x, y)
+        });
+        Self {
+            bounds,
+            centroid,
+            foot_point,
+            hit_mask: HitMask::build(frame, mask_scale_shift)
+        }
+    }
+}
+
+impl ImageData {
+    pub fn analyze_frames(&mut self, mask_scale_shift: u32) {
+        for frame_id in 0..self.frames.len() {
+            self.frame_analysis(frame_id, mask_scale_shift);
+        }
+    }
+
+    // Computed on first access and kept in the frame; recomputed only if another
+    // mask resolution is requested
+    pub fn frame_analysis(&mut self, frame_id: usize, mask_scale_shift: u32) -> &FrameAnalysis {
+        let up_to_date = match &self.frames[frame_id].analysis {
+            Some(analysis) => analysis.hit_mask.scale_shift == mask_scale_shift.min(MAX_MASK_SCALE_SHIFT),
+            None => false
+        };
+        if !up_to_date {
+            let analysis = FrameAnalysis::compute(&self.decode_frame(frame_id), mask_scale_shift);
+            self.frames[frame_id].analysis = Some(analysis);
+        }
+        self.frames[frame_id].analysis.as_ref().unwrap()
+    }
+
+    // Picks against the hit mask of the frame, analyzing it at full resolution if
+    // it was never analyzed before
+    pub fn hit_test(&mut self, frame_id: usize, x: u32, y: u32) -> bool {
+        if self.frames[frame_id].analysis.is_none() {
+            self.frame_analysis(frame_id, 0);
+        }
+        self.frames[frame_id].analysis.as_ref().unwrap().hit_mask.hit_test(x, y)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::images::ingame_sprite::*;
+
+    fn single_frame_image() -> ImageData {
+        // 4x3 frame with two opaque pixels in the middle row
+        let mask = vec![
+            false, false, false, false,
+            false, true,  true,  false,
+            false, false, false, false
+        ];
+        let mut image = ImageData { image_type: ImageType::Dot256, raw: Vec::new(), frames: Vec::new() };
+        image.push_frame(4, 3, &encode_dot256_frame(4, 3, &[7; 12], &mask));
+        image
+    }
+
+    #[test]
+    fn test_hit_test_analyzes_frame_once() {
+        let mut image = single_frame_image();
+        assert!(image.hit_test(0, 1, 1));
+        assert!(!image.hit_test(0, 0, 1));
+        assert!(!image.hit_test(0, 10, 10));
+        assert!(image.frames[0].analysis.is_some());
+        assert_eq!(image.frames[0].analysis.as_ref().unwrap().hit_mask.scale_shift, 0);
+    }
+
+    #[test]
+    fn test_huge_scale_shift_is_clamped() {
+        let mut image = single_frame_image();
+        let analysis = image.frame_analysis(0, 64).clone();
+        assert_eq!(analysis.hit_mask.scale_shift, MAX_MASK_SCALE_SHIFT);
+        assert!(analysis.hit_mask.hit_test(0, 0));
+        assert_eq!(
+            analysis.bounds,
+            Some(FrameBounds { left: 1, top: 1, right: 3, bottom: 2 })
+        );
+    }
+
+    #[test]
+    fn test_centroid_and_foot_point_agree() {
+        let mut image = single_frame_image();
+        let analysis = image.frame_analysis(0, 0);
+        assert_eq!(analysis.centroid, Some((1.5, 1.0)));
+        assert_eq!(analysis.foot_point, Some((1, 1)));
+    }
+}
@@ -0,0 +1,176 @@
+use super::{ImageType, ImageData, ImageFrameData};
+use crate::images::rgba_image::RgbaImage;
+
+// Frames are RLE-packed. Every control unit (a byte for .256, a little endian word for .16/.16a)
+// has two high bits telling what to do:
+// 0b00 - copy the following N pixels,
+// 0b01 - skip N whole lines,
+// 0b10 and 0b11 - skip N pixels, possibly wrapping to the next line
+// Skipped pixels are transparent.
+const DOT256_COUNT_MASK: u8 = 0x3F;
+const DOT16_COUNT_MASK: u16 = 0xFF;
+
+#[derive(Clone, Debug)]
+pub struct DecodedFrame {
+    pub image_type: ImageType,
+    pub width: usize,
+    pub height: usize,
+    pub values: Vec<u16>, // palette index for .256, raw word for .16/.16a
+    pub mask: Vec<bool>   // true for pixels which are actually drawn
+}
+impl DecodedFrame {
+    pub fn is_opaque(&self, x: usize, y: usize) -> bool {
+        self.mask[y * self.width + x]
+    }
+
+    pub fn to_rgba(&self, palette: &[u32]) -> RgbaImage {
+        let mut image = RgbaImage::new(self.width, self.height);
+        for (i, pixel) in image.pixels.iter_mut().enumerate() {
+            if self.mask[i] {
+                *pixel = palette[(self.values[i] & 0xFF) as usize] | 0xFF_00_00_00;
+            }
+        }
+        image
+    }
+
+    pub fn palette_indexes(&self) -> Vec<u8> {
+        self.values.iter().map(|&v| (v & 0xFF) as u8).collect()
+    }
+}
+
+pub fn decode_frame(image_type: ImageType, frame: &ImageFrameData, raw: &[u8]) -> DecodedFrame {
+    let width = frame.width as usize;
+    let height = frame.height as usize;
+    let mut values = vec![0u16; width * height];
+    let mut mask = vec![false; width * height];
+    let data = &raw[frame.data_range.clone()];
+    let total = width * height;
+    let mut position = 0;
+    let mut offset = 0;
+    let unit_size = if image_type == ImageType::Dot256 { 1 } else { 2 };
+    while offset + unit_size <= data.len() && position < total {
+        let (kind, count) = if unit_size == 1 {
+            let b = data[offset];
+            (b >> 6, (b & DOT256_COUNT_MASK) as usize)
+        } else {
+            let w = u16::from_le_bytes([data[offset], data[offset + 1]]);
+            ((w >> 14) as u8, (w & DOT16_COUNT_MASK) as usize)
+        };
+        offset += unit_size;
+        match kind {
+            0 => {
+                for _ in 0..count {
+                    if offset + unit_size > data.len() || position >= total {
+                        break;
+                    }
+                    values[position] = if unit_size == 1 {
+                        data[offset] as u16
+                    } else {
+                        u16::from_le_bytes([data[offset], data[offset + 1]])
+                    };
+                    mask[position] = true;
+                    offset += unit_size;
+                    position += 1;
+                }
+            },
+            1 => position += count * width,
+            _ => position += count
+        }
+    }
+    DecodedFrame {
+        image_type,
+        width,
+        height,
+        values,
+        mask
+    }
+}
+
+pub fn encode_dot256_frame(width: usize, height: usize, palette_indexes: &[u8], mask: &[bool]) -> Vec<u8> {
+    let mut packed = Vec::new();
+    let mut pending_lines = 0;
+    for y in 0..height {
+        let row = y * width..(y + 1) * width;
+        if mask[row.clone()].iter().all(|&opaque| !opaque) {
+            pending_lines += 1;
+            continue;
+        }
+        while pending_lines > 0 {
+            let n = pending_lines.min(DOT256_COUNT_MASK as usize);
+            packed.push(0x40 | n as u8);
+            pending_lines -= n;
+        }
+        let mut x = 0;
+        while x < width {
+            let start = x;
+            let opaque = mask[row.start + x];
+            while x < width && mask[row.start + x] == opaque && x - start < DOT256_COUNT_MASK as usize {
+                x += 1;
+            }
+            let n = x - start;
+            if opaque {
+                packed.push(n as u8);
+                packed.extend_from_slice(&palette_indexes[row.start + start..row.start + x]);
+            } else {
+                packed.push(0x80 | n as u8);
+            }
+        }
+    }
+    packed
+}
+
+impl ImageData {
+    pub fn decode_frame(&self, frame_id: usize) -> DecodedFrame {
+        decode_frame(self.image_type, &self.frames[frame_id], &self.raw)
+    }
+
+    pub fn push_frame(&mut self, width: u32, height: u32, packed: &[u8]) {
+        let start = self.raw.len();
+        self.raw.extend_from_slice(packed);
+        self.frames.push(ImageFrameData {
+            width,
+            height,
+            data_range: start..self.raw.len(),
+            analysis: None
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::images::ingame_sprite::*;
+
+    #[test]
+    fn test_dot256_round_trip() {
+        let (width, height) = (70, 5);
+        let mut palette_indexes = vec![0u8; width * height];
+        let mut mask = vec![false; width * height];
+        for y in 0..height {
+            for x in 0..width {
+                let i = y * width + x;
+                palette_indexes[i] = (x * 3 + y) as u8;
+                // an empty line, a line longer than one control unit and a few gaps
+                mask[i] = y != 1 && (y == 2 || x % 5 != 0);
+            }
+        }
+        let packed = encode_dot256_frame(width, height, &palette_indexes, &mask);
+        let mut image = ImageData { image_type: ImageType::Dot256, raw: Vec::new(), frames: Vec::new() };
+        image.push_frame(width as u32, height as u32, &packed);
+        let decoded = image.decode_frame(0);
+        assert_eq!(decoded.mask, mask);
+        for (i, &opaque) in mask.iter().enumerate() {
+            if opaque {
+                assert_eq!(decoded.values[i], palette_indexes[i] as u16);
+            }
+        }
+    }
+
+    #[test]
+    fn test_dot256_transparent_frame() {
+        let packed = encode_dot256_frame(3, 100, &[0; 300], &[false; 300]);
+        assert!(packed.is_empty());
+        let mut image = ImageData { image_type: ImageType::Dot256, raw: Vec::new(), frames: Vec::new() };
+        image.push_frame(3, 100, &packed);
+        assert!(image.decode_frame(0).mask.iter().all(|&opaque| !opaque));
+    }
+}
@@ -3,14 +3,20 @@ use std::io::{Result, Read, Seek, Cursor, SeekFrom};
 use bin_serialization_rs::{Reflectable, Endianness};
 use crate::shared_types::U32Wrapper;
 
+mod frame_codec;
+mod frame_analysis;
+pub use frame_codec::*;
+pub use frame_analysis::*;
+
 #[derive(Clone)]
 pub struct ImageFrameData {
     pub width: u32,
     pub height: u32,
-    pub data_range: Range<usize>
+    pub data_range: Range<usize>,
+    pub analysis: Option<FrameAnalysis>
 }
 
-#[derive(PartialEq, Clone, Copy)]
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum ImageType {
     Dot256,
     Dot16,
@@ -86,6 +92,7 @@ pub fn read_raw_palette(
     Ok(Some(v))
 }
 
+#[allow(clippy::unused_io_amount)]
 pub(crate) fn read_image_frames(
     stream: &mut Cursor<&[u8]>,
     given_sprite_count: u32,
@@ -97,7 +104,8 @@ pub(crate) fn read_image_frames(
     let mut staging_frame = ImageFrameData {
         width: 0,
         height: 0,
-        data_range: 0..0
+        data_range: 0..0,
+        analysis: None
     };
     for _ in 0..given_sprite_count {
         staging_frame.width = *U32Wrapper::deserialize(stream, Endianness::LittleEndian)?;
@@ -110,7 +118,7 @@ pub(crate) fn read_image_frames(
         stream.read(&mut raw_data_buffer[0..data_size])?;
         staging_frame.data_range.start = staging_frame.data_range.end;
         staging_frame.data_range.end = staging_frame.data_range.start + data_size;
-        (&raw_data_buffer[0..data_size]).iter().for_each(|s| sprite_data.push(*s));
+        raw_data_buffer[0..data_size].iter().for_each(|s| sprite_data.push(*s));
         image_frames.push(staging_frame.clone());
     }
     Ok(ImageData {
@@ -1,3 +1,11 @@
 pub mod bmp;
 pub mod sprite;
-pub mod ingame_sprite;
\ No newline at end of file
+pub mod ingame_sprite;
+pub mod rgba_image;
+pub mod quantization;
+pub mod palette_files;
+pub mod palette_resolver;
+pub mod png;
+pub mod animation;
+pub mod scaling;
+pub mod indexed_texture;
\ No newline at end of file
@@ -0,0 +1,220 @@
+use std::io::{Result, Read, Write, ErrorKind};
+use bin_serialization_rs::{Reflectable, Endianness};
+use crate::shared_types::{U16Wrapper, U32Wrapper};
+
+const RIFF: u32 = 0x46_46_49_52;
+const PAL: u32 = 0x20_4C_41_50;
+const DATA: u32 = 0x6174_6164; // "data"
+const RIFF_PAL_VERSION: u16 = 0x0300;
+const JASC_SIGNATURE: &str = "JASC-PAL";
+const JASC_VERSION: &str = "0100";
+const PALETTE_SIZE: usize = 256;
+const RGB_PALETTE_BYTES: usize = PALETTE_SIZE * 3;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PaletteFileFormat {
+    JascPal,
+    RiffPal,
+    Act,
+    Raw
+}
+impl PaletteFileFormat {
+    // .pal is ambiguous, so prefer detect_palette_format when the content is at hand
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "pal" => Some(Self::JascPal),
+            "act" => Some(Self::Act),
+            "raw" | "rgb" => Some(Self::Raw),
+            _ => None
+        }
+    }
+}
+
+pub fn detect_palette_format(bytes: &[u8]) -> Option<PaletteFileFormat> {
+    if bytes.starts_with(JASC_SIGNATURE.as_bytes()) {
+        Some(PaletteFileFormat::JascPal)
+    } else if bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"PAL " {
+        Some(PaletteFileFormat::RiffPal)
+    } else if bytes.len() == RGB_PALETTE_BYTES + 4 {
+        Some(PaletteFileFormat::Act)
+    } else if bytes.len() == RGB_PALETTE_BYTES {
+        Some(PaletteFileFormat::Raw)
+    } else {
+        None
+    }
+}
+
+pub fn read_palette_file<TStream: Read>(stream: &mut TStream, format: PaletteFileFormat) -> Result<Vec<u32>> {
+    let mut colors = match format {
+        PaletteFileFormat::JascPal => read_jasc(stream)?,
+        PaletteFileFormat::RiffPal => read_riff(stream)?,
+        PaletteFileFormat::Act => read_act(stream)?,
+        PaletteFileFormat::Raw => read_rgb_triplets(stream, PALETTE_SIZE)?
+    };
+    colors.resize(PALETTE_SIZE, 0xFF_00_00_00);
+    Ok(colors)
+}
+
+pub fn write_palette_file<TStream: Write>(
+    stream: &mut TStream,
+    palette: &[u32],
+    format: PaletteFileFormat
+) -> Result<()> {
+    let palette = &palette[..palette.len().min(PALETTE_SIZE)];
+    match format {
+        PaletteFileFormat::JascPal => {
+            write!(stream, "{}\r\n{}\r\n{}\r\n", JASC_SIGNATURE, JASC_VERSION, palette.len())?;
+            for &color in palette {
+                let (r, g, b) = rgb(color);
+                write!(stream, "{} {} {}\r\n", r, g, b)?;
+            }
+            Ok(())
+        },
+        PaletteFileFormat::RiffPal => {
+            let data_size = 4 + 4 * palette.len() as u32;
+            U32Wrapper(RIFF).serialize(stream, Endianness::LittleEndian)?;
+            U32Wrapper(data_size + 12).serialize(stream, Endianness::LittleEndian)?;
+            U32Wrapper(PAL).serialize(stream, Endianness::LittleEndian)?;
+            U32Wrapper(DATA).serialize(stream, Endianness::LittleEndian)?;
+            U32Wrapper(data_size).serialize(stream, Endianness::LittleEndian)?;
+            U16Wrapper(RIFF_PAL_VERSION).serialize(stream, Endianness::LittleEndian)?;
+            U16Wrapper(palette.len() as u16).serialize(stream, Endianness::LittleEndian)?;
+            for &color in palette {
+                let (r, g, b) = rgb(color);
+                stream.write_all(&[r, g, b, 0])?;
+            }
+            Ok(())
+        },
+        PaletteFileFormat::Act | PaletteFileFormat::Raw => {
+            let mut bytes = [0u8; RGB_PALETTE_BYTES];
+            for (chunk, &color) in bytes.chunks_mut(3).zip(palette.iter()) {
+                let (r, g, b) = rgb(color);
+                chunk.copy_from_slice(&[r, g, b]);
+            }
+            stream.write_all(&bytes)?;
+            if format == PaletteFileFormat::Act && palette.len() < PALETTE_SIZE {
+                // optional trailer: color count and transparent index (none)
+                U16Wrapper(palette.len() as u16).serialize(stream, Endianness::BigEndian)?;
+                U16Wrapper(0xFFFF).serialize(stream, Endianness::BigEndian)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn rgb(color: u32) -> (u8, u8, u8) {
+    ((color >> 16) as u8, (color >> 8) as u8, color as u8)
+}
+
+fn from_rgb(r: u8, g: u8, b: u8) -> u32 {
+    0xFF_00_00_00 | (r as u32) << 16 | (g as u32) << 8 | b as u32
+}
+
+fn read_rgb_triplets<TStream: Read>(stream: &mut TStream, count: usize) -> Result<Vec<u32>> {
+    let mut bytes = vec![0u8; count * 3];
+    stream.read_exact(&mut bytes)?;
+    Ok(bytes.chunks(3).map(|c| from_rgb(c[0], c[1], c[2])).collect())
+}
+
+fn read_act<TStream: Read>(stream: &mut TStream) -> Result<Vec<u32>> {
+    let mut colors = read_rgb_triplets(stream, PALETTE_SIZE)?;
+    let mut trailer = [0u8; 4];
+    if stream.read(&mut trailer)? == trailer.len() {
+        let color_count = u16::from_be_bytes([trailer[0], trailer[1]]) as usize;
+        if color_count > 0 && color_count <= PALETTE_SIZE {
+            colors.truncate(color_count);
+        }
+    }
+    Ok(colors)
+}
+
+fn read_riff<TStream: Read>(stream: &mut TStream) -> Result<Vec<u32>> {
+    let signature = *U32Wrapper::deserialize(stream, Endianness::LittleEndian)?;
+    let _riff_size = *U32Wrapper::deserialize(stream, Endianness::LittleEndian)?;
+    let form_type = *U32Wrapper::deserialize(stream, Endianness::LittleEndian)?;
+    if signature != RIFF || form_type != PAL {
+        return Err(std::io::Error::from(ErrorKind::InvalidInput));
+    }
+    loop {
+        let chunk_id = *U32Wrapper::deserialize(stream, Endianness::LittleEndian)?;
+        let chunk_size = *U32Wrapper::deserialize(stream, Endianness::LittleEndian)? as usize;
+        if chunk_id != DATA {
+            let mut skipped = vec![0u8; chunk_size + chunk_size % 2];
+            stream.read_exact(&mut skipped)?;
+            continue;
+        }
+        let _version = *U16Wrapper::deserialize(stream, Endianness::LittleEndian)?;
+        let entry_count = *U16Wrapper::deserialize(stream, Endianness::LittleEndian)? as usize;
+        let mut entries = vec![0u8; entry_count * 4];
+        stream.read_exact(&mut entries)?;
+        return Ok(entries.chunks(4).map(|c| from_rgb(c[0], c[1], c[2])).collect());
+    }
+}
+
+fn read_jasc<TStream: Read>(stream: &mut TStream) -> Result<Vec<u32>> {
+    let mut text = String::new();
+    stream.read_to_string(&mut text)?;
+    let invalid = || std::io::Error::from(ErrorKind::InvalidInput);
+    let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+    if lines.next() != Some(JASC_SIGNATURE) {
+        return Err(invalid());
+    }
+    let _version = lines.next().ok_or_else(invalid)?;
+    let entry_count: usize = lines.next().ok_or_else(invalid)?.parse().map_err(|_| invalid())?;
+    let mut colors = Vec::with_capacity(entry_count);
+    for line in lines.take(entry_count) {
+        let channels: Vec<u8> = line
+            .split_whitespace()
+            .take(3)
+            .map(|v| v.parse::<u8>())
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|_| invalid())?;
+        if channels.len() != 3 {
+            return Err(invalid());
+        }
+        colors.push(from_rgb(channels[0], channels[1], channels[2]));
+    }
+    Ok(colors)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::images::palette_files::*;
+    use std::io::Cursor;
+
+    const FORMATS: [PaletteFileFormat; 4] = [
+        PaletteFileFormat::JascPal,
+        PaletteFileFormat::RiffPal,
+        PaletteFileFormat::Act,
+        PaletteFileFormat::Raw
+    ];
+
+    fn round_trip(palette: &[u32], format: PaletteFileFormat) -> Vec<u32> {
+        let mut bytes = Vec::new();
+        write_palette_file(&mut bytes, palette, format).unwrap();
+        // a full .act has no trailer and is detected as raw, which reads the same
+        let detected = detect_palette_format(&bytes).unwrap();
+        read_palette_file(&mut Cursor::new(&bytes[..]), detected).unwrap()
+    }
+
+    #[test]
+    fn test_full_palette_round_trip() {
+        let palette: Vec<u32> = (0..256u32)
+            .map(|i| 0xFF_00_00_00 | i << 16 | (255 - i) << 8 | (i * 7) & 0xFF)
+            .collect();
+        for &format in FORMATS.iter() {
+            assert_eq!(round_trip(&palette, format), palette, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn test_short_palette_is_padded_with_black() {
+        let palette = vec![0xFF_12_34_56, 0xFF_FF_FF_FF, 0xFF_00_80_00];
+        for &format in FORMATS.iter() {
+            let read = round_trip(&palette, format);
+            assert_eq!(read.len(), 256);
+            assert_eq!(&read[..3], &palette[..], "{:?}", format);
+            assert!(read[3..].iter().all(|&color| color == 0xFF_00_00_00), "{:?}", format);
+        }
+    }
+}
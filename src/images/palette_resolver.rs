@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Result};
+use std::path::PathBuf;
+use crate::images::bmp::RawBmp;
+use crate::images::ingame_sprite::{read_palette, ImageType};
+use crate::images::palette_files::{detect_palette_format, read_palette_file};
+use crate::regfile::Registry;
+
+// Anything files can be fetched from: a directory of an unpacked install, an archive, a test fixture
+pub trait FileSource {
+    fn read_file(&self, path: &str) -> Option<Vec<u8>>;
+}
+impl FileSource for HashMap<String, Vec<u8>> {
+    fn read_file(&self, path: &str) -> Option<Vec<u8>> {
+        self.get(path).cloned()
+    }
+}
+
+pub struct DirectorySource {
+    pub root: PathBuf
+}
+impl FileSource for DirectorySource {
+    fn read_file(&self, path: &str) -> Option<Vec<u8>> {
+        std::fs::read(self.root.join(path)).ok()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PaletteRule {
+    Embedded,
+    CompanionBmp,
+    CompanionPal,
+    DirectoryDefault,
+    Registry
+}
+
+#[derive(Clone, Debug)]
+pub struct ResolvedPalette {
+    pub palette: Vec<u32>,
+    pub rule: PaletteRule,
+    pub source_path: String
+}
+
+// Rules are tried in the order of PaletteRule declaration
+pub struct PaletteResolver {
+    pub directory_defaults: Vec<String>, // file names looked up in the sprite directory
+    pub registry_section: String         // registry strings are looked up as "<section>/<sprite stem>"
+}
+impl Default for PaletteResolver {
+    fn default() -> Self {
+        Self {
+            directory_defaults: vec!["palette.bmp".to_string(), "palette.pal".to_string()],
+            registry_section: "Palettes".to_string()
+        }
+    }
+}
+impl PaletteResolver {
+    pub fn resolve<TSource: FileSource>(
+        &self,
+        sprite_path: &str,
+        source: &TSource,
+        registry: Option<&mut Registry>
+    ) -> Result<Option<ResolvedPalette>> {
+        let (directory, stem, extension) = split_path(sprite_path);
+        let resolved = |palette: Vec<u32>, rule: PaletteRule, source_path: String| {
+            Ok(Some(ResolvedPalette { palette, rule, source_path }))
+        };
+
+        if let (Some(image_type), Some(bytes)) = (image_type_of(&extension), source.read_file(sprite_path)) {
+            if let Some(palette) = read_palette(&mut Cursor::new(&bytes[..]), image_type)? {
+                return resolved(palette, PaletteRule::Embedded, sprite_path.to_string());
+            }
+        }
+
+        let companion_bmp = format!("{}{}.bmp", directory, stem);
+        if let Some(palette) = load_palette_file(&companion_bmp, source)? {
+            return resolved(palette, PaletteRule::CompanionBmp, companion_bmp);
+        }
+        let companion_pal = format!("{}{}.pal", directory, stem);
+        if let Some(palette) = load_palette_file(&companion_pal, source)? {
+            return resolved(palette, PaletteRule::CompanionPal, companion_pal);
+        }
+
+        for default_name in self.directory_defaults.iter() {
+            let default_path = format!("{}{}", directory, default_name);
+            if let Some(palette) = load_palette_file(&default_path, source)? {
+                return resolved(palette, PaletteRule::DirectoryDefault, default_path);
+            }
+        }
+
+        if let Some(registry) = registry {
+            let key = format!("{}/{}", self.registry_section, stem);
+            if let Ok(palette_path) = registry.get_string(&key) {
+                let palette_path = palette_path.to_string();
+                if let Some(palette) = load_palette_file(&palette_path, source)? {
+                    return resolved(palette, PaletteRule::Registry, palette_path);
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+// .bmp files give their palette table, everything else goes through palette_files
+pub fn load_palette_file<TSource: FileSource>(path: &str, source: &TSource) -> Result<Option<Vec<u32>>> {
+    let bytes = match source.read_file(path) {
+        None => return Ok(None),
+        Some(bytes) => bytes
+    };
+    if bytes.starts_with(b"BM") {
+        // only 8 bit images have a palette table
+        return Ok(RawBmp::read_from(&mut Cursor::new(&bytes[..]))?
+            .filter(|bmp| bmp.header.bi_bit_count == 8)
+            .and_then(|bmp| bmp.palette)
+            .map(|palette| palette.iter().map(|&color| color | 0xFF_00_00_00).collect()));
+    }
+    match detect_palette_format(&bytes) {
+        None => Ok(None),
+        Some(format) => Ok(Some(read_palette_file(&mut Cursor::new(&bytes[..]), format)?))
+    }
+}
+
+fn image_type_of(extension: &str) -> Option<ImageType> {
+    match extension.to_ascii_lowercase().as_str() {
+        "256" => Some(ImageType::Dot256),
+        "16a" => Some(ImageType::Dot16a),
+        "16" => Some(ImageType::Dot16),
+        _ => None
+    }
+}
+
+// "graphics/units/orc.256" -> ("graphics/units/", "orc", "256")
+fn split_path(path: &str) -> (String, String, String) {
+    let name_start = path.rfind(['/', '\\']).map_or(0, |idx| idx + 1);
+    let (directory, file_name) = path.split_at(name_start);
+    match file_name.rfind('.') {
+        None => (directory.to_string(), file_name.to_string(), String::new()),
+        Some(dot) => (
+            directory.to_string(),
+            file_name[..dot].to_string(),
+            file_name[dot + 1..].to_string()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::images::palette_files::*;
+    use crate::images::palette_resolver::*;
+
+    fn bmp(bit_count: u16, palette: &[u32]) -> Vec<u8> {
+        let pixel_offset = 14 + 40 + palette.len() as u32 * 4;
+        let mut bytes = b"BM".to_vec();
+        for value in [pixel_offset + 4, 0, pixel_offset, 40, 1, 1] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&bit_count.to_le_bytes());
+        bytes.extend_from_slice(&[0u8; 24]);
+        for color in palette {
+            bytes.extend_from_slice(&color.to_le_bytes());
+        }
+        bytes.extend_from_slice(&[0u8; 4]);
+        bytes
+    }
+
+    fn pal(palette: &[u32]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_palette_file(&mut bytes, palette, PaletteFileFormat::JascPal).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_bmp_palette() {
+        let palette: Vec<u32> = (0..256u32).map(|i| i << 16 | i).collect();
+        let mut source = HashMap::new();
+        source.insert("units/orc.bmp".to_string(), bmp(8, &palette));
+        source.insert("units/true_color.bmp".to_string(), bmp(24, &[]));
+        let read = load_palette_file("units/orc.bmp", &source).unwrap().unwrap();
+        assert_eq!(read[1], 0xFF_01_00_01);
+        assert_eq!(read[255], 0xFF_FF_00_FF);
+        assert_eq!(load_palette_file("units/true_color.bmp", &source).unwrap(), None);
+    }
+
+    #[test]
+    fn test_lookup_order() {
+        let resolver = PaletteResolver::default();
+        let mut source = HashMap::new();
+        source.insert("units/palette.bmp".to_string(), bmp(24, &[]));
+        source.insert("units/palette.pal".to_string(), pal(&[0xFF_00_00_03]));
+        source.insert("units/orc.pal".to_string(), pal(&[0xFF_00_00_02]));
+        source.insert("units/orc.bmp".to_string(), bmp(8, &[0xFF_00_00_01; 256]));
+        let mut rules = Vec::new();
+        for path in ["units/orc.bmp", "units/orc.pal", "units/palette.pal"] {
+            let resolved = resolver.resolve("units/orc.256", &source, None).unwrap().unwrap();
+            rules.push((resolved.rule, resolved.source_path, resolved.palette[0]));
+            source.remove(path);
+        }
+        assert_eq!(rules, vec![
+            (PaletteRule::CompanionBmp, "units/orc.bmp".to_string(), 0xFF_00_00_01),
+            (PaletteRule::CompanionPal, "units/orc.pal".to_string(), 0xFF_00_00_02),
+            // palette.bmp has no palette table, the next default is used
+            (PaletteRule::DirectoryDefault, "units/palette.pal".to_string(), 0xFF_00_00_03)
+        ]);
+        assert!(resolver.resolve("units/orc.256", &source, None).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let source: HashMap<String, Vec<u8>> = HashMap::new();
+        assert_eq!(load_palette_file("palette.pal", &source).unwrap(), None);
+        assert!(PaletteResolver::default().resolve("orc.256", &source, None).unwrap().is_none());
+    }
+}
@@ -0,0 +1,389 @@
+use std::io::{Result, Write};
+use bin_serialization_rs::{Reflectable, Endianness};
+use crate::shared_types::U32Wrapper;
+use crate::images::rgba_image::RgbaImage;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const WINDOW_SIZE: usize = 0x8000;
+const HASH_BITS: u32 = 15;
+const MAX_CHAIN: usize = 64;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258
+];
+const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0
+];
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577
+];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13
+];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PngColorType {
+    Grayscale = 0,
+    Rgba = 6
+}
+impl PngColorType {
+    fn bytes_per_pixel(self) -> usize {
+        match self {
+            PngColorType::Grayscale => 1,
+            PngColorType::Rgba => 4
+        }
+    }
+}
+
+// pixels go row by row without any filter bytes: one byte per pixel for grayscale, RGBA quads otherwise
+pub fn write_png<TStream: Write>(
+    stream: &mut TStream,
+    width: u32,
+    height: u32,
+    color_type: PngColorType,
+    pixels: &[u8]
+) -> Result<()> {
+    stream.write_all(&PNG_SIGNATURE)?;
+    write_chunk(stream, b"IHDR", &ihdr(width, height, color_type))?;
+    write_chunk(stream, b"IDAT", &zlib_compress(&add_filter_bytes(width, color_type, pixels)))?;
+    write_chunk(stream, b"IEND", &[])
+}
+
+pub fn write_rgba_png<TStream: Write>(stream: &mut TStream, image: &RgbaImage) -> Result<()> {
+    write_png(stream, image.width as u32, image.height as u32, PngColorType::Rgba, &rgba_bytes(image))
+}
+
+pub(crate) fn ihdr(width: u32, height: u32, color_type: PngColorType) -> Vec<u8> {
+    let mut data = Vec::with_capacity(13);
+    data.extend_from_slice(&width.to_be_bytes());
+    data.extend_from_slice(&height.to_be_bytes());
+    data.extend_from_slice(&[8, color_type as u8, 0, 0, 0]); // 8 bit, deflate, no filter, no interlace
+    data
+}
+
+pub(crate) fn rgba_bytes(image: &RgbaImage) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(image.pixels.len() * 4);
+    for &pixel in image.pixels.iter() {
+        bytes.extend_from_slice(&[(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8, (pixel >> 24) as u8]);
+    }
+    bytes
+}
+
+pub(crate) fn add_filter_bytes(width: u32, color_type: PngColorType, pixels: &[u8]) -> Vec<u8> {
+    let stride = width as usize * color_type.bytes_per_pixel();
+    let mut filtered = Vec::with_capacity(pixels.len() + pixels.len() / stride.max(1) + 1);
+    for row in pixels.chunks(stride.max(1)) {
+        filtered.push(0);
+        filtered.extend_from_slice(row);
+    }
+    filtered
+}
+
+pub(crate) fn write_chunk<TStream: Write>(stream: &mut TStream, kind: &[u8; 4], data: &[u8]) -> Result<()> {
+    U32Wrapper(data.len() as u32).serialize(stream, Endianness::BigEndian)?;
+    stream.write_all(kind)?;
+    stream.write_all(data)?;
+    let mut crc = Crc32::new();
+    crc.update(kind);
+    crc.update(data);
+    U32Wrapper(crc.finish()).serialize(stream, Endianness::BigEndian)
+}
+
+// zlib stream holding a single deflate block with the fixed Huffman codes,
+// matches are found with hash chains over the 32K window
+pub(crate) fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    let mut bits = BitWriter::default();
+    bits.out.extend_from_slice(&[0x78, 0x01]);
+    bits.write(1, 1); // final block
+    bits.write(1, 2); // fixed codes
+
+    let hash = |pos: usize| {
+        let key = (data[pos] as u32) << 16 | (data[pos + 1] as u32) << 8 | data[pos + 2] as u32;
+        (key.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+    };
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; data.len()];
+    let insert = |pos: usize, head: &mut [usize], prev: &mut [usize]| {
+        if pos + MIN_MATCH <= data.len() {
+            let h = hash(pos);
+            prev[pos] = head[h];
+            head[h] = pos;
+        }
+    };
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let mut best = (0, 0);
+        if pos + MIN_MATCH <= data.len() {
+            let max_len = (data.len() - pos).min(MAX_MATCH);
+            let mut candidate = head[hash(pos)];
+            let mut chain = 0;
+            while candidate != usize::MAX && pos - candidate <= WINDOW_SIZE && chain < MAX_CHAIN {
+                let len = data[candidate..candidate + max_len]
+                    .iter()
+                    .zip(data[pos..pos + max_len].iter())
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if len > best.0 {
+                    best = (len, pos - candidate);
+                    if len == max_len {
+                        break;
+                    }
+                }
+                candidate = prev[candidate];
+                chain += 1;
+            }
+        }
+        if best.0 >= MIN_MATCH {
+            write_match(&mut bits, best.0, best.1);
+            for p in pos..pos + best.0 {
+                insert(p, &mut head, &mut prev);
+            }
+            pos += best.0;
+        } else {
+            write_literal(&mut bits, data[pos] as u16);
+            insert(pos, &mut head, &mut prev);
+            pos += 1;
+        }
+    }
+    write_literal(&mut bits, 256); // end of block
+    let mut out = bits.finish();
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u32,
+    count: u32
+}
+impl BitWriter {
+    // value bits go LSB first
+    fn write(&mut self, value: u32, bit_count: u32) {
+        self.buffer |= value << self.count;
+        self.count += bit_count;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes go MSB first
+    fn write_code(&mut self, code: u32, bit_count: u32) {
+        self.write(code.reverse_bits() >> (32 - bit_count), bit_count);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+fn write_literal(bits: &mut BitWriter, symbol: u16) {
+    let symbol = symbol as u32;
+    match symbol {
+        0..=143 => bits.write_code(0x30 + symbol, 8),
+        144..=255 => bits.write_code(0x190 + symbol - 144, 9),
+        256..=279 => bits.write_code(symbol - 256, 7),
+        _ => bits.write_code(0xC0 + symbol - 280, 8)
+    }
+}
+
+fn write_match(bits: &mut BitWriter, length: usize, distance: usize) {
+    let code = LENGTH_BASES.iter().rposition(|&base| base as usize <= length).unwrap();
+    write_literal(bits, 257 + code as u16);
+    bits.write((length - LENGTH_BASES[code] as usize) as u32, LENGTH_EXTRA_BITS[code] as u32);
+    let code = DISTANCE_BASES.iter().rposition(|&base| base as usize <= distance).unwrap();
+    bits.write_code(code as u32, 5);
+    bits.write((distance - DISTANCE_BASES[code] as usize) as u32, DISTANCE_EXTRA_BITS[code] as u32);
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+struct Crc32 {
+    table: [u32; 256],
+    value: u32
+}
+impl Crc32 {
+    fn new() -> Self {
+        let mut table = [0u32; 256];
+        for (n, entry) in table.iter_mut().enumerate() {
+            let mut c = n as u32;
+            for _ in 0..8 {
+                c = if c & 1 != 0 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+            }
+            *entry = c;
+        }
+        Self { table, value: 0xFFFF_FFFF }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.value = self.table[((self.value ^ byte as u32) & 0xFF) as usize] ^ (self.value >> 8);
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        self.value ^ 0xFFFF_FFFF
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::images::png::*;
+
+    struct BitReader<'a> {
+        data: &'a [u8],
+        position: usize
+    }
+    impl<'a> BitReader<'a> {
+        fn bit(&mut self) -> u32 {
+            let bit = (self.data[self.position / 8] >> (self.position % 8)) & 1;
+            self.position += 1;
+            bit as u32
+        }
+
+        fn bits(&mut self, count: u32) -> u32 {
+            (0..count).fold(0, |value, i| value | self.bit() << i)
+        }
+
+        fn code(&mut self, count: u32) -> u32 {
+            (0..count).fold(0, |value, _| value << 1 | self.bit())
+        }
+    }
+
+    fn fixed_literal(reader: &mut BitReader) -> u32 {
+        let code = reader.code(7);
+        if code <= 0x17 {
+            return code + 256;
+        }
+        let code = code << 1 | reader.bit();
+        match code {
+            0x30..=0xBF => code - 0x30,
+            0xC0..=0xC7 => code - 0xC0 + 280,
+            _ => (code << 1 | reader.bit()) - 0x190 + 144
+        }
+    }
+
+    // Inflates stored and fixed Huffman blocks, the only ones written here
+    pub(crate) fn zlib_decompress(data: &[u8]) -> Vec<u8> {
+        assert_eq!(((data[0] as u32) << 8 | data[1] as u32) % 31, 0);
+        let mut reader = BitReader { data: &data[2..], position: 0 };
+        let mut out: Vec<u8> = Vec::new();
+        loop {
+            let is_final = reader.bit() == 1;
+            match reader.bits(2) {
+                0 => {
+                    reader.position = reader.position.div_ceil(8) * 8;
+                    let len = reader.bits(16) as usize;
+                    assert_eq!(reader.bits(16) as usize, !len & 0xFFFF);
+                    let start = reader.position / 8;
+                    out.extend_from_slice(&reader.data[start..start + len]);
+                    reader.position += len * 8;
+                },
+                1 => loop {
+                    let symbol = fixed_literal(&mut reader);
+                    if symbol < 256 {
+                        out.push(symbol as u8);
+                        continue;
+                    }
+                    if symbol == 256 {
+                        break;
+                    }
+                    let code = (symbol - 257) as usize;
+                    let length = LENGTH_BASES[code] as usize + reader.bits(LENGTH_EXTRA_BITS[code] as u32) as usize;
+                    let code = reader.code(5) as usize;
+                    let distance = DISTANCE_BASES[code] as usize
+                        + reader.bits(DISTANCE_EXTRA_BITS[code] as u32) as usize;
+                    for _ in 0..length {
+                        out.push(out[out.len() - distance]);
+                    }
+                },
+                kind => panic!("unexpected block type {}", kind)
+            }
+            if is_final {
+                break;
+            }
+        }
+        let start = 2 + reader.position.div_ceil(8);
+        assert_eq!(&data[start..start + 4], &adler32(&out).to_be_bytes());
+        out
+    }
+
+    // Chunk kinds and data, with their CRCs checked
+    pub(crate) fn read_chunks(bytes: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(&bytes[..8], &PNG_SIGNATURE);
+        let mut chunks = Vec::new();
+        let mut offset = 8;
+        while offset < bytes.len() {
+            let len = u32::from_be_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]) as usize;
+            let kind = [bytes[offset + 4], bytes[offset + 5], bytes[offset + 6], bytes[offset + 7]];
+            let data = &bytes[offset + 8..offset + 8 + len];
+            let mut crc = Crc32::new();
+            crc.update(&kind);
+            crc.update(data);
+            assert_eq!(&bytes[offset + 8 + len..offset + 12 + len], &crc.finish().to_be_bytes());
+            chunks.push((kind, data.to_vec()));
+            offset += 12 + len;
+        }
+        chunks
+    }
+
+    // Removes the filter bytes written by add_filter_bytes
+    pub(crate) fn unfilter(data: &[u8], stride: usize) -> Vec<u8> {
+        data.chunks(stride + 1)
+            .flat_map(|row| {
+                assert_eq!(row[0], 0);
+                row[1..].to_vec()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_zlib_round_trip() {
+        let mut data: Vec<u8> = (0..70000u64).map(|i| (i * i / 1000) as u8).collect();
+        data.extend(std::iter::repeat(7).take(1000));
+        data.extend((0..300).map(|i| (i * 31 % 251) as u8));
+        let compressed = zlib_compress(&data);
+        assert!(zlib_compress(&[7; 1000]).len() < 50);
+        assert_eq!(zlib_decompress(&compressed), data);
+        assert!(zlib_decompress(&zlib_compress(&[])).is_empty());
+        assert_eq!(zlib_decompress(&zlib_compress(&[1, 2])), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_rgba_png_round_trip() {
+        let mut image = RgbaImage::new(5, 3);
+        for (i, pixel) in image.pixels.iter_mut().enumerate() {
+            *pixel = (i as u32 * 0x01_10_20_30) | if i % 2 == 0 { 0xFF_00_00_00 } else { 0 };
+        }
+        let mut bytes = Vec::new();
+        write_rgba_png(&mut bytes, &image).unwrap();
+        let chunks = read_chunks(&bytes);
+        let kinds: Vec<&[u8; 4]> = chunks.iter().map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, vec![b"IHDR", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, ihdr(5, 3, PngColorType::Rgba));
+        let pixels = unfilter(&zlib_decompress(&chunks[1].1), 5 * 4);
+        assert_eq!(pixels, rgba_bytes(&image));
+    }
+}
@@ -0,0 +1,405 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use crate::images::rgba_image::{RgbaImage, split_argb, join_argb};
+use crate::images::sprite::BmpSprite;
+use crate::images::ingame_sprite::encode_dot256_frame;
+
+const ALPHA_THRESHOLD: u8 = 0x80;
+const ORDERED_DITHER_SPREAD: f32 = 32.0;
+const BAYER_8X8: [[u8; 8]; 8] = [
+    [ 0, 32,  8, 40,  2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44,  4, 36, 14, 46,  6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [ 3, 35, 11, 43,  1, 33,  9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47,  7, 39, 13, 45,  5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21]
+];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Dithering {
+    None,
+    FloydSteinberg,
+    Ordered
+}
+
+// Palette entries which must never be picked for true colour pixels nor overwritten
+// by a generated palette (team colour ramps, the transparent colour and so on)
+#[derive(Clone, Debug, Default)]
+pub struct PaletteLayout {
+    pub transparent_index: Option<u8>,
+    pub reserved_ranges: Vec<Range<usize>>
+}
+impl PaletteLayout {
+    pub fn is_reserved(&self, index: usize) -> bool {
+        self.transparent_index == Some(index as u8)
+            || self.reserved_ranges.iter().any(|range| range.contains(&index))
+    }
+
+    pub fn free_indexes(&self) -> Vec<usize> {
+        (0..256).filter(|&idx| !self.is_reserved(idx)).collect()
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Oklab {
+    l: f32,
+    a: f32,
+    b: f32
+}
+impl Oklab {
+    fn from_rgb(r: f32, g: f32, b: f32) -> Self {
+        fn linear(c: f32) -> f32 {
+            let c = c / 255.0;
+            if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+        }
+        let (r, g, b) = (linear(r), linear(g), linear(b));
+        let l = (0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+        let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+        let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+        Self {
+            l: 0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+            a: 1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+            b: 0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s
+        }
+    }
+
+    fn distance_squared(&self, other: &Oklab) -> f32 {
+        let dl = self.l - other.l;
+        let da = self.a - other.a;
+        let db = self.b - other.b;
+        dl * dl + da * da + db * db
+    }
+}
+
+pub struct PaletteMapper {
+    candidates: Vec<(u8, Oklab)>,
+    cache: HashMap<u32, u8>
+}
+impl PaletteMapper {
+    // None when the layout leaves no palette entry to pick from
+    pub fn new(palette: &[u32], layout: &PaletteLayout) -> Option<Self> {
+        let candidates: Vec<(u8, Oklab)> = palette
+            .iter()
+            .take(256)
+            .enumerate()
+            .filter(|(idx, _)| !layout.is_reserved(*idx))
+            .map(|(idx, &color)| {
+                let (_, r, g, b) = split_argb(color);
+                (idx as u8, Oklab::from_rgb(r as f32, g as f32, b as f32))
+            })
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        Some(Self {
+            candidates,
+            cache: HashMap::new()
+        })
+    }
+
+    // Alpha is ignored here, transparency is a matter of the palette layout
+    pub fn nearest(&mut self, color: u32) -> u8 {
+        let key = color & 0x00_FF_FF_FF;
+        if let Some(&idx) = self.cache.get(&key) {
+            return idx;
+        }
+        let (_, r, g, b) = split_argb(key);
+        let target = Oklab::from_rgb(r as f32, g as f32, b as f32);
+        let mut best = (0u8, f32::MAX);
+        for (idx, lab) in self.candidates.iter() {
+            let distance = target.distance_squared(lab);
+            if distance < best.1 {
+                best = (*idx, distance);
+            }
+        }
+        self.cache.insert(key, best.0);
+        best.0
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct QuantizedImage {
+    pub width: usize,
+    pub height: usize,
+    pub palette: [u32; 256],
+    pub palette_indexes: Vec<u8>,
+    pub transparent_index: Option<u8>
+}
+impl QuantizedImage {
+    pub fn mask(&self) -> Vec<bool> {
+        match self.transparent_index {
+            None => vec![true; self.palette_indexes.len()],
+            Some(transparent) => self.palette_indexes.iter().map(|&idx| idx != transparent).collect()
+        }
+    }
+
+    // Packed frame ready to be pushed into a .256 ImageData
+    pub fn to_dot256_frame(&self) -> Vec<u8> {
+        encode_dot256_frame(self.width, self.height, &self.palette_indexes, &self.mask())
+    }
+}
+impl From<QuantizedImage> for BmpSprite {
+    fn from(image: QuantizedImage) -> Self {
+        BmpSprite::Paletted {
+            width: image.width,
+            height: image.height,
+            palette: image.palette,
+            palette_indexes: image.palette_indexes
+        }
+    }
+}
+
+// Pixels below the alpha threshold become transparent_index; without one the layout has no
+// transparent colour, so they are quantized from whatever RGB they carry like opaque ones.
+// None when every palette entry is reserved
+pub fn quantize_to_palette(
+    image: &RgbaImage,
+    palette: &[u32],
+    layout: &PaletteLayout,
+    dithering: Dithering
+) -> Option<QuantizedImage> {
+    let mut full_palette = [0u32; 256];
+    for (dst, src) in full_palette.iter_mut().zip(palette.iter()) {
+        *dst = *src;
+    }
+    let mut mapper = PaletteMapper::new(&full_palette, layout)?;
+    let (width, height) = (image.width, image.height);
+    let mut palette_indexes = vec![0u8; width * height];
+
+    // Floyd-Steinberg error rows: current and next one, with a guard pixel on each side
+    let mut errors = [vec![[0f32; 3]; width + 2], vec![[0f32; 3]; width + 2]];
+    for y in 0..height {
+        for x in 0..width {
+            let (a, r, g, b) = split_argb(image.get_pixel(x, y));
+            if a < ALPHA_THRESHOLD {
+                if let Some(transparent) = layout.transparent_index {
+                    palette_indexes[y * width + x] = transparent;
+                    continue;
+                }
+            }
+            let mut desired = [r as f32, g as f32, b as f32];
+            match dithering {
+                Dithering::None => {},
+                Dithering::FloydSteinberg => {
+                    for (c, err) in desired.iter_mut().zip(errors[0][x + 1].iter()) {
+                        *c += err;
+                    }
+                },
+                Dithering::Ordered => {
+                    let threshold = (BAYER_8X8[y % 8][x % 8] as f32 + 0.5) / 64.0 - 0.5;
+                    for c in desired.iter_mut() {
+                        *c += threshold * ORDERED_DITHER_SPREAD;
+                    }
+                }
+            }
+            let clamped: Vec<u8> = desired.iter().map(|c| c.round().clamp(0.0, 255.0) as u8).collect();
+            let idx = mapper.nearest(join_argb(0xFF, clamped[0], clamped[1], clamped[2]));
+            palette_indexes[y * width + x] = idx;
+
+            if dithering == Dithering::FloydSteinberg {
+                let (_, pr, pg, pb) = split_argb(full_palette[idx as usize]);
+                let chosen = [pr as f32, pg as f32, pb as f32];
+                for ch in 0..3 {
+                    let err = desired[ch].clamp(0.0, 255.0) - chosen[ch];
+                    errors[0][x + 2][ch] += err * 7.0 / 16.0;
+                    errors[1][x][ch] += err * 3.0 / 16.0;
+                    errors[1][x + 1][ch] += err * 5.0 / 16.0;
+                    errors[1][x + 2][ch] += err / 16.0;
+                }
+            }
+        }
+        errors.swap(0, 1);
+        for err in errors[1].iter_mut() {
+            *err = [0.0; 3];
+        }
+    }
+
+    Some(QuantizedImage {
+        width,
+        height,
+        palette: full_palette,
+        palette_indexes,
+        transparent_index: layout.transparent_index
+    })
+}
+
+// Builds a palette keeping the reserved entries of base_palette intact and filling
+// all the other slots with a median cut of opaque colours found in the images
+pub fn generate_palette(images: &[&RgbaImage], base_palette: &[u32], layout: &PaletteLayout) -> [u32; 256] {
+    let mut palette = [0xFF_00_00_00u32; 256];
+    for (dst, src) in palette.iter_mut().zip(base_palette.iter()) {
+        *dst = *src;
+    }
+    let mut histogram: HashMap<u32, u32> = HashMap::new();
+    for image in images {
+        for &pixel in image.pixels.iter() {
+            if (pixel >> 24) as u8 >= ALPHA_THRESHOLD {
+                *histogram.entry(pixel & 0x00_FF_FF_FF).or_insert(0) += 1;
+            }
+        }
+    }
+    let free_indexes = layout.free_indexes();
+    let colors = median_cut(histogram.into_iter().collect(), free_indexes.len());
+    for (idx, color) in free_indexes.into_iter().zip(colors) {
+        palette[idx] = 0xFF_00_00_00 | color;
+    }
+    palette
+}
+
+pub fn quantize_with_generated_palette(
+    image: &RgbaImage,
+    base_palette: &[u32],
+    layout: &PaletteLayout,
+    dithering: Dithering
+) -> Option<QuantizedImage> {
+    let palette = generate_palette(&[image], base_palette, layout);
+    quantize_to_palette(image, &palette, layout, dithering)
+}
+
+fn channel(color: u32, ch: usize) -> u8 {
+    (color >> (16 - 8 * ch)) as u8
+}
+
+fn median_cut(histogram: Vec<(u32, u32)>, target: usize) -> Vec<u32> {
+    if histogram.is_empty() || target == 0 {
+        return Vec::new();
+    }
+    let mut boxes = vec![histogram];
+    while boxes.len() < target {
+        // pick the box with the widest channel range which still can be split
+        let mut widest = None;
+        for (box_id, colors) in boxes.iter().enumerate() {
+            if colors.len() < 2 {
+                continue;
+            }
+            for ch in 0..3 {
+                let min = colors.iter().map(|(c, _)| channel(*c, ch)).min().unwrap();
+                let max = colors.iter().map(|(c, _)| channel(*c, ch)).max().unwrap();
+                let range = max - min;
+                match widest {
+                    Some((_, _, best_range)) if best_range >= range => {},
+                    _ => widest = Some((box_id, ch, range))
+                }
+            }
+        }
+        let (box_id, ch, _) = match widest {
+            None => break,
+            Some(found) => found
+        };
+        let mut colors = boxes.swap_remove(box_id);
+        colors.sort_by_key(|(c, _)| channel(*c, ch));
+        let total: u64 = colors.iter().map(|(_, n)| *n as u64).sum();
+        let mut accumulated = 0u64;
+        let mut split_at = 1;
+        for (i, (_, n)) in colors.iter().enumerate() {
+            accumulated += *n as u64;
+            if accumulated * 2 >= total {
+                split_at = (i + 1).max(1).min(colors.len() - 1);
+                break;
+            }
+        }
+        let upper = colors.split_off(split_at);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+    boxes
+        .iter()
+        .map(|colors| {
+            let total: u64 = colors.iter().map(|(_, n)| *n as u64).sum();
+            let mut sums = [0u64; 3];
+            for (c, n) in colors.iter() {
+                for (ch, sum) in sums.iter_mut().enumerate() {
+                    *sum += channel(*c, ch) as u64 * *n as u64;
+                }
+            }
+            let avg: Vec<u8> = sums.iter().map(|s| ((s + total / 2) / total) as u8).collect();
+            join_argb(0, avg[0], avg[1], avg[2])
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::images::quantization::*;
+
+    #[test]
+    fn test_fully_reserved_layout() {
+        let layout = PaletteLayout { transparent_index: None, reserved_ranges: vec![0..128, 128..256] };
+        assert!(PaletteMapper::new(&[0xFF_00_00_00; 256], &layout).is_none());
+        let image = RgbaImage { width: 1, height: 1, pixels: vec![0xFF_FF_FF_FF] };
+        assert!(quantize_to_palette(&image, &[0xFF_00_00_00; 256], &layout, Dithering::None).is_none());
+    }
+
+    const BLACK: u32 = 0xFF_00_00_00;
+    const WHITE: u32 = 0xFF_FF_FF_FF;
+
+    #[test]
+    fn test_nearest_is_perceptual() {
+        let layout = PaletteLayout { transparent_index: Some(0), reserved_ranges: Vec::new() };
+        let mut mapper = PaletteMapper::new(&[WHITE, BLACK, WHITE], &layout).unwrap();
+        // closer to black in RGB, yet already lighter than the perceptual midpoint
+        assert_eq!(mapper.nearest(0xFF_65_65_65), 2);
+        assert_eq!(mapper.nearest(0xFF_50_50_50), 1);
+        assert_eq!(mapper.nearest(0x00_FF_FF_FF), 2);
+    }
+
+    #[test]
+    fn test_generated_palette_size() {
+        let colors = [0xFF_FF_00_00, 0xFF_00_FF_00, 0xFF_00_00_FF, 0xFF_FF_FF_00, 0xFF_00_FF_FF, 0x00_12_34_56];
+        let image = RgbaImage { width: 3, height: 2, pixels: colors.to_vec() };
+        let mut base_palette = [0x12_34_56u32; 256];
+        base_palette[255] = WHITE;
+        let layout = PaletteLayout { transparent_index: Some(255), reserved_ranges: vec![0..128, 128..250] };
+
+        // the transparent pixel is left out, five colours fit the five free entries exactly
+        let palette = generate_palette(&[&image], &base_palette, &layout);
+        let mut generated = palette[250..255].to_vec();
+        generated.sort_unstable();
+        let mut expected = colors[..5].to_vec();
+        expected.sort_unstable();
+        assert_eq!(generated, expected);
+        assert!(palette[..250].iter().all(|&color| color == 0x12_34_56));
+        assert_eq!(palette[255], WHITE);
+
+        let layout = PaletteLayout { transparent_index: Some(255), reserved_ranges: vec![0..128, 128..252] };
+        let palette = generate_palette(&[&image], &base_palette, &layout);
+        assert!(palette[252..255].iter().all(|&color| color >> 24 == 0xFF));
+        assert!(palette[252] != palette[253] && palette[253] != palette[254] && palette[252] != palette[254]);
+        // there are never more boxes than colours
+        assert_eq!(median_cut(vec![(0xFF_00_00, 4), (0x00_FF_00, 1)], 8).len(), 2);
+    }
+
+    #[test]
+    fn test_dithering_a_gradient() {
+        let (width, height) = (64, 16);
+        let pixels = (0..width * height).map(|idx| {
+            let value = ((idx % width) * 255 / (width - 1)) as u8;
+            join_argb(0xFF, value, value, value)
+        });
+        let image = RgbaImage { width, height, pixels: pixels.collect() };
+        let layout = PaletteLayout::default();
+        let white_share = |dithering| {
+            let quantized = quantize_to_palette(&image, &[BLACK, WHITE], &layout, dithering).unwrap();
+            let white = quantized.palette_indexes.iter().filter(|&&idx| idx == 1).count();
+            (quantized.palette_indexes, white as f32 / (width * height) as f32)
+        };
+
+        // without dithering every column is flat and the split falls at the perceptual midpoint
+        let (plain, plain_share) = white_share(Dithering::None);
+        assert!((0..height).all(|y| plain[y * width..(y + 1) * width] == plain[..width]));
+        assert!(plain_share > 0.55);
+
+        // error diffusion keeps the average brightness
+        let (_, diffused_share) = white_share(Dithering::FloydSteinberg);
+        assert!((diffused_share - 0.5).abs() < 0.02, "{}", diffused_share);
+
+        // the Bayer pattern repeats every 8 rows and only mixes colours around the threshold
+        let (ordered, ordered_share) = white_share(Dithering::Ordered);
+        assert!(ordered[..8 * width] == ordered[8 * width..]);
+        assert!(ordered_share < plain_share + 0.05 && ordered_share > plain_share - 0.05);
+        let mixed_columns = (0..width).filter(|&x| (0..height).any(|y| ordered[y * width + x] != ordered[x])).count();
+        assert!(mixed_columns > 0 && mixed_columns < width / 4, "{}", mixed_columns);
+    }
+}
@@ -0,0 +1,62 @@
+use crate::images::sprite::BmpSprite;
+
+// Pixels are stored in the same 0xAARRGGBB layout as palettes and BmpSprite::TrueColor
+#[derive(Clone, Debug, PartialEq)]
+pub struct RgbaImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u32>
+}
+impl RgbaImage {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0u32; width * height]
+        }
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> u32 {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: u32) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    pub fn from_paletted(width: usize, height: usize, palette: &[u32], palette_indexes: &[u8]) -> Self {
+        let pixels = palette_indexes
+            .iter()
+            .take(width * height)
+            .map(|&idx| palette[idx as usize])
+            .collect();
+        Self { width, height, pixels }
+    }
+
+    pub fn from_bmp_sprite(sprite: &BmpSprite) -> Option<Self> {
+        match sprite {
+            BmpSprite::Paletted { width, height, palette, palette_indexes } => {
+                Some(Self::from_paletted(*width, *height, palette, palette_indexes))
+            },
+            BmpSprite::TrueColor { width, height, colors } => Some(Self {
+                width: *width,
+                height: *height,
+                pixels: colors[..width * height].to_vec()
+            }),
+            BmpSprite::NotSupported => None
+        }
+    }
+}
+
+pub(crate) fn split_argb(color: u32) -> (u8, u8, u8, u8) {
+    (
+        (color >> 24) as u8,
+        (color >> 16) as u8,
+        (color >> 8) as u8,
+        color as u8
+    )
+}
+
+pub(crate) fn join_argb(a: u8, r: u8, g: u8, b: u8) -> u32 {
+    (a as u32) << 24 | (r as u32) << 16 | (g as u32) << 8 | b as u32
+}
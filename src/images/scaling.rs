@@ -0,0 +1,408 @@
+use crate::images::ingame_sprite::DecodedFrame;
+use crate::images::rgba_image::{RgbaImage, split_argb, join_argb};
+use crate::images::sprite::BmpSprite;
+use crate::multimedia::SmackerDecodeContext;
+
+// Colours closer than that (in YUV units) are treated as the same one by hq2x
+const HQ_SIMILARITY_THRESHOLD: f32 = 48.0;
+const TRANSPARENCY_DISTANCE: f32 = 1000.0;
+const WEIGHT_TOTAL: u32 = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ScaleFilter {
+    Scale2x,
+    Scale3x,
+    Scale4x,
+    Hq2x,
+    Xbr2x
+}
+impl ScaleFilter {
+    pub fn factor(self) -> usize {
+        match self {
+            ScaleFilter::Scale3x => 3,
+            ScaleFilter::Scale4x => 4,
+            _ => 2
+        }
+    }
+
+    // Scale*x only ever copy source pixels, the others blend neighbours
+    pub fn is_exact(self) -> bool {
+        match self {
+            ScaleFilter::Scale2x | ScaleFilter::Scale3x | ScaleFilter::Scale4x => true,
+            ScaleFilter::Hq2x | ScaleFilter::Xbr2x => false
+        }
+    }
+}
+
+pub fn scale_rgba(image: &RgbaImage, filter: ScaleFilter) -> RgbaImage {
+    let (width, height) = (image.width, image.height);
+    let factor = filter.factor();
+    let pixels = if filter.is_exact() {
+        scale_exact(&image.pixels, width, height, filter)
+    } else {
+        let yuv: Vec<_> = image.pixels.iter().map(|&c| Yuva::from_argb(c)).collect();
+        let samples = blend_samples(width, height, filter, |a, b| yuv[a].distance(&yuv[b]));
+        samples.iter().map(|s| mix_argb(&image.pixels, s)).collect()
+    };
+    RgbaImage {
+        width: width * factor,
+        height: height * factor,
+        pixels
+    }
+}
+
+// Stays in palette index mode: blending filters pick the dominant source pixel
+// instead of mixing colours, so team colour ranges never get smeared
+pub fn scale_indexed(
+    palette_indexes: &[u16],
+    mask: &[bool],
+    width: usize,
+    height: usize,
+    palette: &[u32],
+    filter: ScaleFilter
+) -> (Vec<u16>, Vec<bool>) {
+    let keys: Vec<Option<u16>> = palette_indexes
+        .iter()
+        .zip(mask.iter())
+        .map(|(&idx, &opaque)| if opaque { Some(idx) } else { None })
+        .collect();
+    let scaled = if filter.is_exact() {
+        scale_exact(&keys, width, height, filter)
+    } else {
+        let yuv: Vec<Option<Yuva>> = keys
+            .iter()
+            .map(|key| key.map(|idx| Yuva::from_argb(palette[(idx & 0xFF) as usize] | 0xFF_00_00_00)))
+            .collect();
+        let samples = blend_samples(width, height, filter, |a, b| match (&yuv[a], &yuv[b]) {
+            (Some(x), Some(y)) => x.distance(y),
+            (None, None) => 0.0,
+            _ => TRANSPARENCY_DISTANCE
+        });
+        samples.iter().map(|s| keys[dominant(s)]).collect()
+    };
+    let values = scaled.iter().map(|key| key.unwrap_or(0)).collect();
+    let mask = scaled.iter().map(|key| key.is_some()).collect();
+    (values, mask)
+}
+
+pub fn scale_decoded_frame(frame: &DecodedFrame, palette: &[u32], filter: ScaleFilter) -> DecodedFrame {
+    let (values, mask) = scale_indexed(&frame.values, &frame.mask, frame.width, frame.height, palette, filter);
+    DecodedFrame {
+        image_type: frame.image_type,
+        width: frame.width * filter.factor(),
+        height: frame.height * filter.factor(),
+        values,
+        mask
+    }
+}
+
+pub fn scale_bmp_sprite(sprite: &BmpSprite, filter: ScaleFilter) -> BmpSprite {
+    let factor = filter.factor();
+    match sprite {
+        BmpSprite::Paletted { width, height, palette, palette_indexes } => {
+            let indexes: Vec<u16> = palette_indexes[..width * height].iter().map(|&idx| idx as u16).collect();
+            let (values, _) = scale_indexed(&indexes, &vec![true; indexes.len()], *width, *height, palette, filter);
+            BmpSprite::Paletted {
+                width: width * factor,
+                height: height * factor,
+                palette: *palette,
+                palette_indexes: values.iter().map(|&v| v as u8).collect()
+            }
+        },
+        BmpSprite::TrueColor { width, height, colors } => {
+            let image = RgbaImage { width: *width, height: *height, pixels: colors[..width * height].to_vec() };
+            let scaled = scale_rgba(&image, filter);
+            BmpSprite::TrueColor {
+                width: scaled.width,
+                height: scaled.height,
+                colors: scaled.pixels
+            }
+        },
+        BmpSprite::NotSupported => BmpSprite::NotSupported
+    }
+}
+
+// Palette indexes of the currently unpacked Smacker frame
+pub fn scale_smacker_frame(context: &SmackerDecodeContext, width: usize, height: usize, filter: ScaleFilter) -> Vec<u8> {
+    let palette: Vec<u32> = context.palette
+        .iter()
+        .map(|&(r, g, b)| join_argb(0xFF, r, g, b))
+        .collect();
+    let indexes: Vec<u16> = context.image[..width * height].iter().map(|&idx| idx as u16).collect();
+    let (values, _) = scale_indexed(&indexes, &vec![true; indexes.len()], width, height, &palette, filter);
+    values.iter().map(|&v| v as u8).collect()
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Yuva {
+    y: f32,
+    u: f32,
+    v: f32,
+    a: f32
+}
+impl Yuva {
+    fn from_argb(color: u32) -> Self {
+        let (a, r, g, b) = split_argb(color);
+        let (r, g, b) = (r as f32, g as f32, b as f32);
+        Self {
+            y: 0.299 * r + 0.587 * g + 0.114 * b,
+            u: -0.169 * r - 0.331 * g + 0.5 * b,
+            v: 0.5 * r - 0.419 * g - 0.081 * b,
+            a: a as f32
+        }
+    }
+
+    fn distance(&self, other: &Yuva) -> f32 {
+        if (self.a < 128.0) != (other.a < 128.0) {
+            return TRANSPARENCY_DISTANCE;
+        }
+        (self.y - other.y).abs() + (self.u - other.u).abs() + (self.v - other.v).abs()
+    }
+}
+
+fn clamped(x: isize, y: isize, width: usize, height: usize) -> usize {
+    let x = x.clamp(0, width as isize - 1) as usize;
+    let y = y.clamp(0, height as isize - 1) as usize;
+    y * width + x
+}
+
+fn scale_exact<T: Copy + PartialEq>(pixels: &[T], width: usize, height: usize, filter: ScaleFilter) -> Vec<T> {
+    match filter {
+        ScaleFilter::Scale3x => scale3x(pixels, width, height),
+        ScaleFilter::Scale4x => {
+            let doubled = scale2x(pixels, width, height);
+            scale2x(&doubled, width * 2, height * 2)
+        },
+        _ => scale2x(pixels, width, height)
+    }
+}
+
+fn scale2x<T: Copy + PartialEq>(pixels: &[T], width: usize, height: usize) -> Vec<T> {
+    let mut out = match pixels.first() {
+        None => return Vec::new(),
+        Some(&first) => vec![first; pixels.len() * 4]
+    };
+    let out_width = width * 2;
+    for y in 0..height {
+        for x in 0..width {
+            let (xi, yi) = (x as isize, y as isize);
+            let p = pixels[y * width + x];
+            let a = pixels[clamped(xi, yi - 1, width, height)];
+            let b = pixels[clamped(xi + 1, yi, width, height)];
+            let c = pixels[clamped(xi - 1, yi, width, height)];
+            let d = pixels[clamped(xi, yi + 1, width, height)];
+            let (mut e0, mut e1, mut e2, mut e3) = (p, p, p, p);
+            if c == a && c != d && a != b { e0 = a; }
+            if a == b && a != c && b != d { e1 = b; }
+            if d == c && d != b && c != a { e2 = c; }
+            if b == d && b != a && d != c { e3 = d; }
+            let o = y * 2 * out_width + x * 2;
+            out[o] = e0;
+            out[o + 1] = e1;
+            out[o + out_width] = e2;
+            out[o + out_width + 1] = e3;
+        }
+    }
+    out
+}
+
+fn scale3x<T: Copy + PartialEq>(pixels: &[T], width: usize, height: usize) -> Vec<T> {
+    let mut out = match pixels.first() {
+        None => return Vec::new(),
+        Some(&first) => vec![first; pixels.len() * 9]
+    };
+    let out_width = width * 3;
+    for y in 0..height {
+        for x in 0..width {
+            let (xi, yi) = (x as isize, y as isize);
+            let at = |dx: isize, dy: isize| pixels[clamped(xi + dx, yi + dy, width, height)];
+            let (a, b, c) = (at(-1, -1), at(0, -1), at(1, -1));
+            let (d, e, f) = (at(-1, 0), at(0, 0), at(1, 0));
+            let (g, h, i) = (at(-1, 1), at(0, 1), at(1, 1));
+            let mut block = [e; 9];
+            if b != h && d != f {
+                block[0] = if d == b { d } else { e };
+                block[1] = if (d == b && e != c) || (b == f && e != a) { b } else { e };
+                block[2] = if b == f { f } else { e };
+                block[3] = if (d == b && e != g) || (d == h && e != a) { d } else { e };
+                block[5] = if (b == f && e != i) || (h == f && e != c) { f } else { e };
+                block[6] = if d == h { d } else { e };
+                block[7] = if (d == h && e != i) || (h == f && e != g) { h } else { e };
+                block[8] = if h == f { f } else { e };
+            }
+            for (k, value) in block.iter().enumerate() {
+                out[(y * 3 + k / 3) * out_width + x * 3 + k % 3] = *value;
+            }
+        }
+    }
+    out
+}
+
+// Every output pixel is a weighted mix of up to three source pixels, weights summing to WEIGHT_TOTAL
+type Samples = [(usize, u32); 3];
+
+fn blend_samples<F: Fn(usize, usize) -> f32>(
+    width: usize,
+    height: usize,
+    filter: ScaleFilter,
+    distance: F
+) -> Vec<Samples> {
+    let out_width = width * 2;
+    let mut out = vec![[(0usize, WEIGHT_TOTAL), (0, 0), (0, 0)]; width * height * 4];
+    for y in 0..height {
+        for x in 0..width {
+            let (xi, yi) = (x as isize, y as isize);
+            let e = y * width + x;
+            // quadrants: top-left, top-right, bottom-left, bottom-right
+            for (q, &(sx, sy)) in [(-1isize, -1isize), (1, -1), (-1, 1), (1, 1)].iter().enumerate() {
+                let at = |dx: isize, dy: isize| clamped(xi + dx * sx, yi + dy * sy, width, height);
+                let samples = match filter {
+                    ScaleFilter::Hq2x => hq_corner(e, at(1, 0), at(0, 1), at(1, 1), &distance),
+                    _ => xbr_corner(&at, &distance)
+                };
+                let o = (y * 2 + q / 2) * out_width + x * 2 + q % 2;
+                out[o] = samples;
+            }
+        }
+    }
+    out
+}
+
+fn hq_corner<F: Fn(usize, usize) -> f32>(e: usize, side: usize, below: usize, diagonal: usize, distance: &F) -> Samples {
+    let similar = |a: usize, b: usize| distance(a, b) < HQ_SIMILARITY_THRESHOLD;
+    if similar(side, below) && !similar(e, side) && !similar(e, diagonal) {
+        [(e, 4), (side, 2), (below, 2)]
+    } else if similar(side, below) && !similar(e, side) {
+        [(e, 6), (side, 1), (below, 1)]
+    } else if !similar(e, diagonal) && similar(e, side) && similar(e, below) {
+        [(e, 7), (diagonal, 1), (e, 0)]
+    } else {
+        [(e, WEIGHT_TOTAL), (e, 0), (e, 0)]
+    }
+}
+
+// 2xBR level 1 rule for one corner; the at closure is already rotated so that
+// (1, 0) is the side neighbour and (0, 1) the one below
+fn xbr_corner<A: Fn(isize, isize) -> usize, F: Fn(usize, usize) -> f32>(at: &A, distance: &F) -> Samples {
+    let e = at(0, 0);
+    let (f, h, i) = (at(1, 0), at(0, 1), at(1, 1));
+    let (c, g) = (at(1, -1), at(-1, 1));
+    let (d, b) = (at(-1, 0), at(0, -1));
+    let (f4, h5) = (at(2, 0), at(0, 2));
+    let (i4, i5) = (at(2, 1), at(1, 2));
+    let weight_e_i = distance(e, c) + distance(e, g) + distance(i, f4) + distance(i, h5) + 4.0 * distance(h, f);
+    let weight_h_f = distance(h, d) + distance(h, i5) + distance(f, i4) + distance(f, b) + 4.0 * distance(e, i);
+    if weight_e_i < weight_h_f && distance(e, f) > 0.0 && distance(e, h) > 0.0 {
+        let new = if distance(e, f) <= distance(e, h) { f } else { h };
+        [(e, 4), (new, 4), (e, 0)]
+    } else {
+        [(e, WEIGHT_TOTAL), (e, 0), (e, 0)]
+    }
+}
+
+fn dominant(samples: &Samples) -> usize {
+    let mut best = samples[0];
+    for &sample in samples.iter().skip(1) {
+        if sample.1 > best.1 {
+            best = sample;
+        }
+    }
+    best.0
+}
+
+fn mix_argb(pixels: &[u32], samples: &Samples) -> u32 {
+    let (mut a, mut r, mut g, mut b) = (0u32, 0u32, 0u32, 0u32);
+    for &(idx, weight) in samples.iter() {
+        let (pa, pr, pg, pb) = split_argb(pixels[idx]);
+        let (pa, pr, pg, pb) = (pa as u32, pr as u32, pg as u32, pb as u32);
+        a += pa * weight;
+        r += pr * pa * weight;
+        g += pg * pa * weight;
+        b += pb * pa * weight;
+    }
+    if a == 0 {
+        return 0;
+    }
+    join_argb(
+        (a / WEIGHT_TOTAL) as u8,
+        (r / a) as u8,
+        (g / a) as u8,
+        (b / a) as u8
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::images::scaling::*;
+
+    // Straight from the AdvMAME description, B, D, F and H being the pixels above, left, right and below E
+    fn reference(pixels: &[u8], width: usize, height: usize, factor: usize) -> Vec<u8> {
+        let mut out = vec![0; pixels.len() * factor * factor];
+        for y in 0..height {
+            for x in 0..width {
+                let at = |dx: isize, dy: isize| pixels[clamped(x as isize + dx, y as isize + dy, width, height)];
+                let (a, b, c) = (at(-1, -1), at(0, -1), at(1, -1));
+                let (d, e, f) = (at(-1, 0), at(0, 0), at(1, 0));
+                let (g, h, i) = (at(-1, 1), at(0, 1), at(1, 1));
+                let block = if factor == 2 {
+                    vec![
+                        if d == b && b != f && d != h { d } else { e },
+                        if b == f && b != d && f != h { f } else { e },
+                        if d == h && d != b && h != f { d } else { e },
+                        if h == f && d != h && b != f { f } else { e }
+                    ]
+                } else {
+                    vec![
+                        if d == b && b != f && d != h { d } else { e },
+                        if (d == b && b != f && d != h && e != c) || (b == f && b != d && f != h && e != a) { b } else { e },
+                        if b == f && b != d && f != h { f } else { e },
+                        if (d == b && b != f && d != h && e != g) || (d == h && d != b && h != f && e != a) { d } else { e },
+                        e,
+                        if (b == f && b != d && f != h && e != i) || (h == f && d != h && b != f && e != c) { f } else { e },
+                        if d == h && d != b && h != f { d } else { e },
+                        if (d == h && d != b && h != f && e != i) || (h == f && d != h && b != f && e != g) { h } else { e },
+                        if h == f && d != h && b != f { f } else { e }
+                    ]
+                };
+                for (k, &value) in block.iter().enumerate() {
+                    out[(y * factor + k / factor) * width * factor + x * factor + k % factor] = value;
+                }
+            }
+        }
+        out
+    }
+
+    // The corners keep their own colour since the border is extended past them
+    #[test]
+    fn test_scale2x_diagonal() {
+        let diagonal = [
+            1, 0, 0,
+            0, 1, 0,
+            0, 0, 1
+        ];
+        assert_eq!(scale_exact(&diagonal, 3, 3, ScaleFilter::Scale2x), vec![
+            1, 1, 0, 0, 0, 0,
+            1, 0, 1, 0, 0, 0,
+            0, 1, 1, 1, 0, 0,
+            0, 0, 1, 1, 1, 0,
+            0, 0, 0, 1, 0, 1,
+            0, 0, 0, 0, 1, 1
+        ]);
+    }
+
+    #[test]
+    fn test_scale_exact_matches_reference() {
+        let (width, height) = (7, 5);
+        let mut seed = 12345u32;
+        for colors in [2, 3] {
+            let pixels: Vec<u8> = (0..width * height)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                    ((seed >> 16) % colors) as u8
+                })
+                .collect();
+            assert_eq!(scale_exact(&pixels, width, height, ScaleFilter::Scale2x), reference(&pixels, width, height, 2));
+            assert_eq!(scale_exact(&pixels, width, height, ScaleFilter::Scale3x), reference(&pixels, width, height, 3));
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use std::io::{Read, Seek, Result};
 use crate::images::bmp::RawBmp;
 
+#[allow(clippy::large_enum_variant)]
 pub enum BmpSprite {
     Paletted{
         width: usize,
@@ -23,7 +24,7 @@ impl BmpSprite {
             Some(bmp) => {
                 let upside_down = bmp.header.height > 0;
                 let width = bmp.header.width as usize;
-                let height = bmp.header.height.abs() as usize;
+                let height = bmp.header.height.unsigned_abs() as usize;
                 match bmp.header.bi_bit_count {
                     8 => {
                         let mut palette_indexes = vec![0u8; bmp.raw_data.len()];
@@ -46,8 +47,8 @@ impl BmpSprite {
                         let mut palette = bmp.palette.unwrap();
                         for entry in palette.iter_mut() {
                             let mut clr = *entry;
-                            let b = clr & 0xFF; clr = clr / 0x100;
-                            let g = clr & 0xFF; clr = clr / 0x100;
+                            let b = clr & 0xFF; clr /= 0x100;
+                            let g = clr & 0xFF; clr /= 0x100;
                             let r = clr & 0xFF;
 
                             let b = if b <= 127 {
@@ -68,7 +69,7 @@ impl BmpSprite {
                             }  else {
                                 255
                             };
-                            *entry = 0xFF000000 | r * 0x10000 | g * 0x100 + b;
+                            *entry = 0xFF000000 | (r * 0x10000) | (g * 0x100 + b);
                         }
                         Ok(
                             Self::Paletted {
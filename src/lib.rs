@@ -7,6 +7,7 @@ pub mod images;
 pub mod shared_types;
 pub mod multimedia;
 pub mod alm;
+pub mod game_database;
 
 mod stream_utils;
 
@@ -45,7 +45,7 @@ impl<'a, TStream : Read> BitReader<'a, TStream> {
             if self.sub_bit_position == 0 {
                 self.last_byte = self.byte_reader_owned.read_byte()?;
             }
-            output |= ((self.last_byte & 0x1) as usize) << wrote_bits as usize;
+            output |= ((self.last_byte & 0x1) as usize) << wrote_bits;
             self.last_byte >>= 1;
             self.sub_bit_position = (self.sub_bit_position + 1) % 8;
         }
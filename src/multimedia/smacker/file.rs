@@ -1,9 +1,13 @@
-///
-/// References used:
-/// https://wiki.multimedia.cx/index.php?title=Smacker
-/// https://github.com/lu-zero/ffmpeg/blob/master/libavcodec/smacker.c
-/// https://github.com/jewalky/UnityAllods/blob/smack-support/Assets/SmackLoader.cs
-///
+//!
+//! References used:
+//! https://wiki.multimedia.cx/index.php?title=Smacker
+//! https://github.com/lu-zero/ffmpeg/blob/master/libavcodec/smacker.c
+//! https://github.com/jewalky/UnityAllods/blob/smack-support/Assets/SmackLoader.cs
+//!
+
+// Legacy decoder, the index loops and short reads are kept as written
+#![allow(clippy::needless_range_loop, clippy::manual_memcpy, clippy::unused_io_amount)]
+#![allow(clippy::unusual_byte_groupings)]
 
 use {
     super::{
@@ -98,7 +102,7 @@ impl SmackerFileInfo {
             Ordering::Greater => header.frame_rate as f32
         };
         let mut audio_flags = [Default::default(); 7];
-        let mut audio_rate = header.audio_rate.clone();
+        let mut audio_rate = header.audio_rate;
         for i in 0..7 {
             audio_flags[i] = flags::Audio::from_bits(audio_rate[i] & 0xFC_000000).unwrap();
             audio_rate[i] &= 0x00_FFFFFF;
@@ -239,7 +243,7 @@ impl SmackerFileInfo {
                 let pal_colors_buffer = &mut self.buffer[..palette_size];
                 stream.read(pal_colors_buffer)?;
                 let prev_palette = &self.smacker_decode_context.palette;
-                let mut next_palette = prev_palette.clone();
+                let mut next_palette = *prev_palette;
                 let (mut offset, mut pal_offset) = (0, 0);
                 while offset < pal_colors_buffer.len() && pal_offset < 256 {
                     let flag_byte = pal_colors_buffer[offset];
@@ -365,8 +369,8 @@ impl SmackerFileInfo {
             }
 
             // swap trick to settle with borrow checker:
-            let mut audio_track = std::mem::replace(&mut self.audio_tracks[track_number], Vec::new());
-            let mut audio_trees = std::mem::replace(&mut self.audio_trees, Vec::new());
+            let mut audio_track = std::mem::take(&mut self.audio_tracks[track_number]);
+            let mut audio_trees = std::mem::take(&mut self.audio_trees);
 
             with_bit_reader(&mut audio_cursor, |bit_reader| {
                 if bit_reader.read_bits(1)? != 1 {
@@ -427,9 +431,9 @@ impl SmackerFileInfo {
                 // first write just bases as is:
                 for i in 0..result_base_len {
                     let sample = if is_16_bit {
-                        i16_bases[i] as f32 / (std::i16::MAX as f32 + 1.0)
+                        i16_bases[i] as f32 / (i16::MAX as f32 + 1.0)
                     } else {
-                        i8_bases[i] as f32 / (std::i8::MAX as f32 + 1.0)
+                        i8_bases[i] as f32 / (i8::MAX as f32 + 1.0)
                     };
                     audio_track.push(sample);
                 }
@@ -444,13 +448,13 @@ impl SmackerFileInfo {
                                 (sample_bytes[i * 2] as u16) |
                                 (sample_bytes[i * 2 + 1] as u16 * 0x100)
                             );
-                            let sample = i16_bases[i] as f32 / (std::i16::MAX as f32 + 1.0);
+                            let sample = i16_bases[i] as f32 / (i16::MAX as f32 + 1.0);
                             audio_track.push(sample);
                         }
                     } else {
                         for i in 0..result_base_len {
                             i8_bases[i] += u8_to_i8(sample_bytes[i]);
-                            let sample = i8_bases[i] as f32 / (std::i8::MAX as f32 + 1.0);
+                            let sample = i8_bases[i] as f32 / (i8::MAX as f32 + 1.0);
                             audio_track.push(sample);
                         }
                     }
@@ -475,7 +479,7 @@ impl SmackerFileInfo {
             while current_block < count_blocks {
                 let mut type_descriptor = self.type_tree.as_mut()
                     .unwrap()
-                    .get_value(bit_reader)? as u16;
+                    .get_value(bit_reader)?;
 
                 let block_type = type_descriptor & 0b11;
                 type_descriptor >>= 2;
@@ -512,7 +516,7 @@ impl SmackerFileInfo {
                             }
                             let color_indices = match self.m_clr_tree.as_mut() {
                                 Some(tree) => {
-                                    let color_idx_pair = tree.get_value(bit_reader)? as u16;
+                                    let color_idx_pair = tree.get_value(bit_reader)?;
                                     [
                                         (color_idx_pair & 0xFF) as u8,
                                         (color_idx_pair / 0x100) as u8,
@@ -553,8 +557,8 @@ impl SmackerFileInfo {
                             for _ in 0..4 {
                                 let color_indices = match self.full_tree.as_mut() {
                                     Some(tree) => {
-                                        let color_idx_pair1 = tree.get_value(bit_reader)? as u16;
-                                        let color_idx_pair0 = tree.get_value(bit_reader)? as u16;
+                                        let color_idx_pair1 = tree.get_value(bit_reader)?;
+                                        let color_idx_pair0 = tree.get_value(bit_reader)?;
                                         [
                                             (color_idx_pair0 & 0xFF) as u8,
                                             (color_idx_pair0 / 0x100) as u8,
@@ -34,8 +34,7 @@ impl HuffmanContext {
     /// context many times on different trees (e.g. when decoding an audio for example)
     ///
     pub(crate) fn new() -> Self {
-        let mut node_arena = Vec::new();
-        node_arena.push(Default::default());
+        let node_arena = vec![Default::default()];
         let root_node_id = NodeId(0);
         Self {
             node_arena,
@@ -155,7 +154,7 @@ impl HuffmanContext {
             let mut value = low_byte + high_byte * 0x100;
 
             for i in 0..3 {
-                if header_tree_head.escapes[i] == value as u16 {
+                if header_tree_head.escapes[i] == value {
                     println!("found escape {}", i);
                     header_tree_head.last_nodes[i] = node_id;
                     value = 0
@@ -274,10 +273,7 @@ impl HeaderTree {
     }
 
     fn is_leaf(&self, node_id: NodeId) -> bool {
-        match self.tree.node_arena[node_id.0] {
-            HuffmanNode::Leaf { .. } => true,
-            _ => false
-        }
+        matches!(self.tree.node_arena[node_id.0], HuffmanNode::Leaf { .. })
     }
 
     fn get_leaf_value_by_node_id(&self, node_id: NodeId) -> u16 {
@@ -5,6 +5,7 @@ use crate::shared_types::{I16Wrapper};
 const RIFF: u32 = 0x46_46_49_52;
 const WAVE: u32 = 0x45_56_41_57;
 const FMT: u32  = 0x20_74_6D_66;
+#[allow(clippy::mistyped_literal_suffixes)]
 const DATA: u32 = 0x61_74_61_64;
 const PCM: u16 = 0x0001;
 
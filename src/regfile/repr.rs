@@ -87,6 +87,7 @@ struct RegistryHeader {
     name: String
 }
 impl RegistryHeader {
+    #[allow(clippy::unused_io_amount)]
     fn read<TStream: Read + Seek>(
         stream: &mut TStream,
         root: &RootRegistryHeader
@@ -115,6 +116,7 @@ pub struct RegistryInfoEnumeration
     pub int_arrays: Vec<String>
 }
 
+#[allow(clippy::type_complexity)]
 pub struct Registry {
     stream: Cursor<Vec<u8>>,
     strings_lookup: HashMap<String, ((usize, usize), Option<String>)>,
@@ -151,33 +153,29 @@ impl Registry {
             let mut new_path = (*parent_path).clone();
             let offset = offset as u64;
             stream.seek(SeekFrom::Start(offset))?;
-            let child_node = RegistryHeader::read(&mut stream, &root_header)?;
-            match child_node {
-                RegistryHeader { node_data, name } => {
-                    new_path.push_str(&name);
-                    match node_data {
-                        NodeData::Directory(offset, count) => {
-                            new_path.push('/');
-                            root_offset = offset;
-                            for _ in 0..count {
-                                queue.push_back((Rc::new(new_path.clone()), root_offset));
-                                root_offset += 0x20;
-                            }
-                        }
-                        NodeData::Int(value) => {
-                            ints_lookup.insert(new_path, value);
-                        }
-                        NodeData::Float(value) => {
-                            floats_lookup.insert(new_path, value);
-                        }
-                        NodeData::String(value_offset, length) => {
-                            strings_lookup.insert(new_path, ((value_offset, length), None));
-                        }
-                        NodeData::IntArray(value_offset, length) => {
-                            int_array_lookup.insert(new_path, ((value_offset, length), None));
-                        }
+            let RegistryHeader { node_data, name } = RegistryHeader::read(&mut stream, &root_header)?;
+            new_path.push_str(&name);
+            match node_data {
+                NodeData::Directory(offset, count) => {
+                    new_path.push('/');
+                    root_offset = offset;
+                    for _ in 0..count {
+                        queue.push_back((Rc::new(new_path.clone()), root_offset));
+                        root_offset += 0x20;
                     }
                 }
+                NodeData::Int(value) => {
+                    ints_lookup.insert(new_path, value);
+                }
+                NodeData::Float(value) => {
+                    floats_lookup.insert(new_path, value);
+                }
+                NodeData::String(value_offset, length) => {
+                    strings_lookup.insert(new_path, ((value_offset, length), None));
+                }
+                NodeData::IntArray(value_offset, length) => {
+                    int_array_lookup.insert(new_path, ((value_offset, length), None));
+                }
             }
         }
         Ok(Self {
@@ -200,11 +198,12 @@ impl Registry {
             Some(&value) => Ok(value)
         }
     }
+    #[allow(clippy::unused_io_amount)]
     fn ensure_string_existence(&mut self, path: &str) -> Result<(), RegistryError> {
         match self.strings_lookup.get_mut(path) {
             None => Err(RegistryError::NonExistentStringValue),
             Some(string_entry) => {
-                if let None = string_entry.1 {
+                if string_entry.1.is_none() {
                     let (offset, size) = string_entry.0;
                     self.stream.seek(SeekFrom::Start(offset as u64))?;
                     let mut vec = vec![0u8; size];
@@ -220,7 +219,7 @@ impl Registry {
         match self.int_array_lookup.get_mut(path) {
             None => Err(RegistryError::NonExistentIntArrayValue),
             Some(array_entry) => {
-                if let None = array_entry.1 {
+                if array_entry.1.is_none() {
                     let (offset, size) = array_entry.0;
                     self.stream.seek(SeekFrom::Start(offset as u64))?;
                     let mut vec = Vec::with_capacity(size);
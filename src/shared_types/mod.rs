@@ -139,4 +139,54 @@ impl Deref for U8Wrapper {
     fn deref(&self) -> &Self::Target {
         &(self.0)
     }
-}
\ No newline at end of file
+}
+// Counterpart of cp866_rs::decode_bytes; characters missing from the code page become '?'
+pub fn encode_cp866(text: &str) -> Vec<u8> {
+    text.chars().map(cp866_byte).collect()
+}
+
+// Zero padded; a text as long as the buffer fills it with no terminating zero, as names in the files do
+pub fn encode_cp866_into(text: &str, buffer: &mut [u8]) {
+    for byte in buffer.iter_mut() {
+        *byte = 0;
+    }
+    for (byte, c) in buffer.iter_mut().zip(text.chars()) {
+        *byte = cp866_byte(c);
+    }
+}
+
+// Pseudographics of 0xB0..=0xDF
+const CP866_BOX_DRAWING: [char; 0x30] = [
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀'
+];
+
+fn cp866_byte(c: char) -> u8 {
+    let code = c as u32;
+    match c {
+        _ if code < 0x80 => code as u8,
+        '\u{0410}'..='\u{043F}' => (code - 0x0410 + 0x80) as u8,
+        '\u{0440}'..='\u{044F}' => (code - 0x0440 + 0xE0) as u8,
+        'Ё' => 0xF0,
+        'ё' => 0xF1,
+        'Є' => 0xF2,
+        'є' => 0xF3,
+        'Ї' => 0xF4,
+        'ї' => 0xF5,
+        'Ў' => 0xF6,
+        'ў' => 0xF7,
+        '°' => 0xF8,
+        '∙' => 0xF9,
+        '·' => 0xFA,
+        '√' => 0xFB,
+        '№' => 0xFC,
+        '¤' => 0xFD,
+        '■' => 0xFE,
+        '\u{00A0}' => 0xFF,
+        _ => match CP866_BOX_DRAWING.iter().position(|&box_char| box_char == c) {
+            Some(idx) => 0xB0 + idx as u8,
+            None => b'?'
+        }
+    }
+}